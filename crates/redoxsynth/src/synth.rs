@@ -10,6 +10,7 @@ mod reverb;
 mod tuning;
 mod write;
 
+pub use self::chorus::ChorusMode;
 pub use self::tuning::TuningIter;
 pub use self::write::IsSamples;
 
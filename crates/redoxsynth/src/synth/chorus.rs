@@ -1,6 +1,6 @@
 use crate::{engine, Synth};
 
-type ChorusMode = engine::chorus::ChorusMode;
+pub type ChorusMode = engine::chorus::ChorusMode;
 
 /**
 Chorus parameters
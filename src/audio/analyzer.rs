@@ -0,0 +1,216 @@
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use crossbeam::queue::ArrayQueue;
+use rustfft::{Fft, FftPlanner, num_complex::Complex};
+
+use super::source::{CHANNELS, Source};
+
+/// frames the audio thread can get ahead of the engine before some are lost
+const RING_LENGTH: usize = 16384;
+const FFT_LENGTH: usize = 4096;
+const SPECTRUM_BANDS: usize = 64;
+const LOWEST_FREQUENCY: f64 = 20.0;
+const REPORT_INTERVAL: Duration = Duration::from_millis(33);
+/// meters bottom out here
+pub const SILENCE_DB: f32 = -96.0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MeterReport {
+    /// per channel, in dB
+    pub peak: [f32; 2],
+    /// per channel, in dB
+    pub rms: [f32; 2],
+    /// whether a channel reached full scale since the last report, at the
+    /// output or going into the master effects
+    pub clipped: [bool; 2],
+    /// in dB, logarithmically spaced bands from 20 Hz up to Nyquist
+    pub spectrum: Vec<f32>,
+}
+
+/// Copies the output of a source into a lock-free ring for an `Analyzer`.
+pub struct AnalyzerSource {
+    source: Box<dyn Source>,
+    frames: Arc<ArrayQueue<[f32; 2]>>,
+}
+
+/// Watches a source for samples at full scale, set from the audio thread.
+/// It goes before the master rack, whose limiter would otherwise keep the
+/// output from ever reaching full scale and hide the clip.
+pub struct ClipTap {
+    source: Box<dyn Source>,
+    clipped: Arc<[AtomicBool; 2]>,
+}
+
+impl ClipTap {
+    pub fn new(source: Box<dyn Source>) -> (ClipTap, Arc<[AtomicBool; 2]>) {
+        let clipped = Arc::new([AtomicBool::new(false), AtomicBool::new(false)]);
+        (ClipTap { source, clipped: clipped.clone() }, clipped)
+    }
+}
+
+/// Reads what an `AnalyzerSource` played, off the audio thread, and
+/// periodically reports levels and a spectrum.
+pub struct Analyzer {
+    frames: Arc<ArrayQueue<[f32; 2]>>,
+    sample_rate: f64,
+    peak: [f32; 2],
+    sum_squares: [f64; 2],
+    count: usize,
+    clipped: [bool; 2],
+    /// set by a `ClipTap` before the master effects
+    clipped_before: Arc<[AtomicBool; 2]>,
+    /// the most recent mono samples, oldest at `position`
+    history: Vec<f32>,
+    position: usize,
+    window: Vec<f32>,
+    fft: Arc<dyn Fft<f32>>,
+    spectrum: Vec<Complex<f32>>,
+    scratch: Vec<Complex<f32>>,
+    last_report: Instant,
+}
+
+impl Analyzer {
+    pub fn new(source: Box<dyn Source>, clipped_before: Arc<[AtomicBool; 2]>, sample_rate: f64) -> (AnalyzerSource, Analyzer) {
+        let frames = Arc::new(ArrayQueue::new(RING_LENGTH));
+        let fft = FftPlanner::new().plan_fft_forward(FFT_LENGTH);
+
+        // hann
+        let window = (0..FFT_LENGTH)
+            .map(|i| {
+                let phase = i as f32 / FFT_LENGTH as f32 * std::f32::consts::TAU;
+                0.5 - 0.5 * phase.cos()
+            })
+            .collect();
+
+        let analyzer = Analyzer {
+            frames: frames.clone(),
+            sample_rate,
+            peak: [0.0; 2],
+            sum_squares: [0.0; 2],
+            count: 0,
+            clipped: [false; 2],
+            clipped_before,
+            history: vec![0.0; FFT_LENGTH],
+            position: 0,
+            window,
+            spectrum: vec![Complex::default(); FFT_LENGTH],
+            scratch: vec![Complex::default(); fft.get_inplace_scratch_len()],
+            fft,
+            last_report: Instant::now(),
+        };
+
+        (AnalyzerSource { source, frames }, analyzer)
+    }
+
+    /// Takes in everything played since the last call.
+    pub fn process(&mut self) {
+        while let Some(frame) = self.frames.pop() {
+            for (channel, &sample) in frame.iter().enumerate() {
+                self.peak[channel] = self.peak[channel].max(sample.abs());
+                self.sum_squares[channel] += (sample * sample) as f64;
+                self.clipped[channel] |= sample.abs() >= 1.0;
+            }
+            self.count += 1;
+
+            self.history[self.position] = (frame[0] + frame[1]) * 0.5;
+            self.position = (self.position + 1) % FFT_LENGTH;
+        }
+    }
+
+    /// Returns a report once per interval, resetting the levels.
+    pub fn report(&mut self) -> Option<MeterReport> {
+        if self.count == 0 || self.last_report.elapsed() < REPORT_INTERVAL {
+            return None;
+        }
+
+        let count = self.count as f64;
+        for (clipped, clipped_before) in self.clipped.iter_mut().zip(self.clipped_before.iter()) {
+            *clipped |= clipped_before.swap(false, Ordering::Relaxed);
+        }
+        let report = MeterReport {
+            peak: [to_db(self.peak[0]), to_db(self.peak[1])],
+            rms: [
+                to_db((self.sum_squares[0] / count).sqrt() as f32),
+                to_db((self.sum_squares[1] / count).sqrt() as f32),
+            ],
+            clipped: self.clipped,
+            spectrum: self.spectrum(),
+        };
+
+        self.peak = [0.0; 2];
+        self.sum_squares = [0.0; 2];
+        self.count = 0;
+        self.clipped = [false; 2];
+        self.last_report = Instant::now();
+
+        Some(report)
+    }
+
+    fn spectrum(&mut self) -> Vec<f32> {
+        let (older, newer) = self.history.split_at(self.position);
+        for ((bin, &sample), &window) in self.spectrum.iter_mut()
+            .zip(newer.iter().chain(older))
+            .zip(&self.window)
+        {
+            *bin = Complex::new(sample * window, 0.0);
+        }
+        self.fft.process_with_scratch(&mut self.spectrum, &mut self.scratch);
+
+        // a full scale sine reads 0 dB
+        let scale = 4.0 / FFT_LENGTH as f32;
+        let bin_width = self.sample_rate / FFT_LENGTH as f64;
+        let nyquist = self.sample_rate / 2.0;
+        let bins = FFT_LENGTH / 2;
+
+        (0..SPECTRUM_BANDS)
+            .map(|band| {
+                let frequency = |band: usize| {
+                    LOWEST_FREQUENCY * (nyquist / LOWEST_FREQUENCY).powf(band as f64 / SPECTRUM_BANDS as f64)
+                };
+                let low = ((frequency(band) / bin_width) as usize).min(bins - 1);
+                // low bands are narrower than a bin, so always take at least one
+                let high = ((frequency(band + 1) / bin_width) as usize).max(low + 1).min(bins);
+
+                let magnitude = self.spectrum[low..high].iter()
+                    .map(|bin| bin.norm())
+                    .fold(0.0, f32::max);
+                to_db(magnitude * scale)
+            })
+            .collect()
+    }
+}
+
+impl Source for AnalyzerSource {
+    fn output_audio(&mut self, sample: usize, data: &mut [f64]) {
+        self.source.output_audio(sample, data);
+
+        for frame in data.chunks(CHANNELS) {
+            // when the engine falls behind, the newest frames are dropped
+            let _ = self.frames.push([frame[0] as f32, frame[1] as f32]);
+        }
+    }
+}
+
+impl Source for ClipTap {
+    fn output_audio(&mut self, sample: usize, data: &mut [f64]) {
+        self.source.output_audio(sample, data);
+
+        for frame in data.chunks(CHANNELS) {
+            for (clipped, sample) in self.clipped.iter().zip(frame) {
+                if sample.abs() >= 1.0 {
+                    clipped.store(true, Ordering::Relaxed);
+                }
+            }
+        }
+    }
+}
+
+fn to_db(amplitude: f32) -> f32 {
+    (20.0 * amplitude.log10()).max(SILENCE_DB)
+}
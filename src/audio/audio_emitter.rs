@@ -1,10 +1,20 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
+
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
-use iced::futures::channel::mpsc::{Receiver, channel};
+use crossbeam::channel::{Receiver, bounded};
 use super::source::Source;
 
 pub struct AudioEmitter {
     stream: Option<cpal::Stream>,
     config: cpal::StreamConfig,
+    /// in nanoseconds, from a callback running to its buffer being played
+    output_latency: Arc<AtomicU64>,
 }
 
 impl AudioEmitter {
@@ -16,6 +26,7 @@ impl AudioEmitter {
                 sample_rate: cpal::SampleRate(48000),
                 buffer_size: cpal::BufferSize::Default,
             },
+            output_latency: Arc::new(AtomicU64::new(0)),
         }
     }
 
@@ -23,7 +34,14 @@ impl AudioEmitter {
         return self.config.clone();
     }
 
-    pub fn start(&mut self, source: Box<dyn Source>) -> Receiver<usize> {
+    /// Kept up to date by every callback once the stream starts.
+    pub fn output_latency(&self) -> Arc<AtomicU64> {
+        self.output_latency.clone()
+    }
+
+    /// Starts the output stream. Every callback reports the number of frames
+    /// it rendered along with the time it ran.
+    pub fn start(&mut self, source: Box<dyn Source>) -> Receiver<(usize, Instant)> {
         let host = cpal::default_host();
         let device = host.default_output_device().unwrap();
 
@@ -31,6 +49,7 @@ impl AudioEmitter {
             device,
             &self.config,
             source,
+            self.output_latency.clone(),
         );
 
         self.stream = Some(stream);
@@ -41,7 +60,8 @@ impl AudioEmitter {
         device: cpal::Device,
         config: &cpal::StreamConfig,
         mut source: Box<dyn Source>,
-    ) -> (cpal::Stream, Receiver<usize>) {
+        output_latency: Arc<AtomicU64>,
+    ) -> (cpal::Stream, Receiver<(usize, Instant)>) {
         let err_fn = |err| eprintln!("an error occurred on stream: {}", err);
 
         let mut counter = 0usize;
@@ -49,16 +69,21 @@ impl AudioEmitter {
 
         let mut buffer = Vec::new();
 
-        let (mut tx, rx) = channel(64);
+        let (tx, rx) = bounded(64);
 
         let stream = device
             .build_output_stream(
                 config,
-                move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
+                move |data: &mut [f32], info: &cpal::OutputCallbackInfo| {
+                    let timestamp = info.timestamp();
+                    if let Some(latency) = timestamp.playback.duration_since(&timestamp.callback) {
+                        output_latency.store(latency.as_nanos() as u64, Ordering::Relaxed);
+                    }
+
                     buffer.resize(data.len(), Default::default());
                     source.output_audio(counter, buffer.as_mut_slice());
                     data.iter_mut().zip(buffer.iter()).for_each(|(dst, src)| *dst = *src as f32);
-                    tx.try_send(data.len() / ch);
+                    let _ = tx.try_send((data.len() / ch, Instant::now()));
                     counter += data.len() / ch;
                 },
                 err_fn,
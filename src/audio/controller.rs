@@ -1,5 +1,15 @@
 use crate::sequence::Pitch;
 
+/// Channels the player gives notes with a pitch envelope or expression, one
+/// note each so their bends and timbre don't move other notes, as in MPE. 9
+/// is left out, being drums in GM.
+pub const GLIDE_CHANNELS: [u32; 12] = [3, 4, 5, 6, 7, 8, 10, 11, 12, 13, 14, 15];
+/// in semitones, the bend range outputs should use on `GLIDE_CHANNELS`
+pub const GLIDE_BEND_RANGE: f32 = 48.0;
+/// the velocity of previews, and of notes without a pressure to take theirs
+/// from
+pub const DEFAULT_VELOCITY: u8 = 127;
+
 pub struct Event {
     pub sample: usize,
     pub sequence: usize,
@@ -7,11 +17,65 @@ pub struct Event {
 }
 
 pub enum EventData {
-    NoteOn(u32, Pitch),
+    /// channel, pitch and velocity
+    NoteOn(u32, Pitch, u8),
     NoteOff(u32, Pitch),
+    /// raw 14 bit bend value, centred on 8192
+    PitchBend(u32, u32),
+    /// bends the note started at the pitch on the channel by an offset in
+    /// octaves
+    Glide(u32, Pitch, f32),
+    /// channel, controller number, value
+    ControlChange(u32, u8, u8),
+    ChannelPressure(u32, u8),
+    /// pressure on the note started at the pitch on the channel
+    NotePressure(u32, Pitch, u8),
     ClearEvents,
 }
 
+/// Each channel's bend as its last note's detune plus the bend sent on its
+/// own, as by a pitch bend lane, so neither undoes the other.
+#[derive(Debug, Clone, Default)]
+pub struct ChannelBends {
+    /// detune and bend, as offsets from the centre
+    bends: [(i32, i32); 16],
+}
+
+impl ChannelBends {
+    /// the bend to send with a note on detuned by `detune`
+    pub fn note_on(&mut self, channel: u32, detune: u32) -> u32 {
+        let bend = &mut self.bends[channel as usize & 0x0f];
+        bend.0 = detune as i32 - 8192;
+        Self::value(*bend)
+    }
+
+    /// the bend to send for a raw `bend`
+    pub fn bend(&mut self, channel: u32, bend: u32) -> u32 {
+        let channel_bend = &mut self.bends[channel as usize & 0x0f];
+        channel_bend.1 = bend as i32 - 8192;
+        Self::value(*channel_bend)
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    fn value((detune, bend): (i32, i32)) -> u32 {
+        (8192 + detune + bend).max(0).min(0x3fff) as u32
+    }
+}
+
 pub trait Controller {
     fn send_event(&self, event: Event);
 }
+
+/// Inserts an event into a buffer kept sorted by sample, then sequence.
+pub fn insert_event(events: &mut Vec<Event>, event: Event) {
+    let pos = events
+        .binary_search_by_key(&(event.sample, event.sequence), |e: &Event| {
+            (e.sample, e.sequence)
+        })
+        .unwrap_or_else(|e| e);
+
+    events.insert(pos, event);
+}
@@ -0,0 +1,85 @@
+use std::f64::consts::PI;
+
+/// Normalised coefficients from the RBJ audio EQ cookbook.
+#[derive(Debug, Clone, Copy)]
+pub struct BiquadCoefficients {
+    b0: f64,
+    b1: f64,
+    b2: f64,
+    a1: f64,
+    a2: f64,
+}
+
+impl BiquadCoefficients {
+    pub fn peaking(sample_rate: f64, frequency: f64, q: f64, gain: f64) -> Self {
+        let a = 10f64.powf(gain / 40.0);
+        let (cos, sin) = Self::angle(sample_rate, frequency);
+        let alpha = sin / (2.0 * q);
+
+        Self::normalise(
+            1.0 + alpha * a, -2.0 * cos, 1.0 - alpha * a,
+            1.0 + alpha / a, -2.0 * cos, 1.0 - alpha / a,
+        )
+    }
+
+    pub fn low_shelf(sample_rate: f64, frequency: f64, gain: f64) -> Self {
+        let a = 10f64.powf(gain / 40.0);
+        let (cos, sin) = Self::angle(sample_rate, frequency);
+        // shelf slope of 1
+        let beta = sin * a.sqrt() * 2f64.sqrt();
+
+        Self::normalise(
+            a * ((a + 1.0) - (a - 1.0) * cos + beta),
+            2.0 * a * ((a - 1.0) - (a + 1.0) * cos),
+            a * ((a + 1.0) - (a - 1.0) * cos - beta),
+            (a + 1.0) + (a - 1.0) * cos + beta,
+            -2.0 * ((a - 1.0) + (a + 1.0) * cos),
+            (a + 1.0) + (a - 1.0) * cos - beta,
+        )
+    }
+
+    pub fn high_shelf(sample_rate: f64, frequency: f64, gain: f64) -> Self {
+        let a = 10f64.powf(gain / 40.0);
+        let (cos, sin) = Self::angle(sample_rate, frequency);
+        let beta = sin * a.sqrt() * 2f64.sqrt();
+
+        Self::normalise(
+            a * ((a + 1.0) + (a - 1.0) * cos + beta),
+            -2.0 * a * ((a - 1.0) + (a + 1.0) * cos),
+            a * ((a + 1.0) + (a - 1.0) * cos - beta),
+            (a + 1.0) - (a - 1.0) * cos + beta,
+            2.0 * ((a - 1.0) - (a + 1.0) * cos),
+            (a + 1.0) - (a - 1.0) * cos - beta,
+        )
+    }
+
+    fn angle(sample_rate: f64, frequency: f64) -> (f64, f64) {
+        let frequency = frequency.min(sample_rate * 0.49);
+        let w0 = 2.0 * PI * frequency / sample_rate;
+        (w0.cos(), w0.sin())
+    }
+
+    fn normalise(b0: f64, b1: f64, b2: f64, a0: f64, a1: f64, a2: f64) -> Self {
+        Self { b0: b0 / a0, b1: b1 / a0, b2: b2 / a0, a1: a1 / a0, a2: a2 / a0 }
+    }
+}
+
+/// The state of one channel through a biquad, in direct form I.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Biquad {
+    x1: f64,
+    x2: f64,
+    y1: f64,
+    y2: f64,
+}
+
+impl Biquad {
+    pub fn process(&mut self, c: &BiquadCoefficients, x: f64) -> f64 {
+        let y = c.b0 * x + c.b1 * self.x1 + c.b2 * self.x2 - c.a1 * self.y1 - c.a2 * self.y2;
+        self.x2 = self.x1;
+        self.x1 = x;
+        self.y2 = self.y1;
+        self.y1 = y;
+        y
+    }
+}
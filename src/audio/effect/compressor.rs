@@ -0,0 +1,118 @@
+use super::{Effect, ParameterDescriptor, SWITCH, amplitude_to_db, db_to_amplitude, peak, time_coefficient};
+use crate::audio::source::CHANNELS;
+
+const THRESHOLD: usize = 0;
+const RATIO: usize = 1;
+const ATTACK: usize = 2;
+const RELEASE: usize = 3;
+const KNEE: usize = 4;
+const MAKEUP: usize = 5;
+const SIDECHAIN: usize = 6;
+
+static COMPRESSOR_PARAMETERS: [ParameterDescriptor; 7] = [
+    ParameterDescriptor { name: "Threshold", min: -60.0, max: 0.0, default: -18.0, unit: "dB", labels: &[] },
+    ParameterDescriptor { name: "Ratio", min: 1.0, max: 20.0, default: 4.0, unit: "", labels: &[] },
+    ParameterDescriptor { name: "Attack", min: 0.1, max: 100.0, default: 10.0, unit: "ms", labels: &[] },
+    ParameterDescriptor { name: "Release", min: 10.0, max: 1000.0, default: 100.0, unit: "ms", labels: &[] },
+    ParameterDescriptor { name: "Knee", min: 0.0, max: 24.0, default: 6.0, unit: "dB", labels: &[] },
+    ParameterDescriptor { name: "Makeup", min: 0.0, max: 24.0, default: 0.0, unit: "dB", labels: &[] },
+    ParameterDescriptor { name: "Sidechain", min: 0.0, max: 1.0, default: 0.0, unit: "", labels: SWITCH },
+];
+
+/// A feed-forward compressor. The level is detected on the input, or on
+/// the sidechain when it is switched on and the source provides one.
+pub struct Compressor {
+    sample_rate: f64,
+    values: [f64; 7],
+    attack: f64,
+    release: f64,
+    /// smoothed gain reduction in dB, zero or below
+    reduction: f64,
+}
+
+impl Compressor {
+    pub fn new(sample_rate: f64) -> Self {
+        let mut compressor = Self {
+            sample_rate,
+            values: [0.0; 7],
+            attack: 0.0,
+            release: 0.0,
+            reduction: 0.0,
+        };
+
+        for (i, parameter) in COMPRESSOR_PARAMETERS.iter().enumerate() {
+            compressor.set_parameter(i, parameter.default);
+        }
+        compressor
+    }
+
+    /// the static curve, in dB of reduction for a level in dB
+    fn gain_reduction(&self, level: f64) -> f64 {
+        let threshold = self.values[THRESHOLD];
+        let ratio = self.values[RATIO];
+        let knee = self.values[KNEE];
+        let over = level - threshold;
+
+        let output = if 2.0 * over < -knee {
+            level
+        } else if 2.0 * over.abs() <= knee {
+            level + (1.0 / ratio - 1.0) * (over + knee / 2.0).powi(2) / (2.0 * knee)
+        } else {
+            threshold + over / ratio
+        };
+
+        output - level
+    }
+
+    fn compress(&mut self, data: &mut [f64], key: Option<&[f64]>) {
+        let makeup = self.values[MAKEUP];
+
+        for (i, frame) in data.chunks_mut(CHANNELS).enumerate() {
+            let peak = match key {
+                Some(key) => key.get(i * CHANNELS..(i + 1) * CHANNELS).map_or(0.0, peak),
+                None => peak(frame),
+            };
+
+            let target = self.gain_reduction(amplitude_to_db(peak));
+            let coefficient = if target < self.reduction { self.attack } else { self.release };
+            self.reduction += (target - self.reduction) * coefficient;
+
+            let gain = db_to_amplitude(self.reduction + makeup);
+            for sample in frame.iter_mut() {
+                *sample *= gain;
+            }
+        }
+    }
+}
+
+impl Effect for Compressor {
+    fn parameters(&self) -> &'static [ParameterDescriptor] {
+        &COMPRESSOR_PARAMETERS
+    }
+
+    fn get_parameter(&self, index: usize) -> f64 {
+        self.values.get(index).copied().unwrap_or_default()
+    }
+
+    fn set_parameter(&mut self, index: usize, value: f64) {
+        if index >= self.values.len() {
+            return;
+        }
+        self.values[index] = value;
+
+        match index {
+            ATTACK => self.attack = time_coefficient(value / 1000.0, self.sample_rate),
+            RELEASE => self.release = time_coefficient(value / 1000.0, self.sample_rate),
+            _ => {}
+        }
+    }
+
+    fn process_audio(&mut self, _sample_pos: usize, data: &mut [f64]) {
+        self.compress(data, None);
+    }
+
+    fn process_sidechain(&mut self, _sample_pos: usize, data: &mut [f64], sidechain: &[f64]) {
+        let key = if self.values[SIDECHAIN] > 0.5 { Some(sidechain) } else { None };
+        self.compress(data, key);
+    }
+}
@@ -0,0 +1,296 @@
+use std::{path::Path, sync::Arc};
+
+use rustfft::{Fft, FftPlanner, num_complex::Complex};
+
+use super::{Effect, ParameterDescriptor};
+use crate::audio::source::CHANNELS;
+
+/// frames per partition, which is also the latency of the reverb
+const BLOCK_LENGTH: usize = 512;
+const FFT_LENGTH: usize = BLOCK_LENGTH * 2;
+/// the bins up to and including Nyquist, all a real signal's spectrum needs
+const HALF_LENGTH: usize = FFT_LENGTH / 2 + 1;
+/// Longer impulse responses are cut off. At 48 kHz that's 375 partitions,
+/// so every 512 frames the audio thread does one 1024 point FFT each way
+/// and 375 × 2 × 513 complex multiply-adds, about 36 million a second.
+const MAX_IMPULSE_TIME: f64 = 4.0;
+/// the decay time of the impulse response used until one is loaded
+const DEFAULT_DECAY: f64 = 1.5;
+
+const WET: usize = 0;
+const DRY: usize = 1;
+
+static REVERB_PARAMETERS: [ParameterDescriptor; 2] = [
+    ParameterDescriptor { name: "Wet", min: 0.0, max: 1.0, default: 0.3, unit: "", labels: &[] },
+    ParameterDescriptor { name: "Dry", min: 0.0, max: 1.0, default: 1.0, unit: "", labels: &[] },
+];
+
+/// An impulse response cut into partitions of `BLOCK_LENGTH` frames, each
+/// already transformed and kept up to Nyquist, with a set per channel.
+pub struct ImpulseResponse {
+    partitions: [Vec<Vec<Complex<f64>>>; 2],
+}
+
+impl ImpulseResponse {
+    /// Reads a WAV file. Mono files are used for both channels, and files at
+    /// a different sample rate are resampled.
+    pub fn load(path: &Path, sample_rate: f64) -> Result<Self, String> {
+        let mut reader = hound::WavReader::open(path)
+            .map_err(|err| format!("couldn't open {}: {}", path.display(), err))?;
+        let spec = reader.spec();
+        let channels = spec.channels.max(1) as usize;
+
+        let samples: Vec<f64> = match spec.sample_format {
+            hound::SampleFormat::Float => reader.samples::<f32>()
+                .map(|sample| sample.map(|sample| sample as f64))
+                .collect::<Result<_, _>>(),
+            hound::SampleFormat::Int => {
+                let scale = (1i64 << (spec.bits_per_sample - 1)) as f64;
+                reader.samples::<i32>()
+                    .map(|sample| sample.map(|sample| sample as f64 / scale))
+                    .collect::<Result<_, _>>()
+            }
+        }.map_err(|err| format!("couldn't read {}: {}", path.display(), err))?;
+
+        let channel = |channel: usize| -> Vec<f64> {
+            let channel = channel.min(channels - 1);
+            let samples: Vec<f64> = samples.iter().skip(channel).step_by(channels).copied().collect();
+            resample(&samples, spec.sample_rate as f64, sample_rate)
+        };
+
+        Ok(Self::from_channels(&channel(0), &channel(1), sample_rate))
+    }
+
+    /// Decaying noise, different on each side, standing in for a room.
+    pub fn synthetic(sample_rate: f64, decay: f64) -> Self {
+        let length = (decay * sample_rate) as usize;
+        let mut seed = 0x2545f491u32;
+        let mut noise = || {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            seed as f64 / u32::MAX as f64 * 2.0 - 1.0
+        };
+
+        let mut left = Vec::with_capacity(length);
+        let mut right = Vec::with_capacity(length);
+        for i in 0..length {
+            // 60 dB down at the end
+            let envelope = (-6.9 * i as f64 / length as f64).exp();
+            left.push(noise() * envelope);
+            right.push(noise() * envelope);
+        }
+
+        Self::from_channels(&left, &right, sample_rate)
+    }
+
+    fn from_channels(left: &[f64], right: &[f64], sample_rate: f64) -> Self {
+        let max_length = (MAX_IMPULSE_TIME * sample_rate) as usize;
+        let left = &left[..left.len().min(max_length)];
+        let right = &right[..right.len().min(max_length)];
+
+        // roughly unity gain for a broadband input
+        let energy = |samples: &[f64]| samples.iter().map(|s| s * s).sum::<f64>();
+        let scale = 1.0 / energy(left).max(energy(right)).sqrt().max(1e-9);
+
+        let fft = FftPlanner::new().plan_fft_forward(FFT_LENGTH);
+        // both sides get as many, and an empty response still needs one, silent, partition
+        let count = ((left.len().max(right.len()) + BLOCK_LENGTH - 1) / BLOCK_LENGTH).max(1);
+
+        Self {
+            partitions: [partition(&*fft, left, scale, count), partition(&*fft, right, scale, count)],
+        }
+    }
+}
+
+fn partition(fft: &dyn Fft<f64>, samples: &[f64], scale: f64, count: usize) -> Vec<Vec<Complex<f64>>> {
+    let mut scratch = vec![Complex::default(); fft.get_inplace_scratch_len()];
+    let mut blocks = samples.chunks(BLOCK_LENGTH);
+
+    (0..count)
+        .map(|_| {
+            let mut spectrum = vec![Complex::default(); FFT_LENGTH];
+            for (bin, &sample) in spectrum.iter_mut().zip(blocks.next().unwrap_or(&[])) {
+                *bin = Complex::new(sample * scale, 0.0);
+            }
+            fft.process_with_scratch(&mut spectrum, &mut scratch);
+            spectrum.truncate(HALF_LENGTH);
+            spectrum
+        })
+        .collect()
+}
+
+fn resample(samples: &[f64], from_rate: f64, to_rate: f64) -> Vec<f64> {
+    if (from_rate - to_rate).abs() < 1.0 || samples.is_empty() {
+        return samples.to_vec();
+    }
+
+    let step = from_rate / to_rate;
+    let length = ((samples.len() - 1) as f64 / step) as usize + 1;
+    (0..length)
+        .map(|i| {
+            let position = i as f64 * step;
+            let index = position as usize;
+            let next = samples.get(index + 1).copied().unwrap_or(samples[index]);
+            samples[index] + (next - samples[index]) * (position - index as f64)
+        })
+        .collect()
+}
+
+/// Uniformly partitioned overlap-save convolution of both channels. They
+/// share one complex FFT each way, left as the real part and right as the
+/// imaginary, and are pulled apart by the spectrum's symmetry, so only the
+/// lower half of each channel's spectrum is multiplied.
+struct Convolver {
+    fft: Arc<dyn Fft<f64>>,
+    ifft: Arc<dyn Fft<f64>>,
+    partitions: [Vec<Vec<Complex<f64>>>; 2],
+    /// spectra of the most recent input blocks per channel, newest at
+    /// `position`
+    history: [Vec<Vec<Complex<f64>>>; 2],
+    position: usize,
+    /// per channel, the previous input block followed by the one being
+    /// filled
+    input: [Vec<f64>; 2],
+    output: [Vec<f64>; 2],
+    spectrum: Vec<Complex<f64>>,
+    accumulators: [Vec<Complex<f64>>; 2],
+    scratch: Vec<Complex<f64>>,
+}
+
+impl Convolver {
+    fn new(fft: Arc<dyn Fft<f64>>, ifft: Arc<dyn Fft<f64>>, partitions: [Vec<Vec<Complex<f64>>>; 2]) -> Self {
+        let scratch_length = fft.get_inplace_scratch_len().max(ifft.get_inplace_scratch_len());
+        let history = || vec![vec![Complex::default(); HALF_LENGTH]; partitions[0].len()];
+
+        Self {
+            history: [history(), history()],
+            position: 0,
+            input: [vec![0.0; FFT_LENGTH], vec![0.0; FFT_LENGTH]],
+            output: [vec![0.0; BLOCK_LENGTH], vec![0.0; BLOCK_LENGTH]],
+            spectrum: vec![Complex::default(); FFT_LENGTH],
+            accumulators: [vec![Complex::default(); HALF_LENGTH], vec![Complex::default(); HALF_LENGTH]],
+            scratch: vec![Complex::default(); scratch_length],
+            partitions,
+            fft,
+            ifft,
+        }
+    }
+
+    fn process_block(&mut self) {
+        for ((bin, &left), &right) in self.spectrum.iter_mut().zip(&self.input[0]).zip(&self.input[1]) {
+            *bin = Complex::new(left, right);
+        }
+        self.fft.process_with_scratch(&mut self.spectrum, &mut self.scratch);
+
+        for k in 0..HALF_LENGTH {
+            let bin = self.spectrum[k];
+            let mirror = self.spectrum[(FFT_LENGTH - k) % FFT_LENGTH].conj();
+            self.history[0][self.position][k] = (bin + mirror) * 0.5;
+            self.history[1][self.position][k] = (bin - mirror) * Complex::new(0.0, -0.5);
+        }
+
+        let count = self.partitions[0].len();
+        for channel in 0..2 {
+            let accumulator = &mut self.accumulators[channel];
+            accumulator.fill(Complex::default());
+            for (k, partition) in self.partitions[channel].iter().enumerate() {
+                let spectrum = &self.history[channel][(self.position + count - k) % count];
+                for ((acc, x), h) in accumulator.iter_mut().zip(spectrum).zip(partition) {
+                    *acc += x * h;
+                }
+            }
+        }
+
+        // left plus i times right, the upper half mirroring the lower
+        let i = Complex::new(0.0, 1.0);
+        for k in 0..HALF_LENGTH {
+            let (left, right) = (self.accumulators[0][k], self.accumulators[1][k]);
+            self.spectrum[k] = left + right * i;
+            if k > 0 && k < FFT_LENGTH - k {
+                self.spectrum[FFT_LENGTH - k] = left.conj() + right.conj() * i;
+            }
+        }
+        self.ifft.process_with_scratch(&mut self.spectrum, &mut self.scratch);
+
+        // the first half wraps around, only the second is valid
+        for (j, bin) in self.spectrum[BLOCK_LENGTH..].iter().enumerate() {
+            self.output[0][j] = bin.re / FFT_LENGTH as f64;
+            self.output[1][j] = bin.im / FFT_LENGTH as f64;
+        }
+
+        for input in self.input.iter_mut() {
+            input.copy_within(BLOCK_LENGTH.., 0);
+        }
+        self.position = (self.position + 1) % count;
+    }
+}
+
+/// Convolves the input with an impulse response, one block behind the
+/// input. As a send effect the dry level should be turned down.
+pub struct ConvolutionReverb {
+    convolver: Convolver,
+    fill: usize,
+    values: [f64; 2],
+}
+
+impl ConvolutionReverb {
+    pub fn new(sample_rate: f64) -> Self {
+        Self::with_impulse_response(ImpulseResponse::synthetic(sample_rate, DEFAULT_DECAY))
+    }
+
+    pub fn with_impulse_response(impulse_response: ImpulseResponse) -> Self {
+        let mut planner = FftPlanner::new();
+        let fft = planner.plan_fft_forward(FFT_LENGTH);
+        let ifft = planner.plan_fft_inverse(FFT_LENGTH);
+
+        let mut reverb = Self {
+            convolver: Convolver::new(fft, ifft, impulse_response.partitions),
+            fill: 0,
+            values: [0.0; 2],
+        };
+
+        for (i, parameter) in REVERB_PARAMETERS.iter().enumerate() {
+            reverb.set_parameter(i, parameter.default);
+        }
+        reverb
+    }
+}
+
+impl Effect for ConvolutionReverb {
+    fn parameters(&self) -> &'static [ParameterDescriptor] {
+        &REVERB_PARAMETERS
+    }
+
+    fn mix_parameters(&self) -> Option<(usize, usize)> {
+        Some((WET, DRY))
+    }
+
+    fn get_parameter(&self, index: usize) -> f64 {
+        self.values.get(index).copied().unwrap_or_default()
+    }
+
+    fn set_parameter(&mut self, index: usize, value: f64) {
+        if let Some(parameter) = self.values.get_mut(index) {
+            *parameter = value;
+        }
+    }
+
+    fn process_audio(&mut self, _sample_pos: usize, data: &mut [f64]) {
+        let wet = self.values[WET];
+        let dry = self.values[DRY];
+
+        for frame in data.chunks_mut(CHANNELS) {
+            for (channel, sample) in frame.iter_mut().enumerate() {
+                self.convolver.input[channel][BLOCK_LENGTH + self.fill] = *sample;
+                *sample = self.convolver.output[channel][self.fill] * wet + *sample * dry;
+            }
+
+            self.fill += 1;
+            if self.fill == BLOCK_LENGTH {
+                self.fill = 0;
+                self.convolver.process_block();
+            }
+        }
+    }
+}
@@ -0,0 +1,189 @@
+use std::f64::consts::PI;
+
+use super::{Effect, ParameterDescriptor, SWITCH, time_coefficient};
+use crate::audio::source::CHANNELS;
+
+const MAX_DELAY_TIME: f64 = 4.0;
+/// how long a change of delay time takes to settle, in seconds
+const TIME_SMOOTHING: f64 = 0.05;
+/// 120 bpm until the engine says otherwise
+const DEFAULT_BEAT_TIME: f64 = 0.5;
+
+const NOTE_NAMES: &[&str] = &["1/32", "1/16T", "1/16", "1/8T", "1/16.", "1/8", "1/4T", "1/8.", "1/4", "1/4.", "1/2", "1/1"];
+/// note values in beats, matching `NOTE_NAMES`
+const NOTE_BEATS: [f64; 12] = [0.125, 1.0 / 6.0, 0.25, 1.0 / 3.0, 0.375, 0.5, 2.0 / 3.0, 0.75, 1.0, 1.5, 2.0, 4.0];
+
+const SYNC: usize = 0;
+const TIME: usize = 1;
+const NOTE: usize = 2;
+const FEEDBACK: usize = 3;
+const PING_PONG: usize = 4;
+const LOW_CUT: usize = 5;
+const HIGH_CUT: usize = 6;
+const WET: usize = 7;
+const DRY: usize = 8;
+
+static DELAY_PARAMETERS: [ParameterDescriptor; 9] = [
+    ParameterDescriptor { name: "Sync", min: 0.0, max: 1.0, default: 1.0, unit: "", labels: SWITCH },
+    ParameterDescriptor { name: "Time", min: 1.0, max: MAX_DELAY_TIME * 1000.0, default: 250.0, unit: "ms", labels: &[] },
+    ParameterDescriptor { name: "Note", min: 0.0, max: 11.0, default: 5.0, unit: "", labels: NOTE_NAMES },
+    ParameterDescriptor { name: "Feedback", min: 0.0, max: 0.95, default: 0.4, unit: "", labels: &[] },
+    ParameterDescriptor { name: "Ping-pong", min: 0.0, max: 1.0, default: 0.0, unit: "", labels: SWITCH },
+    ParameterDescriptor { name: "Low cut", min: 20.0, max: 2000.0, default: 80.0, unit: "Hz", labels: &[] },
+    ParameterDescriptor { name: "High cut", min: 500.0, max: 20000.0, default: 8000.0, unit: "Hz", labels: &[] },
+    ParameterDescriptor { name: "Wet", min: 0.0, max: 1.0, default: 0.3, unit: "", labels: &[] },
+    ParameterDescriptor { name: "Dry", min: 0.0, max: 1.0, default: 1.0, unit: "", labels: &[] },
+];
+
+struct DelayLine {
+    buffer: Vec<f64>,
+    cursor: usize,
+    lowpass: f64,
+    highpass: f64,
+}
+
+impl DelayLine {
+    fn new(length: usize) -> Self {
+        Self { buffer: vec![Default::default(); length], cursor: 0, lowpass: 0.0, highpass: 0.0 }
+    }
+
+    /// Reads `delay` frames behind the write cursor, interpolating between
+    /// frames so the time can glide.
+    fn read(&self, delay: f64) -> f64 {
+        let length = self.buffer.len();
+        let position = self.cursor as f64 + length as f64 - delay;
+        let index = position.floor();
+        let fraction = position - index;
+        let a = self.buffer[index as usize % length];
+        let b = self.buffer[(index as usize + 1) % length];
+
+        a + (b - a) * fraction
+    }
+
+    fn write(&mut self, sample: f64) {
+        self.buffer[self.cursor] = sample;
+        self.cursor = (self.cursor + 1) % self.buffer.len();
+    }
+
+    /// band limits the feedback with a one pole low pass and high pass
+    fn filter(&mut self, sample: f64, lowpass: f64, highpass: f64) -> f64 {
+        self.lowpass += lowpass * (sample - self.lowpass);
+        self.highpass += highpass * (self.lowpass - self.highpass);
+        self.lowpass - self.highpass
+    }
+}
+
+/// A stereo delay with a line per channel. In ping-pong mode the input is
+/// summed into the left line and the repeats alternate between the sides.
+pub struct Delay {
+    sample_rate: f64,
+    left: DelayLine,
+    right: DelayLine,
+    values: [f64; 9],
+    beat_length: f64,
+    /// the delay in frames, gliding towards `target_delay`
+    delay: f64,
+    target_delay: f64,
+    smoothing: f64,
+    lowpass: f64,
+    highpass: f64,
+}
+
+impl Delay {
+    pub fn new(sample_rate: f64) -> Self {
+        let length = (MAX_DELAY_TIME * sample_rate) as usize + 2;
+        let mut delay = Self {
+            sample_rate,
+            left: DelayLine::new(length),
+            right: DelayLine::new(length),
+            values: [0.0; 9],
+            beat_length: DEFAULT_BEAT_TIME * sample_rate,
+            delay: 0.0,
+            target_delay: 0.0,
+            smoothing: time_coefficient(TIME_SMOOTHING, sample_rate),
+            lowpass: 0.0,
+            highpass: 0.0,
+        };
+
+        for (i, parameter) in DELAY_PARAMETERS.iter().enumerate() {
+            delay.set_parameter(i, parameter.default);
+        }
+        delay.delay = delay.target_delay;
+        delay
+    }
+
+    fn update_delay(&mut self) {
+        let frames = if self.values[SYNC] > 0.5 {
+            NOTE_BEATS[self.values[NOTE] as usize] * self.beat_length
+        } else {
+            self.values[TIME] / 1000.0 * self.sample_rate
+        };
+
+        self.target_delay = frames.max(1.0).min((self.left.buffer.len() - 2) as f64);
+    }
+
+    fn cutoff_coefficient(&self, frequency: f64) -> f64 {
+        1.0 - (-2.0 * PI * frequency / self.sample_rate).exp()
+    }
+}
+
+impl Effect for Delay {
+    fn parameters(&self) -> &'static [ParameterDescriptor] {
+        &DELAY_PARAMETERS
+    }
+
+    fn mix_parameters(&self) -> Option<(usize, usize)> {
+        Some((WET, DRY))
+    }
+
+    fn get_parameter(&self, index: usize) -> f64 {
+        self.values.get(index).copied().unwrap_or_default()
+    }
+
+    fn set_parameter(&mut self, index: usize, value: f64) {
+        if index >= self.values.len() {
+            return;
+        }
+        self.values[index] = value;
+
+        match index {
+            SYNC | TIME | NOTE => self.update_delay(),
+            LOW_CUT => self.highpass = self.cutoff_coefficient(value),
+            HIGH_CUT => self.lowpass = self.cutoff_coefficient(value),
+            _ => {}
+        }
+    }
+
+    fn set_beat_length(&mut self, beat_length: f64) {
+        self.beat_length = beat_length;
+        self.update_delay();
+    }
+
+    fn process_audio(&mut self, _sample_pos: usize, data: &mut [f64]) {
+        let feedback = self.values[FEEDBACK];
+        let ping_pong = self.values[PING_PONG] > 0.5;
+        let wet = self.values[WET];
+        let dry = self.values[DRY];
+
+        for frame in data.chunks_mut(CHANNELS) {
+            self.delay += (self.target_delay - self.delay) * self.smoothing;
+
+            let (in_left, in_right) = (frame[0], frame[1]);
+            let out_left = self.left.read(self.delay);
+            let out_right = self.right.read(self.delay);
+            let feedback_left = self.left.filter(out_left, self.lowpass, self.highpass) * feedback;
+            let feedback_right = self.right.filter(out_right, self.lowpass, self.highpass) * feedback;
+
+            if ping_pong {
+                self.left.write((in_left + in_right) * 0.5 + feedback_right);
+                self.right.write(feedback_left);
+            } else {
+                self.left.write(in_left + feedback_left);
+                self.right.write(in_right + feedback_right);
+            }
+
+            frame[0] = out_left * wet + in_left * dry;
+            frame[1] = out_right * wet + in_right * dry;
+        }
+    }
+}
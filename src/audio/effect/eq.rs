@@ -0,0 +1,105 @@
+use crate::audio::source::CHANNELS;
+
+use super::{
+    Effect, ParameterDescriptor,
+    biquad::{Biquad, BiquadCoefficients},
+};
+
+const LOW_FREQUENCY: usize = 0;
+const LOW_GAIN: usize = 1;
+const MID_1_FREQUENCY: usize = 2;
+const MID_1_GAIN: usize = 3;
+const MID_1_Q: usize = 4;
+const MID_2_FREQUENCY: usize = 5;
+const MID_2_GAIN: usize = 6;
+const MID_2_Q: usize = 7;
+const HIGH_FREQUENCY: usize = 8;
+const HIGH_GAIN: usize = 9;
+
+const BANDS: usize = 4;
+
+static EQ_PARAMETERS: [ParameterDescriptor; 10] = [
+    ParameterDescriptor { name: "Low", min: 20.0, max: 1000.0, default: 100.0, unit: "Hz", labels: &[] },
+    ParameterDescriptor { name: "Low gain", min: -18.0, max: 18.0, default: 0.0, unit: "dB", labels: &[] },
+    ParameterDescriptor { name: "Mid 1", min: 100.0, max: 8000.0, default: 500.0, unit: "Hz", labels: &[] },
+    ParameterDescriptor { name: "Mid 1 gain", min: -18.0, max: 18.0, default: 0.0, unit: "dB", labels: &[] },
+    ParameterDescriptor { name: "Mid 1 Q", min: 0.1, max: 10.0, default: 1.0, unit: "", labels: &[] },
+    ParameterDescriptor { name: "Mid 2", min: 500.0, max: 16000.0, default: 2000.0, unit: "Hz", labels: &[] },
+    ParameterDescriptor { name: "Mid 2 gain", min: -18.0, max: 18.0, default: 0.0, unit: "dB", labels: &[] },
+    ParameterDescriptor { name: "Mid 2 Q", min: 0.1, max: 10.0, default: 1.0, unit: "", labels: &[] },
+    ParameterDescriptor { name: "High", min: 1000.0, max: 20000.0, default: 8000.0, unit: "Hz", labels: &[] },
+    ParameterDescriptor { name: "High gain", min: -18.0, max: 18.0, default: 0.0, unit: "dB", labels: &[] },
+];
+
+/// A four band parametric EQ: a low shelf, two peaks and a high shelf.
+pub struct Equalizer {
+    sample_rate: f64,
+    values: [f64; 10],
+    coefficients: [BiquadCoefficients; BANDS],
+    /// per band, per channel
+    filters: [[Biquad; 2]; BANDS],
+}
+
+impl Equalizer {
+    pub fn new(sample_rate: f64) -> Self {
+        let flat = BiquadCoefficients::peaking(sample_rate, 1000.0, 1.0, 0.0);
+        let mut eq = Self {
+            sample_rate,
+            values: [0.0; 10],
+            coefficients: [flat; BANDS],
+            filters: Default::default(),
+        };
+
+        for (i, parameter) in EQ_PARAMETERS.iter().enumerate() {
+            eq.values[i] = parameter.default;
+        }
+        for band in 0..BANDS {
+            eq.update_band(band);
+        }
+        eq
+    }
+
+    fn update_band(&mut self, band: usize) {
+        let v = &self.values;
+        self.coefficients[band] = match band {
+            0 => BiquadCoefficients::low_shelf(self.sample_rate, v[LOW_FREQUENCY], v[LOW_GAIN]),
+            1 => BiquadCoefficients::peaking(self.sample_rate, v[MID_1_FREQUENCY], v[MID_1_Q], v[MID_1_GAIN]),
+            2 => BiquadCoefficients::peaking(self.sample_rate, v[MID_2_FREQUENCY], v[MID_2_Q], v[MID_2_GAIN]),
+            _ => BiquadCoefficients::high_shelf(self.sample_rate, v[HIGH_FREQUENCY], v[HIGH_GAIN]),
+        };
+    }
+}
+
+impl Effect for Equalizer {
+    fn parameters(&self) -> &'static [ParameterDescriptor] {
+        &EQ_PARAMETERS
+    }
+
+    fn get_parameter(&self, index: usize) -> f64 {
+        self.values.get(index).copied().unwrap_or_default()
+    }
+
+    fn set_parameter(&mut self, index: usize, value: f64) {
+        if index >= self.values.len() {
+            return;
+        }
+        self.values[index] = value;
+
+        match index {
+            LOW_FREQUENCY | LOW_GAIN => self.update_band(0),
+            MID_1_FREQUENCY | MID_1_GAIN | MID_1_Q => self.update_band(1),
+            MID_2_FREQUENCY | MID_2_GAIN | MID_2_Q => self.update_band(2),
+            _ => self.update_band(3),
+        }
+    }
+
+    fn process_audio(&mut self, _sample_pos: usize, data: &mut [f64]) {
+        for frame in data.chunks_mut(CHANNELS) {
+            for (channel, sample) in frame.iter_mut().enumerate() {
+                for (coefficients, filters) in self.coefficients.iter().zip(self.filters.iter_mut()) {
+                    *sample = filters[channel].process(coefficients, *sample);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,120 @@
+use std::collections::VecDeque;
+
+use super::{Effect, ParameterDescriptor, db_to_amplitude, peak, time_coefficient};
+use crate::audio::source::CHANNELS;
+
+/// how far ahead the limiter looks for peaks, in seconds
+const LOOKAHEAD: f64 = 0.005;
+
+const CEILING: usize = 0;
+const RELEASE: usize = 1;
+
+static LIMITER_PARAMETERS: [ParameterDescriptor; 2] = [
+    ParameterDescriptor { name: "Ceiling", min: -12.0, max: 0.0, default: -0.3, unit: "dB", labels: &[] },
+    ParameterDescriptor { name: "Release", min: 10.0, max: 1000.0, default: 100.0, unit: "ms", labels: &[] },
+];
+
+/// A brickwall limiter. The output is delayed by the lookahead so the gain
+/// can come down ahead of a peak rather than clipping it.
+pub struct Limiter {
+    sample_rate: f64,
+    values: [f64; 2],
+    ceiling: f64,
+    attack: f64,
+    release: f64,
+    gain: f64,
+    /// delayed stereo frames
+    delayed: Vec<f64>,
+    /// the gain each delayed frame needs to stay under the ceiling
+    needed: Vec<f64>,
+    cursor: usize,
+    /// frames processed so far
+    frame: usize,
+    /// frame and needed gain, each lower than the ones before it, so the
+    /// front is the least gain needed across the window
+    minimum: VecDeque<(usize, f64)>,
+}
+
+impl Limiter {
+    pub fn new(sample_rate: f64) -> Self {
+        let frames = (LOOKAHEAD * sample_rate) as usize + 1;
+        let mut limiter = Self {
+            sample_rate,
+            values: [0.0; 2],
+            ceiling: 1.0,
+            // reaches the target well within the lookahead
+            attack: time_coefficient(LOOKAHEAD / 4.0, sample_rate),
+            release: 0.0,
+            gain: 1.0,
+            delayed: vec![Default::default(); frames * CHANNELS],
+            needed: vec![1.0; frames],
+            cursor: 0,
+            frame: 0,
+            // the window is the delayed frames and the one leaving
+            minimum: VecDeque::with_capacity(frames + 1),
+        };
+
+        for (i, parameter) in LIMITER_PARAMETERS.iter().enumerate() {
+            limiter.set_parameter(i, parameter.default);
+        }
+        limiter
+    }
+}
+
+impl Effect for Limiter {
+    fn parameters(&self) -> &'static [ParameterDescriptor] {
+        &LIMITER_PARAMETERS
+    }
+
+    fn get_parameter(&self, index: usize) -> f64 {
+        self.values.get(index).copied().unwrap_or_default()
+    }
+
+    fn set_parameter(&mut self, index: usize, value: f64) {
+        if index >= self.values.len() {
+            return;
+        }
+        self.values[index] = value;
+
+        match index {
+            CEILING => self.ceiling = db_to_amplitude(value),
+            RELEASE => self.release = time_coefficient(value / 1000.0, self.sample_rate),
+            _ => {}
+        }
+    }
+
+    fn process_audio(&mut self, _sample_pos: usize, data: &mut [f64]) {
+        let frames = self.needed.len();
+
+        for frame in data.chunks_mut(CHANNELS) {
+            let peak = peak(frame);
+            let needed = if peak > self.ceiling { self.ceiling / peak } else { 1.0 };
+
+            // the slot being overwritten holds the oldest frame, which is
+            // the one to output now, so the two swap
+            self.delayed[self.cursor * CHANNELS..(self.cursor + 1) * CHANNELS].swap_with_slice(frame);
+            let out_needed = self.needed[self.cursor];
+
+            self.needed[self.cursor] = needed;
+            self.cursor = (self.cursor + 1) % frames;
+
+            while matches!(self.minimum.back(), Some(&(_, back)) if back >= needed) {
+                self.minimum.pop_back();
+            }
+            self.minimum.push_back((self.frame, needed));
+            while matches!(self.minimum.front(), Some(&(front, _)) if front + frames < self.frame) {
+                self.minimum.pop_front();
+            }
+            self.frame += 1;
+
+            let target = self.minimum.front().map_or(1.0, |&(_, needed)| needed).min(out_needed);
+            let coefficient = if target < self.gain { self.attack } else { self.release };
+            self.gain += (target - self.gain) * coefficient;
+
+            let gain = self.gain.min(out_needed);
+            for sample in frame.iter_mut() {
+                *sample = (*sample * gain).max(-self.ceiling).min(self.ceiling);
+            }
+        }
+    }
+}
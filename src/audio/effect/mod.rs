@@ -0,0 +1,159 @@
+mod biquad;
+mod compressor;
+mod convolution;
+mod delay;
+mod eq;
+mod limiter;
+
+use std::fmt::{self, Display};
+
+use serde::{Deserialize, Serialize};
+
+use self::{compressor::Compressor, delay::Delay, eq::Equalizer, limiter::Limiter};
+
+pub use self::convolution::{ConvolutionReverb, ImpulseResponse};
+
+const SWITCH: &[&str] = &["Off", "On"];
+
+/// Describes one parameter of an effect, so a generic view can edit it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ParameterDescriptor {
+    pub name: &'static str,
+    pub min: f64,
+    pub max: f64,
+    pub default: f64,
+    pub unit: &'static str,
+    /// names for stepped parameters, whose values are indices into this list
+    pub labels: &'static [&'static str],
+}
+
+impl ParameterDescriptor {
+    pub fn clamp(&self, value: f64) -> f64 {
+        let value = value.max(self.min).min(self.max);
+        if self.labels.is_empty() {
+            value
+        } else {
+            value.round()
+        }
+    }
+}
+
+pub trait Effect: Send {
+    fn parameters(&self) -> &'static [ParameterDescriptor];
+    fn get_parameter(&self, index: usize) -> f64;
+    /// values are clamped to the descriptor's range by the caller
+    fn set_parameter(&mut self, index: usize, value: f64);
+    /// the indices of the wet and dry levels, for effects that mix what they
+    /// make with what they're given
+    fn mix_parameters(&self) -> Option<(usize, usize)> {
+        None
+    }
+    /// the length of a beat in frames, for effects that follow the tempo
+    fn set_beat_length(&mut self, _beat_length: f64) {}
+    fn process_audio(&mut self, sample: usize, data: &mut [f64]);
+    /// Called instead of `process_audio` when the source has a sidechain.
+    /// It may be shorter than `data`.
+    fn process_sidechain(&mut self, sample: usize, data: &mut [f64], _sidechain: &[f64]) {
+        self.process_audio(sample, data);
+    }
+}
+
+/// Every effect that can be inserted into a rack at runtime.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EffectKind {
+    Delay,
+    Equalizer,
+    Compressor,
+    Limiter,
+    Reverb,
+}
+
+impl EffectKind {
+    pub const ALL: [EffectKind; 5] = [
+        EffectKind::Delay,
+        EffectKind::Equalizer,
+        EffectKind::Compressor,
+        EffectKind::Limiter,
+        EffectKind::Reverb,
+    ];
+
+    pub fn create(&self, sample_rate: f64) -> Box<dyn Effect> {
+        match self {
+            EffectKind::Delay => Box::new(Delay::new(sample_rate)),
+            EffectKind::Equalizer => Box::new(Equalizer::new(sample_rate)),
+            EffectKind::Compressor => Box::new(Compressor::new(sample_rate)),
+            EffectKind::Limiter => Box::new(Limiter::new(sample_rate)),
+            EffectKind::Reverb => Box::new(ConvolutionReverb::new(sample_rate)),
+        }
+    }
+}
+
+impl Display for EffectKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EffectKind::Delay => write!(f, "Delay"),
+            EffectKind::Equalizer => write!(f, "EQ"),
+            EffectKind::Compressor => write!(f, "Compressor"),
+            EffectKind::Limiter => write!(f, "Limiter"),
+            EffectKind::Reverb => write!(f, "Reverb"),
+        }
+    }
+}
+
+/// Tells effects in a rack apart wherever they are moved to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EffectId(pub u32);
+
+/// The UI's view of an effect in a rack.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EffectState {
+    pub id: EffectId,
+    pub kind: EffectKind,
+    pub parameters: &'static [ParameterDescriptor],
+    pub values: Vec<f64>,
+}
+
+impl EffectState {
+    pub fn new(id: EffectId, kind: EffectKind, effect: &dyn Effect) -> Self {
+        let parameters = effect.parameters();
+        Self {
+            id,
+            kind,
+            parameters,
+            values: (0..parameters.len()).map(|i| effect.get_parameter(i)).collect(),
+        }
+    }
+}
+
+/// What a project keeps of an effect. The id is kept so automation lanes
+/// still find it when the project is opened.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SavedEffect {
+    pub id: EffectId,
+    pub kind: EffectKind,
+    pub values: Vec<f64>,
+}
+
+impl From<&EffectState> for SavedEffect {
+    fn from(state: &EffectState) -> Self {
+        Self { id: state.id, kind: state.kind, values: state.values.clone() }
+    }
+}
+
+fn db_to_amplitude(db: f64) -> f64 {
+    10f64.powf(db / 20.0)
+}
+
+fn amplitude_to_db(amplitude: f64) -> f64 {
+    20.0 * amplitude.max(1e-6).log10()
+}
+
+/// the loudest sample of a frame
+fn peak(frame: &[f64]) -> f64 {
+    frame.iter().fold(0.0, |peak: f64, sample| peak.max(sample.abs()))
+}
+
+/// the one pole smoothing coefficient that settles in about `time` seconds
+fn time_coefficient(time: f64, sample_rate: f64) -> f64 {
+    1.0 - (-1.0 / (time * sample_rate)).exp()
+}
@@ -0,0 +1,56 @@
+use std::time::{Duration, Instant};
+
+const REPORT_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LatencyReport {
+    pub average: Duration,
+    pub worst: Duration,
+    pub lookahead: Duration,
+}
+
+/// Measures how long the engine takes to wake up and schedule events after
+/// the audio callback reports its progress. As long as the worst case stays
+/// below the lookahead, every event reaches the synth before it is due.
+pub struct LatencyMeter {
+    total: Duration,
+    worst: Duration,
+    count: u32,
+    last_report: Instant,
+}
+
+impl LatencyMeter {
+    pub fn new() -> Self {
+        Self {
+            total: Duration::default(),
+            worst: Duration::default(),
+            count: 0,
+            last_report: Instant::now(),
+        }
+    }
+
+    pub fn record(&mut self, callback_time: Instant) {
+        let latency = callback_time.elapsed();
+
+        self.total += latency;
+        self.worst = self.worst.max(latency);
+        self.count += 1;
+    }
+
+    /// Returns a report once per interval, resetting the measurements.
+    pub fn report(&mut self, lookahead: Duration) -> Option<LatencyReport> {
+        if self.count == 0 || self.last_report.elapsed() < REPORT_INTERVAL {
+            return None;
+        }
+
+        let report = LatencyReport {
+            average: self.total / self.count,
+            worst: self.worst,
+            lookahead,
+        };
+
+        *self = Self::new();
+
+        Some(report)
+    }
+}
@@ -0,0 +1,133 @@
+use std::{f64::consts::PI, sync::Arc};
+
+use crossbeam::queue::SegQueue;
+
+use super::source::{CHANNELS, Source};
+
+const CLICK_LENGTH: f64 = 0.05;
+const ACCENT_FREQUENCY: f64 = 1760.0;
+const BEAT_FREQUENCY: f64 = 880.0;
+const MAX_CLICKS: usize = 8;
+/// room for a long count-in, so scheduling never allocates on the audio thread
+const MAX_SCHEDULED: usize = 256;
+
+enum MetronomeEvent {
+    Click(usize, bool),
+    Clear,
+    SetVolume(f64),
+}
+
+#[derive(Clone)]
+pub struct MetronomeController {
+    event_queue: Arc<SegQueue<MetronomeEvent>>,
+}
+
+struct Click {
+    position: usize,
+    accent: bool,
+}
+
+/// Synthesizes short decaying sine blips at the scheduled samples.
+pub struct MetronomeSource {
+    sample_rate: f64,
+    volume: f64,
+    scheduled: Vec<(usize, bool)>,
+    playing: Vec<Click>,
+    event_queue: Arc<SegQueue<MetronomeEvent>>,
+}
+
+pub struct MetronomeGenerator {}
+
+impl MetronomeGenerator {
+    pub fn new(sample_rate: f64, volume: f64) -> (MetronomeController, MetronomeSource) {
+        let event_queue = Arc::new(SegQueue::new());
+
+        (
+            MetronomeController { event_queue: event_queue.clone() },
+            MetronomeSource {
+                sample_rate,
+                volume,
+                scheduled: Vec::with_capacity(MAX_SCHEDULED),
+                playing: Vec::with_capacity(MAX_CLICKS),
+                event_queue,
+            },
+        )
+    }
+}
+
+impl MetronomeController {
+    /// accented clicks are used for downbeats
+    pub fn click(&self, sample: usize, accent: bool) {
+        self.event_queue.push(MetronomeEvent::Click(sample, accent));
+    }
+
+    pub fn clear(&self) {
+        self.event_queue.push(MetronomeEvent::Clear);
+    }
+
+    pub fn set_volume(&self, volume: f64) {
+        self.event_queue.push(MetronomeEvent::SetVolume(volume));
+    }
+}
+
+impl Click {
+    fn sample(&self, sample_rate: f64) -> f64 {
+        let time = self.position as f64 / sample_rate;
+        let frequency = if self.accent { ACCENT_FREQUENCY } else { BEAT_FREQUENCY };
+        let envelope = (1.0 - time / CLICK_LENGTH).max(0.0).powi(3);
+
+        (time * frequency * 2.0 * PI).sin() * envelope
+    }
+}
+
+impl Source for MetronomeSource {
+    fn output_audio(&mut self, sample: usize, data: &mut [f64]) {
+        while let Some(event) = self.event_queue.pop() {
+            match event {
+                MetronomeEvent::Click(click_sample, accent) => {
+                    let pos = self.scheduled
+                        .binary_search_by_key(&click_sample, |(s, _)| *s)
+                        .unwrap_or_else(|e| e);
+                    if self.scheduled.len() == MAX_SCHEDULED {
+                        // full, so the latest click is the one dropped
+                        if pos == MAX_SCHEDULED {
+                            continue;
+                        }
+                        self.scheduled.pop();
+                    }
+                    self.scheduled.insert(pos, (click_sample, accent));
+                }
+                MetronomeEvent::Clear => {
+                    self.scheduled.clear();
+                    self.playing.clear();
+                }
+                MetronomeEvent::SetVolume(volume) => self.volume = volume,
+            }
+        }
+
+        let click_length = (CLICK_LENGTH * self.sample_rate) as usize;
+        let mut started = 0;
+
+        for (frame, output) in data.chunks_mut(CHANNELS).enumerate() {
+            while started < self.scheduled.len() && self.scheduled[started].0 <= sample + frame {
+                let (click_sample, accent) = self.scheduled[started];
+                if self.playing.len() < MAX_CLICKS {
+                    // clicks scheduled in the past start part way through
+                    self.playing.push(Click { position: sample + frame - click_sample, accent });
+                }
+                started += 1;
+            }
+
+            let mut value = 0.0;
+            for click in self.playing.iter_mut() {
+                value += click.sample(self.sample_rate);
+                click.position += 1;
+            }
+            self.playing.retain(|click| click.position < click_length);
+
+            output.fill(value * self.volume);
+        }
+
+        self.scheduled.drain(0..started);
+    }
+}
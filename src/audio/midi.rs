@@ -0,0 +1,92 @@
+use std::fmt::{self, Display};
+
+use crate::sequence::Pitch;
+
+pub const CLIENT_NAME: &str = "piano_roll";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MidiPort {
+    /// a port other applications can connect to, e.g. through the ALSA sequencer
+    Virtual,
+    Named(String),
+}
+
+impl Display for MidiPort {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MidiPort::Virtual => write!(f, "Virtual port"),
+            MidiPort::Named(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MidiMessage {
+    NoteOn { channel: u8, key: u8, velocity: u8 },
+    NoteOff { channel: u8, key: u8 },
+    PitchBend { channel: u8, value: u16 },
+    ControlChange { channel: u8, controller: u8, value: u8 },
+    ChannelPressure { channel: u8, value: u8 },
+}
+
+impl MidiMessage {
+    /// Parses a channel voice message, ignoring anything we don't handle.
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        let status = *bytes.get(0)?;
+        let channel = status & 0x0f;
+
+        match (status & 0xf0, bytes.get(1), bytes.get(2)) {
+            (0x90, Some(&key), Some(&0)) => Some(MidiMessage::NoteOff { channel, key }),
+            (0x90, Some(&key), Some(&velocity)) => Some(MidiMessage::NoteOn { channel, key, velocity }),
+            (0x80, Some(&key), Some(_)) => Some(MidiMessage::NoteOff { channel, key }),
+            (0xe0, Some(&lsb), Some(&msb)) => Some(MidiMessage::PitchBend {
+                channel,
+                value: (lsb as u16 & 0x7f) | ((msb as u16 & 0x7f) << 7),
+            }),
+            (0xb0, Some(&controller), Some(&value)) => Some(MidiMessage::ControlChange { channel, controller, value }),
+            (0xd0, Some(&value), _) => Some(MidiMessage::ChannelPressure { channel, value }),
+            _ => None,
+        }
+    }
+
+    pub fn to_bytes(&self) -> [u8; 3] {
+        match *self {
+            MidiMessage::NoteOn { channel, key, velocity } => [0x90 | (channel & 0x0f), key & 0x7f, velocity & 0x7f],
+            MidiMessage::NoteOff { channel, key } => [0x80 | (channel & 0x0f), key & 0x7f, 0],
+            MidiMessage::PitchBend { channel, value } => [0xe0 | (channel & 0x0f), (value & 0x7f) as u8, ((value >> 7) & 0x7f) as u8],
+            MidiMessage::ControlChange { channel, controller, value } => [0xb0 | (channel & 0x0f), controller & 0x7f, value & 0x7f],
+            MidiMessage::ChannelPressure { channel, value } => [0xd0 | (channel & 0x0f), value & 0x7f, 0],
+        }
+    }
+
+    /// how much of `to_bytes` makes up the message
+    pub fn byte_count(&self) -> usize {
+        match self {
+            MidiMessage::ChannelPressure { .. } => 2,
+            _ => 3,
+        }
+    }
+
+    /// The registered parameter number messages that set the pitch bend
+    /// range of a channel, in semitones and cents.
+    pub fn bend_range(channel: u8, range: f32) -> Vec<Self> {
+        let semitones = range.trunc() as u8;
+        let cents = (range.fract() * 100.0).round() as u8;
+
+        [(101, 0), (100, 0), (6, semitones), (38, cents), (101, 127), (100, 127)].iter()
+            .map(|&(controller, value)| MidiMessage::ControlChange { channel, controller, value })
+            .collect()
+    }
+
+    /// The MPE configuration message, giving the zone managed by `channel`
+    /// as many member channels.
+    pub fn mpe_configuration(channel: u8, members: u8) -> Vec<Self> {
+        [(101, 0), (100, 6), (6, members), (101, 127), (100, 127)].iter()
+            .map(|&(controller, value)| MidiMessage::ControlChange { channel, controller, value })
+            .collect()
+    }
+}
+
+pub fn key_to_pitch(key: u8) -> Pitch {
+    Pitch::new(key as i32 - 69, 12)
+}
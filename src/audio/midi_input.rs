@@ -0,0 +1,85 @@
+use std::fmt::Display;
+use std::time::Instant;
+
+use crossbeam::channel::Sender;
+use midir::{MidiInput, MidiInputConnection};
+
+use super::midi::{MidiMessage, MidiPort, CLIENT_NAME};
+
+pub fn available_ports() -> Vec<MidiPort> {
+    let mut ports = vec![];
+
+    if cfg!(unix) {
+        ports.push(MidiPort::Virtual);
+    }
+
+    if let Ok(input) = MidiInput::new(CLIENT_NAME) {
+        ports.extend(input.ports().iter()
+            .filter_map(|port| input.port_name(port).ok())
+            .map(MidiPort::Named));
+    }
+
+    ports
+}
+
+/// Keeps the connection to the selected input port alive. Incoming messages
+/// are timestamped on arrival and forwarded to the engine thread.
+pub struct MidiInputHandler {
+    connection: Option<MidiInputConnection<()>>,
+    sender: Sender<(MidiMessage, Instant)>,
+}
+
+impl MidiInputHandler {
+    pub fn new(sender: Sender<(MidiMessage, Instant)>) -> Self {
+        Self {
+            connection: None,
+            sender,
+        }
+    }
+
+    pub fn connect(&mut self, port: &MidiPort) -> Result<(), String> {
+        self.connection = None;
+
+        let input = MidiInput::new(CLIENT_NAME).map_err(midi_error)?;
+        let sender = self.sender.clone();
+        let callback = move |_timestamp: u64, bytes: &[u8], _: &mut ()| {
+            if let Some(message) = MidiMessage::parse(bytes) {
+                let _ = sender.try_send((message, Instant::now()));
+            }
+        };
+
+        let connection = match port {
+            MidiPort::Virtual => Self::connect_virtual(input, callback)?,
+            MidiPort::Named(name) => {
+                let midi_port = input.ports().into_iter()
+                    .find(|midi_port| input.port_name(midi_port).map_or(false, |port_name| &port_name == name))
+                    .ok_or_else(|| format!("MIDI input port not found: {}", name))?;
+
+                input.connect(&midi_port, CLIENT_NAME, callback, ()).map_err(midi_error)?
+            }
+        };
+
+        self.connection = Some(connection);
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    fn connect_virtual<F>(input: MidiInput, callback: F) -> Result<MidiInputConnection<()>, String>
+        where F: FnMut(u64, &[u8], &mut ()) + Send + 'static
+    {
+        use midir::os::unix::VirtualInput;
+
+        input.create_virtual(CLIENT_NAME, callback, ()).map_err(midi_error)
+    }
+
+    #[cfg(not(unix))]
+    fn connect_virtual<F>(_input: MidiInput, _callback: F) -> Result<MidiInputConnection<()>, String>
+        where F: FnMut(u64, &[u8], &mut ()) + Send + 'static
+    {
+        Err("virtual MIDI ports are not supported on this platform".to_string())
+    }
+}
+
+pub(super) fn midi_error<T: Display>(err: T) -> String {
+    format!("MIDI error: {}", err)
+}
@@ -0,0 +1,355 @@
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use crossbeam::{
+    channel::{self, Receiver, Sender},
+    queue::SegQueue,
+};
+use midir::{MidiOutput, MidiOutputConnection};
+
+use crate::sequence::Pitch;
+
+use super::{
+    controller::{ChannelBends, Controller, Event, EventData, GLIDE_BEND_RANGE, GLIDE_CHANNELS, insert_event},
+    midi::{MidiMessage, MidiPort, CLIENT_NAME},
+    midi_input::midi_error,
+    source::{CHANNELS, Source},
+};
+
+const MIDI_CHANNELS: u8 = 16;
+/// Glide channels go out on the members of an MPE upper zone, 3 to 14, so
+/// the plain sequence, preview and monitor channels below it keep their own
+/// bends.
+const ZONE_MANAGER: u8 = MIDI_CHANNELS - 1;
+const ZONE_FIRST_MEMBER: u8 = ZONE_MANAGER - GLIDE_CHANNELS.len() as u8;
+/// events the audio thread can get ahead of the MIDI thread by. More are
+/// dropped rather than allocating on the audio thread.
+const TIMED_QUEUE_LENGTH: usize = 4096;
+/// events scheduled ahead of the audio stream, the rest are dropped for the
+/// same reason
+const EVENT_BUFFER_LENGTH: usize = 4096;
+
+enum MidiOutputCommand {
+    Connect(MidiOutputConnection),
+    SetBendRange(f32),
+}
+
+#[derive(Clone)]
+pub struct MidiOutputController {
+    event_queue: Arc<SegQueue<Event>>,
+    command_sender: Sender<MidiOutputCommand>,
+}
+
+/// Hands events to the MIDI thread once the audio stream reaches them,
+/// stamped with the time their sample is heard, output latency included, so
+/// MIDI stays in time with the internal synth. The source itself outputs
+/// silence.
+pub struct MidiOutputSource {
+    events: Vec<Event>,
+    event_queue: Arc<SegQueue<Event>>,
+    timed_sender: Sender<(Instant, EventData)>,
+    /// in nanoseconds, from a callback to its buffer being played
+    output_latency: Arc<AtomicU64>,
+    sample_rate: f64,
+}
+
+/// Owns the connection, sending each message at its time so the audio
+/// thread never waits on the MIDI driver. It sleeps until a command, an
+/// event or the next pending message's time.
+struct MidiOutputThread {
+    connection: Option<MidiOutputConnection>,
+    pending: VecDeque<(Instant, EventData)>,
+    bend_range: f32,
+    playing_notes: Vec<(u8, u8)>,
+    bends: ChannelBends,
+}
+
+pub struct MidiOutputGenerator {}
+
+impl MidiOutputGenerator {
+    /// `output_latency` is the stream's, in nanoseconds, as the emitter
+    /// measures it.
+    pub fn new(
+        bend_range: f32,
+        sample_rate: f64,
+        output_latency: Arc<AtomicU64>,
+    ) -> (MidiOutputController, MidiOutputSource) {
+        let event_queue = Arc::new(SegQueue::new());
+        let (timed_sender, timed_receiver) = channel::bounded(TIMED_QUEUE_LENGTH);
+        let (command_sender, command_receiver) = channel::unbounded();
+
+        let output_thread = MidiOutputThread {
+            connection: None,
+            pending: VecDeque::with_capacity(TIMED_QUEUE_LENGTH),
+            bend_range,
+            playing_notes: Vec::new(),
+            bends: ChannelBends::default(),
+        };
+        thread::spawn(move || output_thread.run(command_receiver, timed_receiver));
+
+        (
+            MidiOutputController { event_queue: event_queue.clone(), command_sender },
+            MidiOutputSource {
+                events: Vec::with_capacity(EVENT_BUFFER_LENGTH),
+                event_queue,
+                timed_sender,
+                output_latency,
+                sample_rate,
+            },
+        )
+    }
+}
+
+pub fn available_ports() -> Vec<MidiPort> {
+    let mut ports = vec![];
+
+    if cfg!(unix) {
+        ports.push(MidiPort::Virtual);
+    }
+
+    if let Ok(output) = MidiOutput::new(CLIENT_NAME) {
+        ports.extend(output.ports().iter()
+            .filter_map(|port| output.port_name(port).ok())
+            .map(MidiPort::Named));
+    }
+
+    ports
+}
+
+impl MidiOutputController {
+    /// Opens the port on the calling thread and hands the connection over to
+    /// the MIDI thread.
+    pub fn connect(&self, port: &MidiPort) -> Result<(), String> {
+        let output = MidiOutput::new(CLIENT_NAME).map_err(midi_error)?;
+
+        let connection = match port {
+            MidiPort::Virtual => Self::connect_virtual(output)?,
+            MidiPort::Named(name) => {
+                let midi_port = output.ports().into_iter()
+                    .find(|midi_port| output.port_name(midi_port).map_or(false, |port_name| &port_name == name))
+                    .ok_or_else(|| format!("MIDI output port not found: {}", name))?;
+
+                output.connect(&midi_port, CLIENT_NAME).map_err(midi_error)?
+            }
+        };
+
+        let _ = self.command_sender.send(MidiOutputCommand::Connect(connection));
+        Ok(())
+    }
+
+    pub fn set_bend_range(&self, bend_range: f32) {
+        let _ = self.command_sender.send(MidiOutputCommand::SetBendRange(bend_range));
+    }
+
+    #[cfg(unix)]
+    fn connect_virtual(output: MidiOutput) -> Result<MidiOutputConnection, String> {
+        use midir::os::unix::VirtualOutput;
+
+        output.create_virtual(CLIENT_NAME).map_err(midi_error)
+    }
+
+    #[cfg(not(unix))]
+    fn connect_virtual(_output: MidiOutput) -> Result<MidiOutputConnection, String> {
+        Err("virtual MIDI ports are not supported on this platform".to_string())
+    }
+}
+
+impl Controller for MidiOutputController {
+    fn send_event(&self, event: Event) {
+        self.event_queue.push(event);
+    }
+}
+
+impl MidiOutputThread {
+    /// Sends messages as they come due, until the controllers or the source
+    /// are dropped.
+    fn run(mut self, commands: Receiver<MidiOutputCommand>, events: Receiver<(Instant, EventData)>) {
+        loop {
+            // with nothing pending, only a command or an event wakes the thread
+            let deadline = match self.pending.front() {
+                Some((time, _)) => channel::at(*time),
+                None => channel::never(),
+            };
+
+            channel::select! {
+                recv(commands) -> command => match command {
+                    Ok(MidiOutputCommand::Connect(connection)) => {
+                        self.all_notes_off();
+                        self.connection = Some(connection);
+                        self.send_bend_range();
+                    }
+                    Ok(MidiOutputCommand::SetBendRange(bend_range)) => {
+                        self.bend_range = bend_range;
+                        self.send_bend_range();
+                    }
+                    Err(_) => return,
+                },
+                recv(events) -> event => match event {
+                    Ok(event) => self.pending.push_back(event),
+                    Err(_) => return,
+                },
+                recv(deadline) -> _ => {},
+            }
+
+            let now = Instant::now();
+            while matches!(self.pending.front(), Some((time, _)) if *time <= now) {
+                let (_time, data) = self.pending.pop_front().unwrap();
+                self.send_event_data(&data);
+            }
+        }
+    }
+
+    fn send(&mut self, message: MidiMessage) {
+        if let Some(connection) = self.connection.as_mut() {
+            let bytes = message.to_bytes();
+            let _ = connection.send(&bytes[..message.byte_count()]);
+        }
+    }
+
+    /// Declares the MPE zone, then sets each channel's bend range.
+    fn send_bend_range(&mut self) {
+        for message in MidiMessage::mpe_configuration(ZONE_MANAGER, GLIDE_CHANNELS.len() as u8) {
+            self.send(message);
+        }
+
+        for channel in 0..MIDI_CHANNELS {
+            let range = match (ZONE_FIRST_MEMBER..ZONE_MANAGER).contains(&channel) {
+                true => GLIDE_BEND_RANGE,
+                false => self.bend_range,
+            };
+            for message in MidiMessage::bend_range(channel, range) {
+                self.send(message);
+            }
+        }
+    }
+
+    /// the glide channels always get a wide range, the rest the chosen one
+    fn bend_range(&self, channel: u32) -> f32 {
+        if GLIDE_CHANNELS.contains(&channel) {
+            GLIDE_BEND_RANGE
+        } else {
+            self.bend_range
+        }
+    }
+
+    fn all_notes_off(&mut self) {
+        for (channel, key) in std::mem::take(&mut self.playing_notes) {
+            self.send(MidiMessage::NoteOff { channel, key });
+        }
+    }
+
+    fn send_event_data(&mut self, data: &EventData) {
+        match data {
+            EventData::NoteOn(chan, n, velocity) => {
+                let channel = output_channel(*chan);
+                let (key, bend) = match midi_key(n, self.bend_range(*chan)) {
+                    Some(key) => key,
+                    None => return,
+                };
+
+                let bend = self.bends.note_on(*chan, bend);
+                self.send(MidiMessage::PitchBend { channel, value: bend as u16 });
+                self.send(MidiMessage::NoteOn { channel, key, velocity: *velocity });
+
+                if !self.playing_notes.contains(&(channel, key)) {
+                    self.playing_notes.push((channel, key));
+                }
+            }
+            EventData::NoteOff(chan, n) => {
+                let channel = output_channel(*chan);
+                let key = match midi_key(n, self.bend_range(*chan)) {
+                    Some((key, _bend)) => key,
+                    None => return,
+                };
+
+                self.send(MidiMessage::NoteOff { channel, key });
+
+                if let Some(i) = self.playing_notes.iter().position(|t| *t == (channel, key)) {
+                    self.playing_notes.remove(i);
+                }
+            }
+            EventData::PitchBend(chan, bend) => {
+                let bend = self.bends.bend(*chan, *bend);
+                self.send(MidiMessage::PitchBend { channel: output_channel(*chan), value: bend as u16 });
+            }
+            EventData::ControlChange(chan, controller, value) => {
+                self.send(MidiMessage::ControlChange { channel: output_channel(*chan), controller: *controller, value: *value });
+            }
+            EventData::ChannelPressure(chan, value) => {
+                self.send(MidiMessage::ChannelPressure { channel: output_channel(*chan), value: *value });
+            }
+            // the note has the channel to itself, so this is MPE's pressure
+            EventData::NotePressure(chan, _n, value) => {
+                self.send(MidiMessage::ChannelPressure { channel: output_channel(*chan), value: *value });
+            }
+            EventData::Glide(chan, n, offset) => {
+                let channel = output_channel(*chan);
+                let range = self.bend_range(*chan);
+                if let Some((key, _bend)) = midi_key(n, range) {
+                    self.send(MidiMessage::PitchBend { channel, value: n.midi_bend_from(key.into(), *offset, range) as u16 });
+                }
+            }
+            EventData::ClearEvents => {
+                self.all_notes_off();
+                self.bends.clear();
+            }
+        }
+    }
+}
+
+impl Source for MidiOutputSource {
+    fn output_audio(&mut self, sample: usize, data: &mut [f64]) {
+        let length = data.len() / CHANNELS;
+        // what's rendered now is heard one output latency later, as is the synth
+        let heard = Instant::now() + Duration::from_nanos(self.output_latency.load(Ordering::Relaxed));
+
+        while let Some(event) = self.event_queue.pop() {
+            if let EventData::ClearEvents = event.data {
+                self.events.clear();
+            }
+
+            // a full buffer would have to grow on the audio thread
+            if self.events.len() < self.events.capacity() {
+                insert_event(&mut self.events, event);
+            }
+        }
+
+        let due = self.events.iter()
+            .take_while(|event| event.sample < sample + length)
+            .count();
+
+        for event in self.events.drain(0..due) {
+            let offset = event.sample.saturating_sub(sample) as f64 / self.sample_rate;
+            // a full queue means the MIDI thread has stalled
+            let _ = self.timed_sender.try_send((heard + Duration::from_secs_f64(offset), event.data));
+        }
+
+        data.fill(Default::default());
+    }
+}
+
+/// the MIDI channel a player channel goes out on
+fn output_channel(channel: u32) -> u8 {
+    match GLIDE_CHANNELS.iter().position(|&glide| glide == channel) {
+        Some(i) => ZONE_FIRST_MEMBER + i as u8,
+        None => channel as u8,
+    }
+}
+
+/// a pitch's key and the bend detuning it, or `None` if the key is outside
+/// MIDI's 0 to 127, where it would wrap round to an unrelated note
+fn midi_key(pitch: &Pitch, bend_range: f32) -> Option<(u8, u32)> {
+    let (key, bend) = pitch.midi_pitch(bend_range);
+    if (0.0..=127.0).contains(&(pitch.to_f32() * 12.0 + 69.0).round()) {
+        Some((key as u8, bend))
+    } else {
+        None
+    }
+}
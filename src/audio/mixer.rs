@@ -1,39 +1,282 @@
-use super::source::Source;
+use std::sync::{Arc, Mutex};
+
+use crossbeam::queue::SegQueue;
+use serde::{Deserialize, Serialize};
+
+use super::parameter::{ParameterRamps, RAMP_BLOCK, RampController};
+use super::source::{CHANNELS, Source};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ChannelParameter {
+    Gain,
+    Pan,
+    Send,
+}
+
+impl ChannelParameter {
+    pub fn range(&self) -> (f64, f64) {
+        match self {
+            ChannelParameter::Gain => (0.0, 2.0),
+            ChannelParameter::Pan => (-1.0, 1.0),
+            ChannelParameter::Send => (0.0, 1.0),
+        }
+    }
+}
+
+/// The UI's view of a mixer channel. Return channels have no send.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChannelState {
+    pub name: String,
+    pub gain: f64,
+    pub pan: f64,
+    pub send: Option<f64>,
+    /// keys the master effects' sidechain, before its gain and pan
+    pub sidechain: bool,
+}
+
+impl ChannelState {
+    pub fn get(&self, parameter: ChannelParameter) -> Option<f64> {
+        match parameter {
+            ChannelParameter::Gain => Some(self.gain),
+            ChannelParameter::Pan => Some(self.pan),
+            ChannelParameter::Send => self.send,
+        }
+    }
+
+    fn set(&mut self, parameter: ChannelParameter, value: f64) {
+        match parameter {
+            ChannelParameter::Gain => self.gain = value,
+            ChannelParameter::Pan => self.pan = value,
+            ChannelParameter::Send => if self.send.is_some() { self.send = Some(value) },
+        }
+    }
+}
+
+struct Channel {
+    source: Box<dyn Source>,
+    state: ChannelState,
+}
+
+enum MixerCommand {
+    Set(usize, ChannelParameter, f64),
+    SetSidechain(Option<usize>),
+}
 
 pub struct Mixer {
-    sources: Vec<Box<dyn Source>>,
+    channels: Vec<Channel>,
     buffer: Vec<f64>,
+    send_bus: Arc<Mutex<Vec<f64>>>,
+    /// the sidechain channel over the whole callback, however it's chunked
+    sidechain_bus: Arc<Mutex<Vec<f64>>>,
+    command_queue: Arc<SegQueue<MixerCommand>>,
+    ramps: ParameterRamps<(usize, ChannelParameter)>,
+}
+
+/// Sets channel levels from outside the audio thread, keeping a copy for
+/// the UI.
+pub struct MixerController {
+    channels: Vec<ChannelState>,
+    command_queue: Arc<SegQueue<MixerCommand>>,
+    ramps: RampController<(usize, ChannelParameter)>,
+}
+
+/// Plays a buffer the mixer filled earlier in the same callback: the sum of
+/// every channel's send, for a return channel to process, or the sidechain
+/// channel, for the master effects.
+pub struct Bus {
+    buffer: Arc<Mutex<Vec<f64>>>,
 }
 
 impl Mixer {
     /// Creates a new mixer with the provided buffer length. Generally, the
     /// buffer length should be large enough to fill the entire output buffer;
     /// otherwise, the mixing process will run in multiple chunks.
-    fn new(buflen: usize) -> Self {
+    pub fn new(buflen: usize) -> Self {
         Self {
-            sources: Vec::new(),
+            channels: Vec::new(),
             buffer: vec![Default::default(); buflen],
+            send_bus: Arc::new(Mutex::new(Vec::with_capacity(buflen))),
+            sidechain_bus: Arc::new(Mutex::new(Vec::with_capacity(buflen))),
+            command_queue: Arc::new(SegQueue::new()),
+            ramps: ParameterRamps::new(),
+        }
+    }
+
+    pub fn add_channel(&mut self, name: &str, source: Box<dyn Source>) {
+        self.push_channel(name, source, Some(0.0));
+    }
+
+    /// Adds a channel without a send, e.g. the effects fed by `send_bus`.
+    /// Returns are mixed after every other channel.
+    pub fn add_return(&mut self, name: &str, source: Box<dyn Source>) {
+        self.push_channel(name, source, None);
+    }
+
+    pub fn send_bus(&self) -> Bus {
+        Bus { buffer: self.send_bus.clone() }
+    }
+
+    /// Plays the channel chosen to key the sidechain, silence if none is.
+    /// It must be rendered after the mixer.
+    pub fn sidechain_bus(&self) -> Bus {
+        Bus { buffer: self.sidechain_bus.clone() }
+    }
+
+    pub fn controller(&self) -> MixerController {
+        MixerController {
+            channels: self.channels.iter().map(|channel| channel.state.clone()).collect(),
+            command_queue: self.command_queue.clone(),
+            ramps: self.ramps.controller(),
         }
     }
+
+    fn push_channel(&mut self, name: &str, source: Box<dyn Source>, send: Option<f64>) {
+        self.channels.push(Channel {
+            source,
+            state: ChannelState { name: name.to_string(), gain: 1.0, pan: 0.0, send, sidechain: false },
+        });
+    }
+}
+
+impl MixerController {
+    pub fn channels(&self) -> &[ChannelState] {
+        &self.channels
+    }
+
+    pub fn set(&mut self, channel: usize, parameter: ChannelParameter, value: f64) {
+        if let Some(state) = self.channels.get_mut(channel) {
+            let (min, max) = parameter.range();
+            let value = value.max(min).min(max);
+            state.set(parameter, value);
+            self.command_queue.push(MixerCommand::Set(channel, parameter, value));
+        }
+    }
+
+    /// Picks the channel keying the sidechain, or none.
+    pub fn set_sidechain(&mut self, channel: Option<usize>) {
+        for (i, state) in self.channels.iter_mut().enumerate() {
+            state.sidechain = Some(i) == channel;
+        }
+        self.command_queue.push(MixerCommand::SetSidechain(channel));
+    }
+
+    /// Schedules a ramp to a value, from 0 to 1 across the parameter's
+    /// range, returning whether the UI's copy changed.
+    pub fn automate(&mut self, sample: usize, channel: usize, parameter: ChannelParameter, value: f64) -> bool {
+        match self.channels.get_mut(channel) {
+            Some(state) if state.get(parameter).is_some() => {
+                let (min, max) = parameter.range();
+                let value = min + (max - min) * value.max(0.0).min(1.0);
+                self.ramps.schedule(sample, (channel, parameter), value, true);
+
+                let changed = state.get(parameter) != Some(value);
+                state.set(parameter, value);
+                changed
+            }
+            _ => false,
+        }
+    }
+
+    pub fn clear_automation(&self) {
+        self.ramps.clear();
+    }
 }
 
 impl Source for Mixer {
     fn output_audio(&mut self, mut sample: usize, output: &mut [f64]) {
-        for output_chunk in output.chunks_mut(self.buffer.len()) {
+        while let Some(command) = self.command_queue.pop() {
+            match command {
+                MixerCommand::Set(channel, parameter, value) => {
+                    if let Some(channel) = self.channels.get_mut(channel) {
+                        channel.state.set(parameter, value);
+                    }
+                }
+                MixerCommand::SetSidechain(sidechain) => {
+                    for (i, channel) in self.channels.iter_mut().enumerate() {
+                        channel.state.sidechain = Some(i) == sidechain;
+                    }
+                }
+            }
+        }
+        self.ramps.receive();
+
+        let first_sample = sample;
+        if let Ok(mut sidechain_bus) = self.sidechain_bus.try_lock() {
+            let length = output.len().min(sidechain_bus.capacity());
+            sidechain_bus.clear();
+            sidechain_bus.resize(length, Default::default());
+        }
+
+        let chunk_length = match self.ramps.is_idle() {
+            true => self.buffer.len(),
+            false => RAMP_BLOCK * CHANNELS,
+        };
+
+        for output_chunk in output.chunks_mut(chunk_length) {
+            let channels = &mut self.channels;
+            self.ramps.apply_at(sample, |(channel, parameter), value| {
+                if let Some(channel) = channels.get_mut(channel) {
+                    channel.state.set(parameter, value);
+                }
+            });
+
             output_chunk.fill(Default::default());
+            if let Ok(mut send_bus) = self.send_bus.try_lock() {
+                send_bus.clear();
+                send_bus.resize(output_chunk.len(), Default::default());
+            }
+
+            // channels feeding the send bus go first, so returns hear all of it
+            for returns in [false, true].iter() {
+                for channel in self.channels.iter_mut().filter(|channel| channel.state.send.is_none() == *returns) {
+                    let buffer_chunk = &mut self.buffer[0..output_chunk.len()];
+                    channel.source.output_audio(sample, buffer_chunk);
 
-            for source in self.sources.iter_mut() {
-                let buffer_chunk = &mut self.buffer[0..output_chunk.len()];
-                source.output_audio(sample, buffer_chunk);
+                    if channel.state.sidechain {
+                        if let Ok(mut sidechain_bus) = self.sidechain_bus.try_lock() {
+                            // at the chunk's place in the callback
+                            let offset = (sample - first_sample) * CHANNELS;
+                            if let Some(key) = sidechain_bus.get_mut(offset..) {
+                                let length = key.len().min(buffer_chunk.len());
+                                key[..length].copy_from_slice(&buffer_chunk[..length]);
+                            }
+                        }
+                    }
 
-                output_chunk
-                    .iter_mut()
-                    .zip(buffer_chunk)
-                    .for_each(|(a, b)| *a += *b);
+                    let state = &channel.state;
+                    let left = state.gain * (1.0 - state.pan).min(1.0);
+                    let right = state.gain * (1.0 + state.pan).min(1.0);
+
+                    for (output_frame, frame) in output_chunk.chunks_mut(CHANNELS).zip(buffer_chunk.chunks_mut(CHANNELS)) {
+                        frame[0] *= left;
+                        frame[1] *= right;
+                        output_frame[0] += frame[0];
+                        output_frame[1] += frame[1];
+                    }
+
+                    if let Some(send) = state.send {
+                        if let Ok(mut send_bus) = self.send_bus.try_lock() {
+                            send_bus.iter_mut()
+                                .zip(buffer_chunk.iter())
+                                .for_each(|(a, b)| *a += *b * send);
+                        }
+                    }
+                }
             }
-                
-            // TODO: hardcoded channel count
-            sample += output_chunk.len() / 2;
+
+            sample += output_chunk.len() / CHANNELS;
+        }
+    }
+}
+
+impl Source for Bus {
+    fn output_audio(&mut self, _sample: usize, data: &mut [f64]) {
+        data.fill(Default::default());
+
+        // filled by the mixer earlier on the same thread, so this never waits
+        if let Ok(buffer) = self.buffer.try_lock() {
+            let length = data.len().min(buffer.len());
+            data[..length].copy_from_slice(&buffer[..length]);
         }
     }
 }
@@ -1,44 +1,112 @@
+mod analyzer;
 mod audio_emitter;
 mod controller;
 mod effect;
+mod latency;
+mod metronome;
+mod midi;
+mod midi_input;
+mod midi_output;
 mod mixer;
+mod parameter;
 mod player;
+mod rack;
+mod recorder;
 mod redoxsynth;
 mod source;
 
 use std::{
+    path::PathBuf,
     sync::{
         Arc, Mutex,
     },
     thread,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
-use iced::futures::channel::mpsc::{Receiver, Sender, channel};
+use crossbeam::channel::{self, Receiver, Sender};
+use iced::futures::{channel::mpsc, executor, SinkExt};
 use source::FxSource;
 
-use crate::sequence::{Sequence, Pitch};
+use crate::automation::Automation;
+use crate::sequence::{Sequence, Pitch, Note};
+use crate::widgets::tick_grid::TickGrid;
 
 use self::{
-    audio_emitter::AudioEmitter, effect::Delay, player::Player, redoxsynth::RedoxSynthGenerator,
+    analyzer::{Analyzer, ClipTap}, audio_emitter::AudioEmitter, effect::{ConvolutionReverb, ImpulseResponse}, latency::LatencyMeter, metronome::MetronomeGenerator, midi::MidiMessage,
+    midi_input::MidiInputHandler, midi_output::MidiOutputGenerator, mixer::{Mixer, MixerController}, parameter::ParameterEvent, player::Player,
+    rack::{Rack, Racks}, recorder::Recorder, redoxsynth::RedoxSynthGenerator,
 };
 
+pub use self::analyzer::{MeterReport, SILENCE_DB};
+pub use self::effect::{EffectId, EffectKind, EffectState, ParameterDescriptor, SavedEffect};
+pub use self::latency::LatencyReport;
+pub use self::mixer::{ChannelParameter, ChannelState};
+pub use self::parameter::ParameterId;
+pub use self::rack::RackId;
+pub use self::redoxsynth::{ChorusSettings, ChorusWaveform, REVERB_PRESETS, ReverbSettings, SynthEffects};
+pub use self::midi::MidiPort;
+pub use self::player::MetronomeSettings;
+
 #[derive(Debug, Clone)]
 pub enum SynthCommand {
     SetNotes(Arc<Mutex<Sequence>>),
+    SetAutomation(Arc<Mutex<Automation>>),
     Play,
     Pause,
     Stop,
     Seek(i32),
     SetLoop(Option<(i32, i32)>),
+    SetChase(bool),
+    /// takes effect the next time playback starts
+    SetLookahead(Duration),
     StartPreview(Pitch),
     StopPreview,
+    OpenMidiInput(MidiPort),
+    SetRecording(bool),
+    OpenMidiOutput(MidiPort),
+    SetOutput(SynthOutput),
+    /// in semitones, sent to the external synth as an RPN
+    SetMidiBendRange(f32),
+    SetTickGrid(Arc<dyn TickGrid>),
+    SetMetronome(MetronomeSettings),
+    InsertEffect { rack: RackId, index: usize, kind: EffectKind },
+    RemoveEffect { rack: RackId, index: usize },
+    MoveEffect { rack: RackId, from: usize, to: usize },
+    /// replaces a rack's effects with ones saved in a project
+    SetRack { rack: RackId, effects: Vec<SavedEffect> },
+    SetEffectParameter { rack: RackId, effect: usize, parameter: usize, value: f64 },
+    /// a WAV file for a reverb
+    LoadImpulseResponse { rack: RackId, effect: usize, path: PathBuf },
+    SetChannel { channel: usize, parameter: ChannelParameter, value: f64 },
+    /// the mixer channel keying the master effects' sidechain, if any
+    SetSidechain(Option<usize>),
+    /// the internal synth's reverb and chorus
+    SetSynthEffects(SynthEffects),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SynthOutput {
+    Internal,
+    Midi,
 }
 
 #[derive(Debug, Clone)]
 pub enum Status {
     CommandChannel(Sender<SynthCommand>),
     PlaybackStateUpdated(PlaybackState),
+    SchedulingLatency(LatencyReport),
+    MidiInputPorts(Vec<MidiPort>),
+    MidiOutputPorts(Vec<MidiPort>),
+    NoteRecorded(Note),
+    EffectRack(RackId, Vec<EffectState>),
+    MixerChannels(Vec<ChannelState>),
+    /// levels and spectrum of the output, about 30 times a second
+    Meters(MeterReport),
+    /// a key pressed or let go on the MIDI input
+    InputNote { key: u8, on: bool },
+    /// something asked of the engine that couldn't be done
+    Error(String),
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -46,7 +114,10 @@ pub struct PlaybackState {
     pub playback_cursor: i32,
     pub playback_start_cursor: i32,
     pub playing: bool,
-    pub looping: Option<(i32,i32)>
+    pub looping: Option<(i32,i32)>,
+    pub chase: bool,
+    pub recording: bool,
+    pub output: SynthOutput,
 }
 
 impl PlaybackState {
@@ -56,93 +127,347 @@ impl PlaybackState {
             playback_start_cursor: 0,
             playing: false,
             looping: None,
+            chase: false,
+            recording: false,
+            output: SynthOutput::Internal,
         }
     }
 }
 
+const MIXER_BUFFER_LENGTH: usize = 8192;
+const SAMPLES_PER_TICK: usize = 200;
+const DEFAULT_MIDI_BEND_RANGE: f32 = 2.0;
+/// how often the UI is told of values automation has moved
+const AUTOMATION_ECHO_INTERVAL: Duration = Duration::from_millis(100);
+
 pub struct Synth {
     recv: Receiver<SynthCommand>,
-    send: Sender<Status>,
+    send: mpsc::Sender<Status>,
+    lookahead: Duration,
+    /// automation has moved the mixer since the UI was last told
+    echo_mixer: bool,
+    /// racks automation has moved since the UI was last told
+    echo_racks: Vec<RackId>,
+    last_echo: Instant,
 }
 
 impl Synth {
-    pub fn create() -> (Receiver<Status>, Synth) {
-        let (cmd_tx, cmd_rx) = channel(64);
-        let (mut status_tx, status_rx) = channel(64);
-        status_tx.try_send(Status::CommandChannel(cmd_tx));
-        (status_rx, Synth { recv: cmd_rx, send: status_tx })
+    pub fn create(lookahead: Duration) -> (mpsc::Receiver<Status>, Synth) {
+        let (cmd_tx, cmd_rx) = channel::bounded(64);
+        let (mut status_tx, status_rx) = mpsc::channel(64);
+        let _ = status_tx.try_send(Status::CommandChannel(cmd_tx));
+        (status_rx, Synth {
+            recv: cmd_rx,
+            send: status_tx,
+            lookahead,
+            echo_mixer: false,
+            echo_racks: vec![],
+            last_echo: Instant::now(),
+        })
     }
 
     pub fn run(mut self) {
-        let notes;
-
-        loop {
-            match self.recv.try_next() {
-                Ok(Some(SynthCommand::SetNotes(n))) => {
-                    notes = n;
-                    break;
-                }
-                _ => {},
+        let notes = loop {
+            match self.recv.recv() {
+                Ok(SynthCommand::SetNotes(n)) => break n,
+                Ok(_) => {},
+                Err(_) => return,
             }
-        }
+        };
 
         let mut sample_pos = 0;
         let mut playback_state = PlaybackState::new();
         let mut last_playback_state = playback_state.clone();
         let mut emitter = AudioEmitter::new();
         let config = emitter.get_config();
+        let sample_rate = config.sample_rate.0 as usize;
         let (controller, source) = RedoxSynthGenerator::new(config.sample_rate.0 as f32, "gm.sf2")
             .expect("redoxsynth init to succeed");
-        let mut player = Player::new(200, notes.clone(), Box::new(controller), 4800);
-        let delay = Delay::new(10000, 0.0, 1.0, 0.75);
-        let fxsource = FxSource::new(Box::new(source), vec![Box::new(delay)]);
-        let mut samples_receiver = emitter.start(Box::new(fxsource));
+        let (midi_output, midi_output_source) = MidiOutputGenerator::new(
+            DEFAULT_MIDI_BEND_RANGE,
+            sample_rate as f64,
+            emitter.output_latency(),
+        );
+        let (metronome, metronome_source) = MetronomeGenerator::new(sample_rate as f64, MetronomeSettings::default().volume as f64);
+        let mut player = Player::new(
+            SAMPLES_PER_TICK,
+            notes.clone(),
+            Box::new(controller.clone()),
+            metronome,
+            duration_to_samples(self.lookahead, sample_rate),
+        );
+        let mut mixer = Mixer::new(MIXER_BUFFER_LENGTH);
+        mixer.add_channel("Synth", Box::new(source));
+        mixer.add_channel("MIDI out", Box::new(midi_output_source));
+        mixer.add_channel("Metronome", Box::new(metronome_source));
+        let sidechain_bus = mixer.sidechain_bus();
+        let send_fxsource = FxSource::new(Box::new(mixer.send_bus()), vec![]);
+        let send_rack = Rack::new(send_fxsource.controller(), sample_rate as f64);
+        mixer.add_return("Send", Box::new(send_fxsource));
+        let mut mixer_controller = mixer.controller();
+        let (mixer, clipped_before_master) = ClipTap::new(Box::new(mixer));
+        let fxsource = FxSource::with_sidechain(Box::new(mixer), vec![], Box::new(sidechain_bus), MIXER_BUFFER_LENGTH);
+        let mut racks = Racks {
+            master: Rack::new(fxsource.controller(), sample_rate as f64),
+            send: send_rack,
+        };
+        // keeps the summed synth voices from clipping in the emitter
+        // the racks start empty, so there's room for these
+        let _ = racks.master.insert(0, EffectKind::Limiter);
+        let _ = racks.send.insert_as_send(0, EffectKind::Reverb);
+        let (fxsource, mut analyzer) = Analyzer::new(Box::new(fxsource), clipped_before_master, sample_rate as f64);
+        let samples_receiver = emitter.start(Box::new(fxsource));
+        let mut latency = LatencyMeter::new();
+        let mut last_callback = Instant::now();
+
+        // impulse responses are loaded on their own thread and come back ready to swap in
+        let (reverb_sender, reverb_receiver) = channel::unbounded();
+
+        let (midi_sender, midi_receiver) = channel::bounded(256);
+        let mut midi_input = MidiInputHandler::new(midi_sender);
+        let mut recorder = Recorder::new();
+        let _ = self.send.try_send(Status::MidiInputPorts(midi_input::available_ports()));
+        let _ = self.send.try_send(Status::MidiOutputPorts(midi_output::available_ports()));
+        self.send_rack(RackId::Master, &racks.master);
+        self.send_rack(RackId::Send, &racks.send);
+        let _ = self.send.try_send(Status::MixerChannels(mixer_controller.channels().to_vec()));
 
         let mut start_cursor = 0;
 
         loop {
-            while let Ok(Some(command)) = self.recv.try_next() {
-                match command {
-                    SynthCommand::Play => {
+            channel::select! {
+                recv(self.recv) -> command => match command {
+                    Ok(SynthCommand::Play) => {
                         player.play(sample_pos);
                         playback_state.playing = true;
                     },
-                    SynthCommand::Stop => {
+                    Ok(SynthCommand::Stop) => {
+                        self.send_recorded(recorder.flush(player.get_position()));
                         player.pause();
                         player.seek(sample_pos, start_cursor);
                         playback_state.playing = false;
                     },
-                    SynthCommand::Pause => {
+                    Ok(SynthCommand::Pause) => {
+                        self.send_recorded(recorder.flush(player.get_position()));
                         player.pause();
                         playback_state.playing = false;
                     },
-                    SynthCommand::Seek(seek_pos) => {
+                    Ok(SynthCommand::Seek(seek_pos)) => {
                         player.seek(sample_pos, seek_pos);
                         start_cursor = seek_pos;
                         playback_state.playback_start_cursor = seek_pos;
                     },
-                    SynthCommand::StartPreview(pitch) => player.play_preview(pitch),
-                    SynthCommand::StopPreview => player.stop_preview(),
-                    SynthCommand::SetNotes(_) => panic!("SetNotes after init"),
-                    SynthCommand::SetLoop(looping) => {
+                    Ok(SynthCommand::StartPreview(pitch)) => player.play_preview(pitch),
+                    Ok(SynthCommand::StopPreview) => player.stop_preview(),
+                    Ok(SynthCommand::SetNotes(_)) => panic!("SetNotes after init"),
+                    Ok(SynthCommand::SetLoop(looping)) => {
                         player.set_loop(looping);
                         playback_state.looping = looping;
                     }
-                }
+                    Ok(SynthCommand::SetChase(chase)) => {
+                        player.set_chase(chase);
+                        playback_state.chase = chase;
+                    }
+                    Ok(SynthCommand::SetLookahead(lookahead)) => {
+                        self.lookahead = lookahead;
+                        player.set_lookahead(duration_to_samples(lookahead, sample_rate));
+                    }
+                    Ok(SynthCommand::OpenMidiInput(port)) => {
+                        if let Err(err) = midi_input.connect(&port) {
+                            let _ = self.send.try_send(Status::Error(err));
+                        }
+                    }
+                    Ok(SynthCommand::SetRecording(recording)) => {
+                        if !recording {
+                            self.send_recorded(recorder.flush(player.get_position()));
+                        }
+                        playback_state.recording = recording;
+                    }
+                    Ok(SynthCommand::OpenMidiOutput(port)) => {
+                        if let Err(err) = midi_output.connect(&port) {
+                            let _ = self.send.try_send(Status::Error(err));
+                        }
+                    }
+                    Ok(SynthCommand::SetOutput(output)) => {
+                        match output {
+                            SynthOutput::Internal => player.set_controller(sample_pos, Box::new(controller.clone())),
+                            SynthOutput::Midi => player.set_controller(sample_pos, Box::new(midi_output.clone())),
+                        }
+                        playback_state.output = output;
+                    }
+                    Ok(SynthCommand::SetMidiBendRange(bend_range)) => midi_output.set_bend_range(bend_range),
+                    Ok(SynthCommand::SetTickGrid(tick_grid)) => {
+                        let beat_length = (tick_grid.beat_length() as usize * SAMPLES_PER_TICK) as f64;
+                        racks.master.set_beat_length(beat_length);
+                        racks.send.set_beat_length(beat_length);
+                        player.set_tick_grid(tick_grid);
+                    }
+                    Ok(SynthCommand::SetMetronome(settings)) => player.set_metronome(settings),
+                    Ok(SynthCommand::SetAutomation(automation)) => player.set_automation(automation),
+                    Ok(SynthCommand::InsertEffect { rack, index, kind }) => {
+                        if let Err(err) = racks.get_mut(rack).insert(index, kind) {
+                            let _ = self.send.try_send(Status::Error(err));
+                        }
+                        self.send_rack(rack, racks.get_mut(rack));
+                    }
+                    Ok(SynthCommand::RemoveEffect { rack, index }) => {
+                        racks.get_mut(rack).remove(index);
+                        self.send_rack(rack, racks.get_mut(rack));
+                    }
+                    Ok(SynthCommand::MoveEffect { rack, from, to }) => {
+                        racks.get_mut(rack).move_effect(from, to);
+                        self.send_rack(rack, racks.get_mut(rack));
+                    }
+                    Ok(SynthCommand::SetRack { rack, effects }) => {
+                        if let Err(err) = racks.get_mut(rack).restore(&effects) {
+                            let _ = self.send.try_send(Status::Error(err));
+                        }
+                        self.send_rack(rack, racks.get_mut(rack));
+                    }
+                    Ok(SynthCommand::SetEffectParameter { rack, effect, parameter, value }) => {
+                        racks.get_mut(rack).set_parameter(effect, parameter, value);
+                        self.send_rack(rack, racks.get_mut(rack));
+                    }
+                    Ok(SynthCommand::LoadImpulseResponse { rack, effect, path }) => {
+                        let state = racks.get_mut(rack).effects().get(effect);
+                        if let Some(id) = state.filter(|state| state.kind == EffectKind::Reverb).map(|state| state.id) {
+                            let reverb_sender = reverb_sender.clone();
+                            thread::spawn(move || {
+                                let reverb = ImpulseResponse::load(&path, sample_rate as f64)
+                                    .map(ConvolutionReverb::with_impulse_response);
+                                let _ = reverb_sender.send((rack, id, reverb));
+                            });
+                        }
+                    }
+                    Ok(SynthCommand::SetSynthEffects(effects)) => controller.set_effects(effects),
+                    Ok(SynthCommand::SetChannel { channel, parameter, value }) => {
+                        mixer_controller.set(channel, parameter, value);
+                        let _ = self.send.try_send(Status::MixerChannels(mixer_controller.channels().to_vec()));
+                    }
+                    Ok(SynthCommand::SetSidechain(channel)) => {
+                        mixer_controller.set_sidechain(channel);
+                        let _ = self.send.try_send(Status::MixerChannels(mixer_controller.channels().to_vec()));
+                    }
+                    Err(_) => return,
+                },
+                recv(samples_receiver) -> samples => match samples {
+                    Ok((samples, callback_time)) => {
+                        player.process(samples);
+                        sample_pos += samples;
+                        latency.record(callback_time);
+                        racks.collect_garbage();
+                        analyzer.process();
+                        last_callback = callback_time;
+                    }
+                    Err(_) => return,
+                },
+                recv(reverb_receiver) -> loaded => if let Ok((rack, id, reverb)) = loaded {
+                    match reverb {
+                        // the reverb may have been removed while it loaded
+                        Ok(reverb) => if let Some(index) = racks.get_mut(rack).index_of(id) {
+                            racks.get_mut(rack).replace(index, Box::new(reverb));
+                            self.send_rack(rack, racks.get_mut(rack));
+                        },
+                        Err(err) => {
+                            let _ = self.send.try_send(Status::Error(err));
+                        }
+                    }
+                },
+                recv(midi_receiver) -> message => if let Ok((message, arrival)) = message {
+                    player.monitor(message);
+                    let input_note = match message {
+                        MidiMessage::NoteOn { key, .. } => Some(Status::InputNote { key, on: true }),
+                        MidiMessage::NoteOff { key, .. } => Some(Status::InputNote { key, on: false }),
+                        _ => None,
+                    };
+                    if let Some(status) = input_note {
+                        let _ = self.send.try_send(status);
+                    }
+
+                    if playback_state.recording && player.is_playing() {
+                        let since_callback = arrival.saturating_duration_since(last_callback);
+                        let tick = player.position_after(duration_to_samples(since_callback, sample_rate));
+
+                        match message {
+                            MidiMessage::NoteOn { key, .. } => recorder.note_on(key, tick),
+                            MidiMessage::NoteOff { key, .. } => {
+                                self.send_recorded(recorder.note_off(key, tick).into_iter().collect());
+                            }
+                            _ => {}
+                        }
+                    }
+                },
             }
 
+            self.automate(player.take_parameter_events(), &mut mixer_controller, &mut racks);
+            self.echo_automation(&mixer_controller, &mut racks);
+
             playback_state.playback_cursor = player.get_position();
             if playback_state != last_playback_state {
                 last_playback_state = playback_state.clone();
-                self.send.try_send(Status::PlaybackStateUpdated(playback_state.clone()));
+                let _ = self.send.try_send(Status::PlaybackStateUpdated(playback_state.clone()));
             }
 
-            while let Ok(Some(samples)) = samples_receiver.try_next() {
-                player.process(samples);
-                sample_pos += samples;
+            if let Some(report) = latency.report(self.lookahead) {
+                let _ = self.send.try_send(Status::SchedulingLatency(report));
             }
 
-            thread::sleep(Duration::from_millis(10));
+            if let Some(report) = analyzer.report() {
+                let _ = self.send.try_send(Status::Meters(report));
+            }
         }
     }
+
+    fn send_rack(&mut self, id: RackId, rack: &Rack) {
+        let _ = self.send.try_send(Status::EffectRack(id, rack.effects().to_vec()));
+    }
+
+    /// Hands the player's automation to the mixer and racks, noting which
+    /// the UI needs telling of.
+    fn automate(&mut self, events: Vec<ParameterEvent>, mixer_controller: &mut MixerController, racks: &mut Racks) {
+        for event in events {
+            match event {
+                ParameterEvent::Clear => {
+                    mixer_controller.clear_automation();
+                    racks.clear_automation();
+                }
+                ParameterEvent::Set { sample, id: ParameterId::Channel { channel, parameter }, value } => {
+                    self.echo_mixer |= mixer_controller.automate(sample, channel, parameter, value);
+                }
+                ParameterEvent::Set { sample, id: ParameterId::Effect { rack, effect, parameter }, value } => {
+                    if racks.get_mut(rack).automate(sample, effect, parameter, value) && !self.echo_racks.contains(&rack) {
+                        self.echo_racks.push(rack);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Tells the UI of the values automation has moved, at most every
+    /// `AUTOMATION_ECHO_INTERVAL` rather than with each one scheduled.
+    fn echo_automation(&mut self, mixer_controller: &MixerController, racks: &mut Racks) {
+        if (!self.echo_mixer && self.echo_racks.is_empty()) || self.last_echo.elapsed() < AUTOMATION_ECHO_INTERVAL {
+            return;
+        }
+        self.last_echo = Instant::now();
+
+        if std::mem::take(&mut self.echo_mixer) {
+            let _ = self.send.try_send(Status::MixerChannels(mixer_controller.channels().to_vec()));
+        }
+        for rack in std::mem::take(&mut self.echo_racks) {
+            self.send_rack(rack, racks.get_mut(rack));
+        }
+    }
+
+    /// Waits for room in the status channel, which a meter frame doesn't,
+    /// so that no note played is lost when the UI falls behind.
+    fn send_recorded(&mut self, notes: Vec<Note>) {
+        for note in notes {
+            let _ = executor::block_on(self.send.send(Status::NoteRecorded(note)));
+        }
+    }
+}
+
+fn duration_to_samples(duration: Duration, sample_rate: usize) -> usize {
+    (duration.as_secs_f64() * sample_rate as f64) as usize
 }
@@ -0,0 +1,165 @@
+use std::fmt::{self, Display};
+use std::sync::Arc;
+
+use crossbeam::queue::SegQueue;
+use serde::{Deserialize, Serialize};
+
+use super::effect::EffectId;
+use super::mixer::ChannelParameter;
+use super::rack::RackId;
+
+/// automated sources are split into blocks of this many frames, the values
+/// being updated at the start of each
+pub const RAMP_BLOCK: usize = 32;
+/// room for scheduled values without reallocating on the audio thread
+const MAX_PENDING: usize = 4096;
+
+/// Names an app-side parameter in a way that survives effects being
+/// inserted, moved or removed around it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ParameterId {
+    Channel { channel: usize, parameter: ChannelParameter },
+    Effect { rack: RackId, effect: EffectId, parameter: usize },
+}
+
+impl Display for ParameterId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParameterId::Channel { channel, parameter } => write!(f, "Channel {} {:?}", channel + 1, parameter),
+            ParameterId::Effect { rack, effect, parameter } => {
+                write!(f, "{:?} effect {} parameter {}", rack, effect.0, parameter + 1)
+            }
+        }
+    }
+}
+
+/// What the player asks of automated parameters.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ParameterEvent {
+    /// forget everything scheduled, e.g. when playback stops
+    Clear,
+    /// reach a value at a sample, from 0 to 1 across the parameter's range
+    Set { sample: usize, id: ParameterId, value: f64 },
+}
+
+enum RampCommand<K> {
+    Target(Target<K>),
+    Clear,
+    ClearKey(K),
+}
+
+#[derive(Clone, Copy)]
+struct Target<K> {
+    sample: usize,
+    key: K,
+    value: f64,
+    /// stepped parameters jump at the sample instead
+    ramp: bool,
+}
+
+/// Values scheduled for a source's parameters, played on the audio thread.
+/// Each is ramped to in a straight line from the one before it for the same
+/// parameter.
+pub struct ParameterRamps<K> {
+    queue: Arc<SegQueue<RampCommand<K>>>,
+    /// in order of sample for each parameter
+    pending: Vec<Target<K>>,
+    /// the last value reached for each parameter, and when
+    reached: Vec<(K, usize, f64)>,
+}
+
+/// Schedules values for a `ParameterRamps` from outside the audio thread.
+pub struct RampController<K> {
+    queue: Arc<SegQueue<RampCommand<K>>>,
+}
+
+impl<K> Clone for RampController<K> {
+    fn clone(&self) -> Self {
+        Self { queue: self.queue.clone() }
+    }
+}
+
+impl<K: Copy + PartialEq> ParameterRamps<K> {
+    pub fn new() -> Self {
+        Self {
+            queue: Arc::new(SegQueue::new()),
+            pending: Vec::with_capacity(MAX_PENDING),
+            reached: Vec::with_capacity(MAX_PENDING),
+        }
+    }
+
+    pub fn controller(&self) -> RampController<K> {
+        RampController { queue: self.queue.clone() }
+    }
+
+    /// Takes in what was scheduled since the last call.
+    pub fn receive(&mut self) {
+        while let Some(command) = self.queue.pop() {
+            match command {
+                RampCommand::Target(target) => {
+                    if self.pending.len() < MAX_PENDING {
+                        self.pending.push(target);
+                    }
+                }
+                RampCommand::Clear => {
+                    self.pending.clear();
+                    self.reached.clear();
+                }
+                RampCommand::ClearKey(key) => {
+                    self.pending.retain(|target| target.key != key);
+                    self.reached.retain(|(reached, _, _)| *reached != key);
+                }
+            }
+        }
+    }
+
+    /// whether nothing is left to play, so sources needn't split their blocks
+    pub fn is_idle(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Calls `apply` with the value of each parameter that is moving at
+    /// `sample`, passing any values that have been reached.
+    pub fn apply_at(&mut self, sample: usize, mut apply: impl FnMut(K, f64)) {
+        let mut i = 0;
+        while i < self.pending.len() {
+            let target = self.pending[i];
+            if target.sample > sample {
+                i += 1;
+                continue;
+            }
+
+            self.pending.remove(i);
+            let reached = (target.key, target.sample, target.value);
+            match self.reached.iter().position(|(key, _, _)| *key == target.key) {
+                Some(index) => self.reached[index] = reached,
+                None if self.reached.len() < MAX_PENDING => self.reached.push(reached),
+                None => {}
+            }
+            apply(target.key, target.value);
+        }
+
+        for &(key, from_sample, from_value) in &self.reached {
+            if let Some(target) = self.pending.iter().find(|target| target.key == key) {
+                if target.ramp {
+                    let position = (sample - from_sample) as f64 / (target.sample - from_sample) as f64;
+                    apply(key, from_value + (target.value - from_value) * position);
+                }
+            }
+        }
+    }
+}
+
+impl<K> RampController<K> {
+    pub fn schedule(&self, sample: usize, key: K, value: f64, ramp: bool) {
+        self.queue.push(RampCommand::Target(Target { sample, key, value, ramp }));
+    }
+
+    pub fn clear(&self) {
+        self.queue.push(RampCommand::Clear);
+    }
+
+    pub fn clear_key(&self, key: K) {
+        self.queue.push(RampCommand::ClearKey(key));
+    }
+}
@@ -1,8 +1,25 @@
+use std::cmp::min;
 use std::sync::{Arc, Mutex};
 
-use crate::sequence::{Pitch, Sequence};
+use crate::automation::{Automation, AutomationLane, AutomationTarget};
+use crate::sequence::{Note, NoteId, Pitch, Sequence};
+use crate::widgets::tick_grid::{LineType, TickGrid};
 
-use super::controller::{Controller, Event, EventData};
+use super::controller::{Controller, Event, EventData, DEFAULT_VELOCITY, GLIDE_CHANNELS};
+use super::metronome::MetronomeController;
+use super::midi::{MidiMessage, key_to_pitch};
+use super::parameter::{ParameterEvent, ParameterId};
+
+const SEQUENCE_CHANNEL: u32 = 0;
+const PREVIEW_CHANNEL: u32 = 1;
+const MONITOR_CHANNEL: u32 = 2;
+/// ticks between the bends and expression sent for a note's envelopes
+const GLIDE_INTERVAL: usize = 2;
+/// the timbre a note gets without an envelope, as CC74 leaves it
+const DEFAULT_TIMBRE: u8 = 64;
+const TIMBRE_CONTROLLER: u8 = 74;
+/// ticks between the values sent for an automation lane
+const AUTOMATION_INTERVAL: i32 = 4;
 
 pub struct Player {
     notes: Arc<Mutex<Sequence>>,
@@ -15,8 +32,69 @@ pub struct Player {
     cursor: usize,
     playing: bool,
     preview: Option<Pitch>,
-    buffer_size: usize,
+    lookahead: usize,
     playing_frame: usize,
+    chase: bool,
+    metronome: MetronomeController,
+    metronome_settings: MetronomeSettings,
+    tick_grid: Option<Arc<dyn TickGrid>>,
+    count_in_remaining: usize,
+    /// the sample each glide channel's last note ends at
+    glide_channel_ends: [usize; GLIDE_CHANNELS.len()],
+    /// sounding notes whose envelopes are still being sent
+    enveloped_notes: Vec<EnvelopedNote>,
+    automation: Option<Arc<Mutex<Automation>>>,
+    /// the last value sent per target, so only changes are sent
+    automation_values: Vec<(AutomationTarget, u32)>,
+    /// the last value scheduled per automated parameter, and the latest
+    /// sample it was held at without being scheduled again
+    parameter_values: Vec<(ParameterId, f64, Option<usize>)>,
+    /// for the engine to hand to the mixer and racks
+    parameter_events: Vec<ParameterEvent>,
+}
+
+/// A note with a pitch envelope or expression, playing on a glide channel.
+/// Its bends, timbre and pressure are sent as the lookahead reaches them.
+struct EnvelopedNote {
+    id: NoteId,
+    channel: u32,
+    /// as it was played, which its events must keep to
+    pitch: Pitch,
+    start_frame: usize,
+    end_frame: usize,
+    /// the frame to send values for next
+    next_frame: usize,
+    /// `start_sample` and `start_cursor` when it was played, so it keeps
+    /// time across a loop's wrap
+    start_sample: usize,
+    start_cursor: usize,
+    last_timbre: Option<u8>,
+    last_pressure: Option<u8>,
+}
+
+impl EnvelopedNote {
+    fn sample(&self, frame: usize) -> usize {
+        self.start_sample + frame - self.start_cursor
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MetronomeSettings {
+    pub enabled: bool,
+    pub volume: f32,
+    /// bars of clicks played before the cursor starts moving, whether or
+    /// not the metronome is on
+    pub count_in_bars: u32,
+}
+
+impl Default for MetronomeSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            volume: 0.5,
+            count_in_bars: 0,
+        }
+    }
 }
 
 impl Player {
@@ -24,7 +102,8 @@ impl Player {
         samples_per_tick: usize,
         notes: Arc<Mutex<Sequence>>,
         controller: Box<dyn Controller>,
-        buffer_size: usize,
+        metronome: MetronomeController,
+        lookahead: usize,
     ) -> Self {
         Self {
             notes,
@@ -37,33 +116,129 @@ impl Player {
             cursor: 0,
             playing: false,
             preview: None,
-            buffer_size,
+            lookahead,
             playing_frame: 0,
+            chase: false,
+            metronome,
+            metronome_settings: MetronomeSettings::default(),
+            tick_grid: None,
+            count_in_remaining: 0,
+            glide_channel_ends: [0; GLIDE_CHANNELS.len()],
+            enveloped_notes: vec![],
+            automation: None,
+            automation_values: vec![],
+            parameter_values: vec![],
+            parameter_events: vec![],
         }
     }
 
+    /// Routes events to a different output, rescheduling from the current
+    /// position if playing.
+    pub fn set_controller(&mut self, start_sample: usize, controller: Box<dyn Controller>) {
+        let playing = self.playing;
+
+        self.pause();
+        self.controller = controller;
+
+        if playing {
+            self.start(start_sample, false);
+        }
+    }
+
+    pub fn set_tick_grid(&mut self, tick_grid: Arc<dyn TickGrid>) {
+        self.tick_grid = Some(tick_grid);
+    }
+
+    pub fn set_automation(&mut self, automation: Arc<Mutex<Automation>>) {
+        self.automation = Some(automation);
+    }
+
+    /// Takes what automated parameters should do since the last call.
+    pub fn take_parameter_events(&mut self) -> Vec<ParameterEvent> {
+        std::mem::take(&mut self.parameter_events)
+    }
+
+    pub fn set_metronome(&mut self, settings: MetronomeSettings) {
+        self.metronome.set_volume(settings.volume as f64);
+        if !settings.enabled {
+            self.metronome.clear();
+        }
+        self.metronome_settings = settings;
+    }
+
+    pub fn set_lookahead(&mut self, lookahead: usize) {
+        self.lookahead = lookahead;
+    }
+
     pub fn set_loop(&mut self, looping: Option<(i32, i32)>) {
         self.looping = looping.map(|x| (x.0 as usize * self.samples_per_tick, x.1 as usize * self.samples_per_tick));
     }
 
+    /// when chasing, notes that are already sounding at the start position
+    /// (or at the loop start after wrapping) are triggered as well
+    pub fn set_chase(&mut self, chase: bool) {
+        self.chase = chase;
+    }
+
     pub fn seek(&mut self, start_sample: usize, cursor: i32) {
         let new_playing_frame = cursor as usize * self.samples_per_tick;
         if self.playing_frame != new_playing_frame {
             self.playing_frame = new_playing_frame;
             if self.playing {
                 self.playing = false;
-                self.play(start_sample);
+                self.count_in_remaining = 0;
+                self.metronome.clear();
+                self.start(start_sample, false);
             }
         }
     }
 
     pub fn play(&mut self, start_sample: usize) {
+        self.start(start_sample, true);
+    }
+
+    fn start(&mut self, start_sample: usize, count_in: bool) {
         if !self.playing {
             self.start_sample = start_sample;
+            if count_in {
+                self.count_in_remaining = self.count_in(start_sample);
+                self.start_sample += self.count_in_remaining;
+            }
             self.cursor = self.playing_frame;
             self.playing = true;
             self.start_cursor = self.cursor;
-            self.scan_events(self.buffer_size);
+            self.chase_notes(self.cursor);
+            // controllers always chase, or they'd keep whatever they were left at
+            self.automation_values.clear();
+            self.parameter_values.clear();
+            self.parameter_events.push(ParameterEvent::Clear);
+            self.scan_events(self.lookahead);
+        }
+    }
+
+    /// schedules the count-in clicks, returning its length in samples
+    fn count_in(&mut self, start_sample: usize) -> usize {
+        let tick_grid = match &self.tick_grid {
+            Some(tick_grid) => tick_grid.clone(),
+            None => return 0,
+        };
+
+        let length = tick_grid.bar_length() * self.metronome_settings.count_in_bars as i32;
+
+        for line in tick_grid.get_grid_lines(0, length) {
+            if line.tick < length {
+                self.click(start_sample + line.tick as usize * self.samples_per_tick, line.line_type);
+            }
+        }
+
+        length as usize * self.samples_per_tick
+    }
+
+    fn click(&mut self, sample: usize, line_type: LineType) {
+        match line_type {
+            LineType::Bar(_) => self.metronome.click(sample, true),
+            LineType::Beat => self.metronome.click(sample, false),
+            LineType::InBetween => {},
         }
     }
 
@@ -71,8 +246,35 @@ impl Player {
         return self.playing_frame as i32 / self.samples_per_tick as i32;
     }
 
+    /// the tick that will be playing once another `samples` have been
+    /// processed, used to place events that arrive between callbacks
+    pub fn position_after(&self, samples: usize) -> i32 {
+        let mut frame = self.playing_frame;
+
+        if self.playing && samples > self.count_in_remaining {
+            frame += samples - self.count_in_remaining;
+
+            if let Some((loop_start_sample, loop_end_sample)) = self.looping {
+                if self.playing_frame < loop_end_sample && frame >= loop_end_sample {
+                    frame -= loop_end_sample - loop_start_sample;
+                }
+            }
+        }
+
+        frame as i32 / self.samples_per_tick as i32
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
     pub fn pause(&mut self) {
         self.playing = false;
+        self.count_in_remaining = 0;
+        self.metronome.clear();
+        self.parameter_events.push(ParameterEvent::Clear);
+        self.enveloped_notes.clear();
+        self.glide_channel_ends = [0; GLIDE_CHANNELS.len()];
 
         self.controller.send_event(Event {
             sample: 0,
@@ -82,11 +284,17 @@ impl Player {
         self.sequence += 1;
     }
 
-    pub fn process(&mut self, samples: usize) {
+    pub fn process(&mut self, mut samples: usize) {
         if !self.playing {
             return;
         }
 
+        if self.count_in_remaining > 0 {
+            let count_in = min(samples, self.count_in_remaining);
+            self.count_in_remaining -= count_in;
+            samples -= count_in;
+        }
+
         self.advance_playing_frame(samples);
         self.scan_events(samples);
     }
@@ -96,14 +304,14 @@ impl Player {
             self.controller.send_event(Event {
                 sample: 0,
                 sequence: self.sequence,
-                data: EventData::NoteOff(1, old_pitch.clone()),
+                data: EventData::NoteOff(PREVIEW_CHANNEL, old_pitch.clone()),
             });
             self.sequence += 1;
         }
         self.controller.send_event(Event {
             sample: 0,
             sequence: self.sequence,
-            data: EventData::NoteOn(1, pitch.clone()),
+            data: EventData::NoteOn(PREVIEW_CHANNEL, pitch.clone(), DEFAULT_VELOCITY),
         });
         self.sequence += 1;
 
@@ -115,7 +323,7 @@ impl Player {
             self.controller.send_event(Event {
                 sample: 0,
                 sequence: self.sequence,
-                data: EventData::NoteOff(1, old_pitch.clone()),
+                data: EventData::NoteOff(PREVIEW_CHANNEL, old_pitch.clone()),
             });
             self.sequence += 1;
         }
@@ -123,6 +331,24 @@ impl Player {
         self.preview = None;
     }
 
+    /// plays incoming MIDI straight away on its own channel
+    pub fn monitor(&mut self, message: MidiMessage) {
+        let data = match message {
+            MidiMessage::NoteOn { key, velocity, .. } => EventData::NoteOn(MONITOR_CHANNEL, key_to_pitch(key), velocity),
+            MidiMessage::NoteOff { key, .. } => EventData::NoteOff(MONITOR_CHANNEL, key_to_pitch(key)),
+            MidiMessage::PitchBend { value, .. } => EventData::PitchBend(MONITOR_CHANNEL, value as u32),
+            MidiMessage::ControlChange { controller, value, .. } => EventData::ControlChange(MONITOR_CHANNEL, controller, value),
+            MidiMessage::ChannelPressure { value, .. } => EventData::ChannelPressure(MONITOR_CHANNEL, value),
+        };
+
+        self.controller.send_event(Event {
+            sample: 0,
+            sequence: self.sequence,
+            data,
+        });
+        self.sequence += 1;
+    }
+
     fn advance_playing_frame(&mut self, length: usize) {
         let playing_frame_end = self.playing_frame + length;
 
@@ -146,6 +372,7 @@ impl Player {
                 length -= loop_end_sample - self.cursor;
                 self.start_sample += loop_end_sample - loop_start_sample;
                 self.cursor = loop_start_sample;
+                self.chase_notes(self.cursor);
 
                 // length may be zero at the exact end sample of a loop, in which case
                 // it is pointless to scan notes again
@@ -159,28 +386,353 @@ impl Player {
         self.cursor += length;
     }
 
-    fn scan_event_range(&mut self, range_start: usize, range_end: usize) {
-        let notes = self.notes.lock().unwrap();
+    fn chase_notes(&mut self, position: usize) {
+        if !self.chase {
+            return;
+        }
+
+        // notes starting on the tick itself are picked up by scan_event_range
+        let tick = (position / self.samples_per_tick) as i32;
+
+        let notes = self.notes.lock().unwrap().get_notes_overlapping(tick);
+        for (id, note) in notes {
+            self.schedule_note(id, &note, position);
+        }
+    }
 
+    fn scan_event_range(&mut self, range_start: usize, range_end: usize) {
         let start_tick = (range_start / self.samples_per_tick) as i32;
         let end_tick = (range_end / self.samples_per_tick) as i32;
 
-        for (_, note) in notes.get_notes_in_range(start_tick, end_tick) {
-            let start_sample = note.tick as usize * self.samples_per_tick;
-            let end_sample = start_sample + note.length as usize * self.samples_per_tick;
+        let notes = self.notes.lock().unwrap().get_notes_in_range(start_tick, end_tick);
+        for (id, note) in notes {
+            self.schedule_note(id, &note, note.tick as usize * self.samples_per_tick);
+        }
 
-            self.controller.send_event(Event {
-                sample: self.start_sample + start_sample - self.start_cursor,
-                sequence: self.sequence,
-                data: EventData::NoteOn(0, note.pitch.clone()),
-            });
-            self.sequence += 1;
-            self.controller.send_event(Event {
-                sample: self.start_sample + end_sample - self.start_cursor,
-                sequence: self.sequence,
-                data: EventData::NoteOff(0, note.pitch.clone()),
-            });
-            self.sequence += 1;
+        self.scan_envelopes(range_end);
+        self.scan_clicks(start_tick, end_tick);
+        self.scan_automation(range_start, range_end);
+    }
+
+    /// sends the automation every `AUTOMATION_INTERVAL` ticks in a range of
+    /// frames, and at its start if that's the first frame played
+    fn scan_automation(&mut self, range_start: usize, range_end: usize) {
+        let automation = match &self.automation {
+            Some(automation) => automation.clone(),
+            None => return,
+        };
+        let automation = automation.lock().unwrap();
+
+        if range_start == self.start_cursor {
+            self.send_automation(automation.lanes(), range_start as f32 / self.samples_per_tick as f32, range_start);
+        }
+
+        let interval = AUTOMATION_INTERVAL as usize * self.samples_per_tick;
+        let first_frame = (range_start + interval - 1) / interval * interval;
+        for frame in (first_frame..range_end).step_by(interval) {
+            self.send_automation(automation.lanes(), (frame / self.samples_per_tick) as f32, frame);
         }
     }
+
+    /// sends each lane's value at a tick, where it has changed
+    fn send_automation(&mut self, lanes: &[AutomationLane], tick: f32, frame: usize) {
+        for lane in lanes {
+            let value = match lane.value_at(tick) {
+                Some(value) => value.max(0.0).min(1.0),
+                None => continue,
+            };
+
+            let value = match lane.target {
+                AutomationTarget::Parameter(id) => {
+                    let sample = self.start_sample + frame - self.start_cursor;
+                    self.schedule_parameter(id, value as f64, sample);
+                    continue;
+                }
+                AutomationTarget::PitchBend => (value * 16383.0).round() as u32,
+                _ => (value * 127.0).round() as u32,
+            };
+
+            match self.automation_values.iter_mut().find(|(target, _)| *target == lane.target) {
+                Some((_, last)) if *last == value => continue,
+                Some((_, last)) => *last = value,
+                None => self.automation_values.push((lane.target, value)),
+            }
+
+            // the glide channels bend by themselves
+            let glide_channels = match lane.target {
+                AutomationTarget::PitchBend => &[][..],
+                _ => &GLIDE_CHANNELS[..],
+            };
+
+            for &channel in [SEQUENCE_CHANNEL].iter().chain(glide_channels) {
+                let data = match lane.target {
+                    AutomationTarget::ControlChange(controller) => EventData::ControlChange(channel, controller, value as u8),
+                    AutomationTarget::ChannelPressure => EventData::ChannelPressure(channel, value as u8),
+                    _ => EventData::PitchBend(channel, value),
+                };
+                self.send_at(frame, data);
+            }
+        }
+    }
+
+    /// Schedules an automated parameter's value where it has changed. Each
+    /// value is ramped to from the last, so when a parameter starts moving
+    /// after holding still, the held value goes first to start the ramp
+    /// where the lane does.
+    fn schedule_parameter(&mut self, id: ParameterId, value: f64, sample: usize) {
+        match self.parameter_values.iter_mut().find(|(last_id, _, _)| *last_id == id) {
+            Some((_, last, held)) if *last == value => *held = Some(sample),
+            Some((_, last, held)) => {
+                if let Some(held) = held.take() {
+                    self.parameter_events.push(ParameterEvent::Set { sample: held, id, value: *last });
+                }
+                *last = value;
+                self.parameter_events.push(ParameterEvent::Set { sample, id, value });
+            }
+            None => {
+                self.parameter_values.push((id, value, None));
+                self.parameter_events.push(ParameterEvent::Set { sample, id, value });
+            }
+        }
+    }
+
+    /// Plays a note from `from_frame`, which is later than its start when
+    /// chasing. Notes with a pitch envelope or expression get a glide
+    /// channel to themselves, and their envelopes are sent from there on by
+    /// `scan_envelopes`.
+    fn schedule_note(&mut self, id: NoteId, note: &Note, from_frame: usize) {
+        let mut end_frame = note.end_tick() as usize * self.samples_per_tick;
+        // cut off where the loop wraps, as it's chased from the start again
+        if let Some((_loop_start, loop_end)) = self.looping {
+            if from_frame < loop_end {
+                end_frame = min(end_frame, loop_end);
+            }
+        }
+
+        if note.pitch_envelope.is_empty() && note.expression.is_empty() {
+            self.send_at(from_frame, EventData::NoteOn(SEQUENCE_CHANNEL, note.pitch.clone(), DEFAULT_VELOCITY));
+            self.send_at(end_frame, EventData::NoteOff(SEQUENCE_CHANNEL, note.pitch.clone()));
+            return;
+        }
+
+        let channel = self.glide_channel(from_frame, end_frame);
+
+        let mut envelope = EnvelopedNote {
+            id,
+            channel,
+            pitch: note.pitch.clone(),
+            start_frame: note.tick as usize * self.samples_per_tick,
+            end_frame,
+            next_frame: from_frame,
+            start_sample: self.start_sample,
+            start_cursor: self.start_cursor,
+            last_timbre: None,
+            last_pressure: None,
+        };
+        self.send_envelopes(&mut envelope, note, true);
+        self.send_at(end_frame, EventData::NoteOff(channel, note.pitch.clone()));
+
+        if envelope.next_frame < envelope.end_frame {
+            self.enveloped_notes.push(envelope);
+        }
+    }
+
+    /// Picks the glide channel whose last note ended longest ago. A channel
+    /// is only taken from a note still sounding when all of them are.
+    fn glide_channel(&mut self, from_frame: usize, end_frame: usize) -> u32 {
+        let (i, _end) = self.glide_channel_ends.iter()
+            .enumerate()
+            .min_by_key(|(_i, end)| **end)
+            .unwrap();
+
+        // a stolen channel's old note is cut off where the new one starts
+        if self.glide_channel_ends[i] > self.start_sample + from_frame - self.start_cursor {
+            let channel = GLIDE_CHANNELS[i];
+            if let Some(stolen) = self.enveloped_notes.iter().position(|envelope| envelope.channel == channel) {
+                let stolen = self.enveloped_notes.swap_remove(stolen);
+                self.send_at(from_frame, EventData::NoteOff(channel, stolen.pitch));
+            }
+        }
+
+        self.glide_channel_ends[i] = self.start_sample + end_frame.max(from_frame) - self.start_cursor;
+        GLIDE_CHANNELS[i]
+    }
+
+    /// Sends the envelopes of the notes still sounding, up to where
+    /// `range_end` falls. They're read as they are now, so edits made
+    /// during playback are heard.
+    fn scan_envelopes(&mut self, range_end: usize) {
+        let until_sample = self.start_sample + range_end - self.start_cursor;
+        let sequence = self.notes.clone();
+        let notes = sequence.lock().unwrap();
+        let mut envelopes = std::mem::take(&mut self.enveloped_notes);
+
+        for envelope in envelopes.iter_mut() {
+            let note = match notes.get(envelope.id) {
+                Some(note) => note,
+                // removed, so its note off is all that's left
+                None => {
+                    envelope.next_frame = envelope.end_frame;
+                    continue;
+                }
+            };
+
+            while envelope.next_frame < envelope.end_frame && envelope.sample(envelope.next_frame) < until_sample {
+                self.send_envelopes(envelope, note, false);
+            }
+        }
+
+        envelopes.retain(|envelope| envelope.next_frame < envelope.end_frame);
+        self.enveloped_notes = envelopes;
+    }
+
+    /// Sends a note's timbre and pressure where they've changed, and its
+    /// bend, at its next frame. The first timbre and pressure go before the
+    /// note on, as MPE synths expect, and both are reset on the channel for
+    /// notes without any, so nothing is left over from its last note.
+    fn send_envelopes(&mut self, envelope: &mut EnvelopedNote, note: &Note, first: bool) {
+        let frame = envelope.next_frame;
+        let tick = frame.saturating_sub(envelope.start_frame) as f32 / self.samples_per_tick as f32;
+
+        let timbre = match to_midi(note.expression.timbre.value_at(tick)) {
+            None if first => Some(DEFAULT_TIMBRE),
+            timbre => timbre,
+        };
+        if let Some(value) = timbre.filter(|_| timbre != envelope.last_timbre) {
+            self.send_enveloped(envelope, EventData::ControlChange(envelope.channel, TIMBRE_CONTROLLER, value));
+            envelope.last_timbre = timbre;
+        }
+
+        let pressure = match to_midi(note.expression.pressure.value_at(tick)) {
+            None if first => Some(0),
+            pressure => pressure,
+        };
+        if let Some(value) = pressure.filter(|_| pressure != envelope.last_pressure) {
+            self.send_enveloped(envelope, EventData::NotePressure(envelope.channel, envelope.pitch.clone(), value));
+            envelope.last_pressure = pressure;
+        }
+
+        // the note on sets its own bend, so glides go after it. It's struck
+        // as hard as it's pressed where it starts, as velocity 0 is a note off.
+        if first {
+            let velocity = to_midi(note.expression.pressure.value_at(0.0)).unwrap_or(DEFAULT_VELOCITY).max(1);
+            self.send_enveloped(envelope, EventData::NoteOn(envelope.channel, envelope.pitch.clone(), velocity));
+        }
+
+        if !note.pitch_envelope.is_empty() {
+            let offset = note.pitch_envelope.offset_at(tick);
+            self.send_enveloped(envelope, EventData::Glide(envelope.channel, envelope.pitch.clone(), offset));
+        }
+
+        envelope.next_frame += GLIDE_INTERVAL * self.samples_per_tick;
+    }
+
+    /// sends an event for an enveloped note's next frame
+    fn send_enveloped(&mut self, envelope: &EnvelopedNote, data: EventData) {
+        self.controller.send_event(Event {
+            sample: envelope.sample(envelope.next_frame),
+            sequence: self.sequence,
+            data,
+        });
+        self.sequence += 1;
+    }
+
+    /// sends an event for a frame of the sequence
+    fn send_at(&mut self, frame: usize, data: EventData) {
+        self.controller.send_event(Event {
+            sample: self.start_sample + frame - self.start_cursor,
+            sequence: self.sequence,
+            data,
+        });
+        self.sequence += 1;
+    }
+
+    fn scan_clicks(&mut self, start_tick: i32, end_tick: i32) {
+        let tick_grid = match &self.tick_grid {
+            Some(tick_grid) if self.metronome_settings.enabled => tick_grid.clone(),
+            _ => return,
+        };
+
+        for line in tick_grid.get_grid_lines(start_tick, end_tick) {
+            if line.tick >= start_tick && line.tick < end_tick {
+                let sample = line.tick as usize * self.samples_per_tick;
+                self.click(self.start_sample + sample - self.start_cursor, line.line_type);
+            }
+        }
+    }
+}
+
+/// an envelope's value from 0 to 1 as a MIDI value
+fn to_midi(value: Option<f32>) -> Option<u8> {
+    value.map(|value| (value.max(0.0).min(1.0) * 127.0).round() as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::metronome::{MetronomeGenerator, MetronomeSource};
+    use crate::audio::source::Source;
+    use crate::sequence::SequenceChange;
+    use crate::widgets::tick_grid::SimpleGrid;
+
+    const SAMPLES_PER_TICK: usize = 10;
+    const LOOKAHEAD: usize = 4800;
+
+    struct NoOutput;
+
+    impl Controller for NoOutput {
+        fn send_event(&self, _event: Event) {}
+    }
+
+    /// the sequence channel's note ons and offs, by sample
+    #[derive(Clone, Default)]
+    struct NoteEvents(Arc<Mutex<Vec<(usize, bool)>>>);
+
+    impl Controller for NoteEvents {
+        fn send_event(&self, event: Event) {
+            match event.data {
+                EventData::NoteOn(SEQUENCE_CHANNEL, _, _) => self.0.lock().unwrap().push((event.sample, true)),
+                EventData::NoteOff(SEQUENCE_CHANNEL, _) => self.0.lock().unwrap().push((event.sample, false)),
+                _ => {}
+            }
+        }
+    }
+
+    fn player(notes: Sequence, controller: Box<dyn Controller>) -> (Player, MetronomeSource) {
+        let (metronome, source) = MetronomeGenerator::new(48000.0, 1.0);
+        let mut player = Player::new(SAMPLES_PER_TICK, Arc::new(Mutex::new(notes)), controller, metronome, LOOKAHEAD);
+        player.set_tick_grid(Arc::new(SimpleGrid { ticks_per_16th: 24 }));
+        (player, source)
+    }
+
+    #[test]
+    fn count_in_clicks_with_the_metronome_off() {
+        let (mut player, mut metronome) = player(Sequence::new(), Box::new(NoOutput));
+        player.set_metronome(MetronomeSettings { enabled: false, volume: 1.0, count_in_bars: 1 });
+        player.play(0);
+        assert_eq!(player.count_in_remaining, 384 * SAMPLES_PER_TICK);
+
+        let mut output = vec![0.0; 2 * 64];
+        metronome.output_audio(0, &mut output);
+        assert!(output.iter().any(|sample| *sample != 0.0));
+    }
+
+    #[test]
+    fn chased_note_stops_where_the_loop_wraps() {
+        let mut notes = Sequence::new();
+        notes.update_sequence(SequenceChange::Add(Note::new(0, Pitch::new(0, 1), 1920)));
+        let events = NoteEvents::default();
+        let (mut player, _metronome) = player(notes, Box::new(events.clone()));
+        player.set_chase(true);
+        player.set_loop(Some((0, 960)));
+        player.seek(0, 480);
+
+        // starts halfway through the loop, wrapping as the lookahead ends
+        player.play(0);
+        player.process(LOOKAHEAD);
+
+        let mut events = events.0.lock().unwrap().clone();
+        events.sort_by_key(|(sample, _on)| *sample);
+        assert_eq!(events, vec![(0, true), (4800, false), (4800, true), (14400, false)]);
+    }
 }
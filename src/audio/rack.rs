@@ -0,0 +1,210 @@
+use serde::{Deserialize, Serialize};
+
+use super::{
+    effect::{Effect, EffectId, EffectKind, EffectState, SavedEffect},
+    source::{FxController, MAX_EFFECTS},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RackId {
+    Master,
+    /// the effects on the mixer's send bus
+    Send,
+}
+
+/// Keeps the engine's copy of an effect chain in step with the `FxSource`
+/// it controls, so the UI can be told what the chain looks like.
+pub struct Rack {
+    controller: FxController,
+    effects: Vec<EffectState>,
+    sample_rate: f64,
+    beat_length: Option<f64>,
+    next_id: u32,
+}
+
+impl Rack {
+    pub fn new(controller: FxController, sample_rate: f64) -> Self {
+        Self { controller, effects: vec![], sample_rate, beat_length: None, next_id: 0 }
+    }
+
+    pub fn effects(&self) -> &[EffectState] {
+        &self.effects
+    }
+
+    pub fn index_of(&self, id: EffectId) -> Option<usize> {
+        self.effects.iter().position(|effect| effect.id == id)
+    }
+
+    /// Adds an effect, unless the chain is already as long as the audio
+    /// thread has room for.
+    pub fn insert(&mut self, index: usize, kind: EffectKind) -> Result<(), String> {
+        let effect = self.create(kind);
+        self.insert_effect(index, EffectId(self.next_id), kind, effect)
+    }
+
+    /// builds an effect, following the rack's tempo
+    fn create(&self, kind: EffectKind) -> Box<dyn Effect> {
+        let mut effect = kind.create(self.sample_rate);
+        if let Some(beat_length) = self.beat_length {
+            effect.set_beat_length(beat_length);
+        }
+        effect
+    }
+
+    fn insert_effect(&mut self, index: usize, id: EffectId, kind: EffectKind, effect: Box<dyn Effect>) -> Result<(), String> {
+        if self.effects.len() >= MAX_EFFECTS {
+            return Err(format!("a rack can hold at most {} effects", MAX_EFFECTS));
+        }
+
+        let index = index.min(self.effects.len());
+        self.next_id = self.next_id.max(id.0 + 1);
+
+        self.effects.insert(index, EffectState::new(id, kind, &*effect));
+        self.controller.insert_effect(index, id, effect);
+        Ok(())
+    }
+
+    /// Replaces the whole chain with effects saved in a project, keeping
+    /// their ids. The new chain is built before the old one is taken out, so
+    /// the rack is never left half restored. Effects past what a rack can
+    /// hold are left out.
+    pub fn restore(&mut self, effects: &[SavedEffect]) -> Result<(), String> {
+        let chain: Vec<_> = effects
+            .iter()
+            .take(MAX_EFFECTS)
+            .map(|saved| {
+                let mut effect = self.create(saved.kind);
+                for (parameter, (&value, descriptor)) in saved.values.iter().zip(effect.parameters()).enumerate() {
+                    effect.set_parameter(parameter, descriptor.clamp(value));
+                }
+                (saved.id, saved.kind, effect)
+            })
+            .collect();
+
+        while !self.effects.is_empty() {
+            self.remove(self.effects.len() - 1);
+        }
+        for (index, (id, kind, effect)) in chain.into_iter().enumerate() {
+            self.insert_effect(index, id, kind, effect)?;
+        }
+
+        if effects.len() > MAX_EFFECTS {
+            return Err(format!("a rack can hold at most {} effects", MAX_EFFECTS));
+        }
+        Ok(())
+    }
+
+    /// Inserts an effect that will only hear the send bus, so nothing of the
+    /// dry signal should pass through.
+    pub fn insert_as_send(&mut self, index: usize, kind: EffectKind) -> Result<(), String> {
+        let mut effect = self.create(kind);
+        if let Some((wet, dry)) = effect.mix_parameters() {
+            let parameters = effect.parameters();
+            effect.set_parameter(wet, parameters[wet].max);
+            effect.set_parameter(dry, parameters[dry].min);
+        }
+        self.insert_effect(index, EffectId(self.next_id), kind, effect)
+    }
+
+    /// Swaps in a new instance of the effect at `index`, keeping its
+    /// parameter values.
+    pub fn replace(&mut self, index: usize, mut effect: Box<dyn Effect>) {
+        if let Some(state) = self.effects.get(index) {
+            for (parameter, &value) in state.values.iter().enumerate() {
+                effect.set_parameter(parameter, value);
+            }
+            if let Some(beat_length) = self.beat_length {
+                effect.set_beat_length(beat_length);
+            }
+            self.controller.replace_effect(index, effect);
+        }
+    }
+
+    pub fn remove(&mut self, index: usize) {
+        if index < self.effects.len() {
+            let effect = self.effects.remove(index);
+            for parameter in 0..effect.parameters.len() {
+                self.controller.clear_parameter_automation(effect.id, parameter);
+            }
+            self.controller.remove_effect(index);
+        }
+    }
+
+    pub fn move_effect(&mut self, from: usize, to: usize) {
+        if from < self.effects.len() && to < self.effects.len() {
+            let effect = self.effects.remove(from);
+            self.effects.insert(to, effect);
+            self.controller.move_effect(from, to);
+        }
+    }
+
+    pub fn set_parameter(&mut self, index: usize, parameter: usize, value: f64) {
+        if let Some(effect) = self.effects.get_mut(index) {
+            if let Some(descriptor) = effect.parameters.get(parameter) {
+                let value = descriptor.clamp(value);
+                effect.values[parameter] = value;
+                self.controller.set_parameter(index, parameter, value);
+            }
+        }
+    }
+
+    /// Schedules a ramp to a value, from 0 to 1 across the parameter's
+    /// range, returning whether the UI's copy changed. Stepped parameters
+    /// jump instead.
+    pub fn automate(&mut self, sample: usize, id: EffectId, parameter: usize, value: f64) -> bool {
+        let index = match self.index_of(id) {
+            Some(index) => index,
+            None => return false,
+        };
+        let effect = &mut self.effects[index];
+
+        match effect.parameters.get(parameter) {
+            Some(descriptor) => {
+                let value = descriptor.clamp(descriptor.min + (descriptor.max - descriptor.min) * value);
+                self.controller.automate_parameter(sample, id, parameter, value, descriptor.labels.is_empty());
+
+                let changed = effect.values[parameter] != value;
+                effect.values[parameter] = value;
+                changed
+            }
+            None => false,
+        }
+    }
+
+    pub fn clear_automation(&self) {
+        self.controller.clear_automation();
+    }
+
+    pub fn set_beat_length(&mut self, beat_length: f64) {
+        self.beat_length = Some(beat_length);
+        self.controller.set_beat_length(beat_length);
+    }
+
+    pub fn collect_garbage(&self) {
+        self.controller.collect_garbage();
+    }
+}
+
+pub struct Racks {
+    pub master: Rack,
+    pub send: Rack,
+}
+
+impl Racks {
+    pub fn get_mut(&mut self, id: RackId) -> &mut Rack {
+        match id {
+            RackId::Master => &mut self.master,
+            RackId::Send => &mut self.send,
+        }
+    }
+
+    pub fn collect_garbage(&self) {
+        self.master.collect_garbage();
+        self.send.collect_garbage();
+    }
+
+    pub fn clear_automation(&self) {
+        self.master.clear_automation();
+        self.send.clear_automation();
+    }
+}
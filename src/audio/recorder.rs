@@ -0,0 +1,40 @@
+use crate::sequence::Note;
+
+use super::midi::key_to_pitch;
+
+/// Turns incoming note on/off pairs into notes on the player's tick clock.
+pub struct Recorder {
+    held: Vec<(u8, i32)>,
+}
+
+impl Recorder {
+    pub fn new() -> Self {
+        Self { held: vec![] }
+    }
+
+    pub fn note_on(&mut self, key: u8, tick: i32) {
+        if !self.held.iter().any(|(held_key, _)| *held_key == key) {
+            self.held.push((key, tick));
+        }
+    }
+
+    pub fn note_off(&mut self, key: u8, tick: i32) -> Option<Note> {
+        let idx = self.held.iter().position(|(held_key, _)| *held_key == key)?;
+        let (_, start_tick) = self.held.remove(idx);
+
+        Some(Self::note(key, start_tick, tick))
+    }
+
+    /// ends every held note, used when playback stops mid-note
+    pub fn flush(&mut self, tick: i32) -> Vec<Note> {
+        self.held.drain(..)
+            .map(|(key, start_tick)| Self::note(key, start_tick, tick))
+            .collect()
+    }
+
+    fn note(key: u8, start_tick: i32, end_tick: i32) -> Note {
+        // a loop wrap while the key was held leaves the end before the start
+        let length = if end_tick > start_tick { end_tick - start_tick } else { 1 };
+        Note::new(start_tick, key_to_pitch(key), length)
+    }
+}
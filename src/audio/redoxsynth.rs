@@ -1,21 +1,102 @@
-use std::{fmt::Display, path::Path, sync::Arc};
+use std::{fmt::{self, Display}, path::Path, sync::Arc};
 
 use crossbeam::queue::SegQueue;
+use serde::{Deserialize, Serialize};
 
 use super::{
-    controller::{Controller, Event, EventData},
+    controller::{ChannelBends, Controller, Event, EventData, GLIDE_BEND_RANGE, GLIDE_CHANNELS, insert_event},
     source::Source,
 };
 
+/// in semitones, what the synth starts with
+const DEFAULT_BEND_RANGE: f32 = 2.0;
+/// room for the events scheduled ahead without growing on the audio thread
+const EVENT_BUFFER_LENGTH: usize = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ReverbSettings {
+    pub enabled: bool,
+    pub room_size: f64,
+    pub damping: f64,
+    pub width: f64,
+    pub level: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ChorusWaveform {
+    Sine,
+    Triangle,
+}
+
+impl fmt::Display for ChorusWaveform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChorusWaveform::Sine => write!(f, "Sine"),
+            ChorusWaveform::Triangle => write!(f, "Triangle"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ChorusSettings {
+    pub enabled: bool,
+    pub voices: u32,
+    pub level: f64,
+    /// in Hz
+    pub speed: f64,
+    /// in ms
+    pub depth: f64,
+    pub waveform: ChorusWaveform,
+}
+
+/// The synth's built-in reverb and chorus.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct SynthEffects {
+    pub reverb: ReverbSettings,
+    pub chorus: ChorusSettings,
+}
+
+/// The reverb models from FluidSynth's preset table.
+pub const REVERB_PRESETS: [(&str, ReverbSettings); 5] = [
+    ("Small room", ReverbSettings { enabled: true, room_size: 0.2, damping: 0.0, width: 0.5, level: 0.9 }),
+    ("Medium room", ReverbSettings { enabled: true, room_size: 0.4, damping: 0.2, width: 0.5, level: 0.8 }),
+    ("Large room", ReverbSettings { enabled: true, room_size: 0.6, damping: 0.4, width: 0.5, level: 0.7 }),
+    ("Hall", ReverbSettings { enabled: true, room_size: 0.8, damping: 0.7, width: 0.5, level: 0.6 }),
+    ("Large hall", ReverbSettings { enabled: true, room_size: 0.8, damping: 1.0, width: 0.5, level: 0.5 }),
+];
+
+impl Default for ReverbSettings {
+    fn default() -> Self {
+        REVERB_PRESETS[0].1
+    }
+}
+
+impl Default for ChorusSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            voices: 3,
+            level: 2.0,
+            speed: 0.3,
+            depth: 8.0,
+            waveform: ChorusWaveform::Sine,
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct RedoxSynthController {
     event_queue: Arc<SegQueue<Event>>,
+    effects_queue: Arc<SegQueue<SynthEffects>>,
 }
 
 pub struct RedoxSynthSource {
     synth: redoxsynth::Synth,
     events: Vec<Event>,
     event_queue: Arc<SegQueue<Event>>,
+    effects_queue: Arc<SegQueue<SynthEffects>>,
     playing_notes: Vec<(u32, u32)>,
+    bends: ChannelBends,
 }
 
 pub struct RedoxSynthGenerator {}
@@ -35,18 +116,26 @@ impl RedoxSynthGenerator {
         synth
             .sfload(soundfont_filename, true)
             .map_err(redoxsynth_error)?;
+        for &channel in GLIDE_CHANNELS.iter() {
+            synth.pitch_wheel_sens(channel, GLIDE_BEND_RANGE as u32).map_err(redoxsynth_error)?;
+        }
 
         let event_queue = Arc::new(SegQueue::new());
+        let effects_queue = Arc::new(SegQueue::new());
         Ok((
-            RedoxSynthController::new(event_queue.clone()),
-            RedoxSynthSource::new(synth, event_queue.clone()),
+            RedoxSynthController::new(event_queue.clone(), effects_queue.clone()),
+            RedoxSynthSource::new(synth, event_queue.clone(), effects_queue),
         ))
     }
 }
 
 impl RedoxSynthController {
-    fn new(event_queue: Arc<SegQueue<Event>>) -> Self {
-        Self { event_queue }
+    fn new(event_queue: Arc<SegQueue<Event>>, effects_queue: Arc<SegQueue<SynthEffects>>) -> Self {
+        Self { event_queue, effects_queue }
+    }
+
+    pub fn set_effects(&self, effects: SynthEffects) {
+        self.effects_queue.push(effects);
     }
 }
 
@@ -57,28 +146,41 @@ impl Controller for RedoxSynthController {
 }
 
 impl RedoxSynthSource {
-    fn new(synth: redoxsynth::Synth, event_queue: Arc<SegQueue<Event>>) -> Self {
+    fn new(
+        synth: redoxsynth::Synth,
+        event_queue: Arc<SegQueue<Event>>,
+        effects_queue: Arc<SegQueue<SynthEffects>>,
+    ) -> Self {
         Self {
             synth,
-            events: Vec::new(),
+            events: Vec::with_capacity(EVENT_BUFFER_LENGTH),
             event_queue,
+            effects_queue,
             playing_notes: Vec::new(),
+            bends: ChannelBends::default(),
         }
     }
 
+    fn set_effects(&mut self, effects: SynthEffects) {
+        let SynthEffects { reverb, chorus } = effects;
+
+        self.synth.set_reverb_on(reverb.enabled);
+        self.synth.set_reverb_params(reverb.room_size, reverb.damping, reverb.width, reverb.level);
+
+        let mode = match chorus.waveform {
+            ChorusWaveform::Sine => redoxsynth::ChorusMode::Sine,
+            ChorusWaveform::Triangle => redoxsynth::ChorusMode::Triangle,
+        };
+        self.synth.set_chorus_on(chorus.enabled);
+        self.synth.set_chorus_params(chorus.voices, chorus.level, chorus.speed, chorus.depth, mode);
+    }
+
     fn clear_events(&mut self) {
         self.events.clear();
     }
 
     fn insert_event(&mut self, event: Event) {
-        let pos = self
-            .events
-            .binary_search_by_key(&(event.sample, event.sequence), |e: &Event| {
-                (e.sample, e.sequence)
-            })
-            .unwrap_or_else(|e| e);
-
-        self.events.insert(pos, event);
+        insert_event(&mut self.events, event);
     }
 }
 
@@ -87,6 +189,10 @@ impl Source for RedoxSynthSource {
         // TODO: hardcoded channel count
         let length = data.len() / 2;
 
+        while let Some(effects) = self.effects_queue.pop() {
+            self.set_effects(effects);
+        }
+
         loop {
             match self.event_queue.pop() {
                 Some(event) => {
@@ -120,28 +226,47 @@ impl Source for RedoxSynthSource {
             }
 
             match &event.data {
-                EventData::NoteOn(chan, n) => {
-                    let (key, bend) = n.midi_pitch(2.0);
-                    self.synth.note_on(*chan, key, 127);
-                    self.synth.pitch_bend(*chan, bend);
+                EventData::NoteOn(chan, n, velocity) => {
+                    let (key, bend) = n.midi_pitch(bend_range(*chan));
+                    self.synth.note_on(*chan, key, (*velocity).into());
+                    self.synth.pitch_bend(*chan, self.bends.note_on(*chan, bend));
 
                     if !self.playing_notes.contains(&(*chan, key)) {
                         self.playing_notes.push((*chan, key));
                     }
                 }
                 EventData::NoteOff(chan, n) => {
-                    let (key, _bend) = n.midi_pitch(2.0);
+                    let (key, _bend) = n.midi_pitch(bend_range(*chan));
                     self.synth.note_off(*chan, key);
 
                     if let Some(i) = self.playing_notes.iter().position(|t| *t == (*chan, key)) {
                         self.playing_notes.remove(i);
                     }
                 }
+                EventData::PitchBend(chan, bend) => {
+                    self.synth.pitch_bend(*chan, self.bends.bend(*chan, *bend));
+                }
+                EventData::ControlChange(chan, controller, value) => {
+                    self.synth.cc(*chan, *controller as u32, *value as u32);
+                }
+                EventData::ChannelPressure(chan, value) => {
+                    self.synth.channel_pressure(*chan, *value as u32);
+                }
+                EventData::NotePressure(chan, n, value) => {
+                    let (key, _bend) = n.midi_pitch(bend_range(*chan));
+                    self.synth.key_pressure(*chan, key, *value as u32);
+                }
+                EventData::Glide(chan, n, offset) => {
+                    let range = bend_range(*chan);
+                    let (key, _bend) = n.midi_pitch(range);
+                    self.synth.pitch_bend(*chan, n.midi_bend_from(key, *offset, range));
+                }
                 EventData::ClearEvents => {
                     for note in &self.playing_notes {
                         self.synth.note_off(note.0, note.1);
                     }
                     self.playing_notes.clear();
+                    self.bends.clear();
                 }
             }
 
@@ -154,3 +279,12 @@ impl Source for RedoxSynthSource {
         self.synth.write(&mut data[generated_frames * 2..length * 2]).unwrap();
     }
 }
+
+/// in semitones
+fn bend_range(channel: u32) -> f32 {
+    if GLIDE_CHANNELS.contains(&channel) {
+        GLIDE_BEND_RANGE
+    } else {
+        DEFAULT_BEND_RANGE
+    }
+}
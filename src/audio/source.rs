@@ -1,25 +1,224 @@
-use super::effect::Effect;
+use std::sync::Arc;
+
+use crossbeam::queue::SegQueue;
+
+use super::effect::{Effect, EffectId};
+use super::parameter::{ParameterRamps, RAMP_BLOCK, RampController};
+
+/// room for inserts without reallocating on the audio thread, which racks
+/// keep to
+pub const MAX_EFFECTS: usize = 32;
+/// interleaved in every buffer a source renders
+pub const CHANNELS: usize = 2;
 
 pub trait Source: Send {
     fn output_audio(&mut self, sample: usize, data: &mut [f64]);
 }
 
+enum FxCommand {
+    Insert(usize, EffectId, Box<dyn Effect>),
+    Replace(usize, Box<dyn Effect>),
+    Remove(usize),
+    Move(usize, usize),
+    SetParameter(usize, usize, f64),
+    SetBeatLength(f64),
+}
+
+/// Edits an `FxSource`'s effect chain from outside the audio thread. Effects
+/// are built and dropped on the caller's side, never in the callback.
+#[derive(Clone)]
+pub struct FxController {
+    command_queue: Arc<SegQueue<FxCommand>>,
+    graveyard: Arc<SegQueue<Box<dyn Effect>>>,
+    ramps: RampController<(EffectId, usize)>,
+}
+
 pub struct FxSource {
     source: Box<dyn Source>,
-    effects: Vec<Box<dyn Effect>>,
+    effects: Vec<(EffectId, Box<dyn Effect>)>,
+    sidechain: Option<(Box<dyn Source>, Vec<f64>)>,
+    command_queue: Arc<SegQueue<FxCommand>>,
+    graveyard: Arc<SegQueue<Box<dyn Effect>>>,
+    /// keyed by effect and parameter index, so they follow effects that move
+    ramps: ParameterRamps<(EffectId, usize)>,
 }
 
 impl FxSource {
-    pub fn new(source: Box<dyn Source>, effects: Vec<Box<dyn Effect>>) -> FxSource {
-        Self{source, effects}
+    pub fn new(source: Box<dyn Source>, mut effects: Vec<(EffectId, Box<dyn Effect>)>) -> FxSource {
+        effects.reserve(MAX_EFFECTS.saturating_sub(effects.len()));
+
+        Self {
+            source,
+            effects,
+            sidechain: None,
+            command_queue: Arc::new(SegQueue::new()),
+            graveyard: Arc::new(SegQueue::new()),
+            ramps: ParameterRamps::new(),
+        }
+    }
+
+    /// Renders `sidechain` alongside the source and hands it to every effect
+    /// as a key signal. It is not heard.
+    pub fn with_sidechain(
+        source: Box<dyn Source>,
+        effects: Vec<(EffectId, Box<dyn Effect>)>,
+        sidechain: Box<dyn Source>,
+        buflen: usize,
+    ) -> FxSource {
+        Self {
+            sidechain: Some((sidechain, vec![Default::default(); buflen])),
+            ..Self::new(source, effects)
+        }
+    }
+
+    pub fn controller(&self) -> FxController {
+        FxController {
+            command_queue: self.command_queue.clone(),
+            graveyard: self.graveyard.clone(),
+            ramps: self.ramps.controller(),
+        }
+    }
+
+    fn handle_command(&mut self, command: FxCommand) {
+        match command {
+            FxCommand::Insert(index, id, effect) => {
+                if self.effects.len() < MAX_EFFECTS {
+                    let index = index.min(self.effects.len());
+                    self.effects.insert(index, (id, effect));
+                } else {
+                    self.graveyard.push(effect);
+                }
+            }
+            FxCommand::Replace(index, effect) => {
+                match self.effects.get_mut(index) {
+                    Some((_id, slot)) => self.graveyard.push(std::mem::replace(slot, effect)),
+                    None => self.graveyard.push(effect),
+                }
+            }
+            FxCommand::Remove(index) => {
+                if index < self.effects.len() {
+                    self.graveyard.push(self.effects.remove(index).1);
+                }
+            }
+            FxCommand::Move(from, to) => {
+                if from < self.effects.len() && to < self.effects.len() {
+                    let effect = self.effects.remove(from);
+                    self.effects.insert(to, effect);
+                }
+            }
+            FxCommand::SetParameter(index, parameter, value) => {
+                if let Some((_id, effect)) = self.effects.get_mut(index) {
+                    effect.set_parameter(parameter, value);
+                }
+            }
+            FxCommand::SetBeatLength(beat_length) => {
+                for (_id, effect) in self.effects.iter_mut() {
+                    effect.set_beat_length(beat_length);
+                }
+            }
+        }
+    }
+}
+
+impl FxController {
+    pub fn insert_effect(&self, index: usize, id: EffectId, effect: Box<dyn Effect>) {
+        self.command_queue.push(FxCommand::Insert(index, id, effect));
+    }
+
+    pub fn replace_effect(&self, index: usize, effect: Box<dyn Effect>) {
+        self.command_queue.push(FxCommand::Replace(index, effect));
+    }
+
+    pub fn remove_effect(&self, index: usize) {
+        self.command_queue.push(FxCommand::Remove(index));
+    }
+
+    pub fn move_effect(&self, from: usize, to: usize) {
+        self.command_queue.push(FxCommand::Move(from, to));
+    }
+
+    pub fn set_parameter(&self, index: usize, parameter: usize, value: f64) {
+        self.command_queue.push(FxCommand::SetParameter(index, parameter, value));
+    }
+
+    /// Schedules a value for a parameter at a sample, ramped to from the
+    /// last one unless `ramp` is false. Values are clamped by the caller.
+    pub fn automate_parameter(&self, sample: usize, id: EffectId, parameter: usize, value: f64, ramp: bool) {
+        self.ramps.schedule(sample, (id, parameter), value, ramp);
+    }
+
+    pub fn clear_automation(&self) {
+        self.ramps.clear();
+    }
+
+    /// Drops the values scheduled for one parameter, as when its effect is
+    /// removed.
+    pub fn clear_parameter_automation(&self, id: EffectId, parameter: usize) {
+        self.ramps.clear_key((id, parameter));
+    }
+
+    pub fn set_beat_length(&self, beat_length: f64) {
+        self.command_queue.push(FxCommand::SetBeatLength(beat_length));
+    }
+
+    /// Drops effects the audio thread has removed.
+    pub fn collect_garbage(&self) {
+        while let Some(effect) = self.graveyard.pop() {
+            drop(effect);
+        }
     }
 }
 
 impl Source for FxSource {
     fn output_audio(&mut self, sample: usize, data: &mut [f64]) {
+        while let Some(command) = self.command_queue.pop() {
+            self.handle_command(command);
+        }
+
+        self.ramps.receive();
+
         self.source.output_audio(sample, data);
-        for effect in self.effects.iter_mut() {
-            effect.process_audio(sample, data);
+
+        let sidechain = match self.sidechain.as_mut() {
+            Some((sidechain, buffer)) => {
+                let length = data.len().min(buffer.len());
+                let buffer = &mut buffer[..length];
+                sidechain.output_audio(sample, buffer);
+                Some(&*buffer)
+            }
+            None => None,
+        };
+
+        let chunk_length = match self.ramps.is_idle() {
+            true => data.len().max(1),
+            false => RAMP_BLOCK * CHANNELS,
+        };
+
+        for (i, chunk) in data.chunks_mut(chunk_length).enumerate() {
+            let offset = i * chunk_length;
+            let chunk_sample = sample + offset / CHANNELS;
+
+            let effects = &mut self.effects;
+            self.ramps.apply_at(chunk_sample, |(id, parameter), value| {
+                if let Some((_id, effect)) = effects.iter_mut().find(|(effect_id, _)| *effect_id == id) {
+                    effect.set_parameter(parameter, value);
+                }
+            });
+
+            match sidechain {
+                Some(sidechain) => {
+                    let start = offset.min(sidechain.len());
+                    let end = (offset + chunk.len()).min(sidechain.len());
+                    for (_id, effect) in self.effects.iter_mut() {
+                        effect.process_sidechain(chunk_sample, chunk, &sidechain[start..end]);
+                    }
+                }
+                None => {
+                    for (_id, effect) in self.effects.iter_mut() {
+                        effect.process_audio(chunk_sample, chunk);
+                    }
+                }
+            }
         }
     }
 }
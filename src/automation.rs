@@ -0,0 +1,254 @@
+use std::fmt::{self, Display};
+
+use serde::{Deserialize, Serialize};
+
+use crate::audio::ParameterId;
+use crate::envelope::{Envelope, EnvelopePoint};
+
+/// What an automation lane controls, either on the sequence's channels or
+/// in the app's mixer and effects.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AutomationTarget {
+    ControlChange(u8),
+    ChannelPressure,
+    PitchBend,
+    Parameter(ParameterId),
+}
+
+impl AutomationTarget {
+    /// every CC number, then pressure and bend, but no parameters
+    pub fn all() -> Vec<AutomationTarget> {
+        (0..128).map(AutomationTarget::ControlChange)
+            .chain(vec![AutomationTarget::ChannelPressure, AutomationTarget::PitchBend])
+            .collect()
+    }
+}
+
+impl Display for AutomationTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AutomationTarget::ControlChange(1) => write!(f, "CC 1 Modulation"),
+            AutomationTarget::ControlChange(7) => write!(f, "CC 7 Volume"),
+            AutomationTarget::ControlChange(10) => write!(f, "CC 10 Pan"),
+            AutomationTarget::ControlChange(11) => write!(f, "CC 11 Expression"),
+            AutomationTarget::ControlChange(64) => write!(f, "CC 64 Sustain"),
+            AutomationTarget::ControlChange(74) => write!(f, "CC 74 Brightness"),
+            AutomationTarget::ControlChange(number) => write!(f, "CC {}", number),
+            AutomationTarget::ChannelPressure => write!(f, "Channel pressure"),
+            AutomationTarget::PitchBend => write!(f, "Pitch bend"),
+            AutomationTarget::Parameter(id) => write!(f, "{}", id),
+        }
+    }
+}
+
+/// The project's automation lanes, shared with the player like the
+/// `Sequence`.
+#[derive(Debug, Clone, Default)]
+pub struct Automation {
+    lanes: Vec<AutomationLane>,
+}
+
+/// Each target has at most one lane, since two would fight over it.
+#[derive(Debug, Clone)]
+pub enum AutomationChange {
+    /// if the target already has a lane, the first without one is used
+    AddLane(AutomationTarget),
+    RemoveLane(usize),
+    /// ignored if the target already has a lane
+    SetTarget(usize, AutomationTarget),
+    UpdateLane(usize, AutomationLane),
+}
+
+impl Automation {
+    pub fn lanes(&self) -> &[AutomationLane] {
+        &self.lanes
+    }
+
+    pub fn update(&mut self, change: AutomationChange) {
+        match change {
+            AutomationChange::AddLane(target) => {
+                let target = match self.has_lane(target) {
+                    true => AutomationTarget::all().into_iter().find(|target| !self.has_lane(*target)),
+                    false => Some(target),
+                };
+                if let Some(target) = target {
+                    self.lanes.push(AutomationLane::new(target));
+                }
+            }
+            AutomationChange::RemoveLane(index) => {
+                if index < self.lanes.len() {
+                    self.lanes.remove(index);
+                }
+            }
+            AutomationChange::SetTarget(index, target) => {
+                if self.has_lane(target) {
+                    return;
+                }
+                if let Some(lane) = self.lanes.get_mut(index) {
+                    lane.target = target;
+                }
+            }
+            AutomationChange::UpdateLane(index, new_lane) => {
+                if let Some(lane) = self.lanes.get_mut(index) {
+                    *lane = new_lane;
+                }
+            }
+        }
+    }
+
+    /// Replaces the lanes, keeping only the first for each target.
+    pub fn set_lanes(&mut self, lanes: Vec<AutomationLane>) {
+        self.lanes.clear();
+        for lane in lanes {
+            if !self.has_lane(lane.target) {
+                self.lanes.push(lane);
+            }
+        }
+    }
+
+    fn has_lane(&self, target: AutomationTarget) -> bool {
+        self.lanes.iter().any(|lane| lane.target == target)
+    }
+}
+
+/// Writes parameter moves into lanes while armed and playing. A parameter
+/// latches once moved, holding its last value until playback stops.
+#[derive(Debug, Clone, Default)]
+pub struct AutomationRecorder {
+    pub armed: bool,
+    /// the tick and value last written for each parameter moved
+    touched: Vec<(AutomationTarget, i32, f32)>,
+}
+
+impl AutomationRecorder {
+    /// Writes a value at the playback cursor, adding a lane if the target
+    /// has none.
+    pub fn record(&mut self, automation: &mut Automation, target: AutomationTarget, tick: i32, value: f32) {
+        let from_tick = match self.touched.iter_mut().find(|(touched, _, _)| *touched == target) {
+            Some(touched) => {
+                let from_tick = touched.1;
+                *touched = (target, tick, value);
+                from_tick
+            }
+            None => {
+                self.touched.push((target, tick, value));
+                tick
+            }
+        };
+
+        let index = match automation.lanes.iter().position(|lane| lane.target == target) {
+            Some(index) => index,
+            None => {
+                automation.lanes.push(AutomationLane::new(target));
+                automation.lanes.len() - 1
+            }
+        };
+
+        // wrapped around a loop
+        let from_tick = if from_tick > tick { tick } else { from_tick };
+        automation.lanes[index].draw(from_tick, tick, value);
+    }
+
+    /// Carries the latched values up to the playback cursor.
+    pub fn advance(&mut self, automation: &mut Automation, tick: i32) {
+        for (target, _, value) in self.touched.clone() {
+            self.record(automation, target, tick, value);
+        }
+    }
+
+    pub fn stop(&mut self) {
+        self.touched.clear();
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Breakpoint {
+    pub tick: i32,
+    /// from 0 to 1 across the target's range
+    pub value: f32,
+    /// bends the segment to the next point, from -1 to 1, 0 being a straight
+    /// line
+    pub curve: f32,
+}
+
+impl EnvelopePoint for Breakpoint {
+    type Value = f32;
+
+    fn tick(&self) -> i32 {
+        self.tick
+    }
+
+    fn towards(&self, next: &Self, position: f32) -> f32 {
+        self.value + (next.value - self.value) * shape(position, self.curve)
+    }
+}
+
+/// A target and the envelope it follows.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AutomationLane {
+    pub target: AutomationTarget,
+    pub points: Envelope<Breakpoint>,
+}
+
+impl AutomationLane {
+    pub fn new(target: AutomationTarget) -> Self {
+        Self { target, points: Envelope::default() }
+    }
+
+    pub fn value_at(&self, tick: f32) -> Option<f32> {
+        self.points.value_at(tick)
+    }
+
+    /// Puts a point at `to_tick`, replacing any drawn over since `from_tick`.
+    pub fn draw(&mut self, from_tick: i32, to_tick: i32, value: f32) {
+        self.points.draw(from_tick, Breakpoint { tick: to_tick, value, curve: 0.0 });
+    }
+
+    /// Replaces the points between two with a straight line.
+    pub fn line(&mut self, from: (i32, f32), to: (i32, f32)) {
+        let (from, to) = if from.0 <= to.0 { (from, to) } else { (to, from) };
+        self.points.retain(|point| point.tick < from.0 || point.tick > to.0);
+        self.points.insert_point(Breakpoint { tick: from.0, value: from.1, curve: 0.0 });
+        if to.0 != from.0 {
+            self.points.insert_point(Breakpoint { tick: to.0, value: to.1, curve: 0.0 });
+        }
+    }
+
+    /// Sets the curve of the segment that `tick` falls in, if any.
+    pub fn set_curve(&mut self, tick: i32, curve: f32) {
+        if let Some(i) = self.points.iter().rposition(|point| point.tick <= tick) {
+            if i + 1 < self.points.len() {
+                self.points[i].curve = curve.max(-1.0).min(1.0);
+            }
+        }
+    }
+
+    pub fn curve_at(&self, tick: i32) -> f32 {
+        self.points.iter().rev()
+            .find(|point| point.tick <= tick)
+            .map_or(0.0, |point| point.curve)
+    }
+
+    /// Removes the point closest to `tick` within `distance` ticks.
+    pub fn remove_near(&mut self, tick: i32, distance: i32) {
+        let closest = self.points.iter()
+            .enumerate()
+            .filter(|(_, point)| (point.tick - tick).abs() <= distance)
+            .min_by_key(|(_, point)| (point.tick - tick).abs())
+            .map(|(i, _)| i);
+
+        if let Some(i) = closest {
+            self.points.remove(i);
+        }
+    }
+}
+
+/// Maps 0..1 onto itself, bowed by `curve`: positive changes late, negative
+/// early.
+fn shape(position: f32, curve: f32) -> f32 {
+    if curve.abs() < 1e-3 {
+        position
+    } else {
+        position.powf(2f32.powf(curve * 3.0))
+    }
+}
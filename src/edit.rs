@@ -0,0 +1,227 @@
+use std::cmp::max;
+
+use crate::sequence::{ExpressionEnvelope, Note, NoteId, Pitch, Sequence, SequenceChange};
+use crate::widgets::tick_grid::TickGrid;
+
+/// Lengthens each note to where the next one in the sequence starts,
+/// selected or not.
+pub fn legato(notes: &Sequence, selected: &[(NoteId, &Note)]) -> SequenceChange {
+    let changes = selected.iter()
+        .filter_map(|&(id, note)| {
+            let next = notes.next_start(note.tick)?;
+            Some(SequenceChange::Update(id, Note { length: next - note.tick, ..note.clone() }))
+        })
+        .collect();
+
+    SequenceChange::Batch(changes)
+}
+
+/// Joins notes of the same pitch that touch or overlap into the first of
+/// them.
+pub fn glue(selected: &[(NoteId, &Note)]) -> SequenceChange {
+    let mut sorted = selected.to_vec();
+    sorted.sort_by(|(_, a), (_, b)| a.pitch.cmp(&b.pitch).then(a.tick.cmp(&b.tick)));
+
+    let mut changes = vec![];
+    let mut glued: Option<(NoteId, Note)> = None;
+    for (id, note) in sorted {
+        glued = match glued {
+            Some((first_id, mut first)) if first.pitch == note.pitch && note.tick <= first.end_tick() => {
+                append(&mut first, note);
+                changes.push(SequenceChange::Remove(id));
+                Some((first_id, first))
+            }
+            finished => {
+                changes.extend(finished.map(|(id, note)| SequenceChange::Update(id, note)));
+                Some((id, note.clone()))
+            }
+        };
+    }
+    changes.extend(glued.map(|(id, note)| SequenceChange::Update(id, note)));
+
+    SequenceChange::Batch(changes)
+}
+
+/// Cuts the notes sounding at a tick in two there.
+pub fn split(selected: &[(NoteId, &Note)], tick: i32) -> SequenceChange {
+    let mut changes = vec![];
+    for &(id, note) in selected {
+        if note.tick < tick && tick < note.end_tick() {
+            let (first, second) = split_note(note, tick - note.tick);
+            changes.push(SequenceChange::Update(id, first));
+            changes.push(SequenceChange::Add(second));
+        }
+    }
+
+    SequenceChange::Batch(changes)
+}
+
+/// Cuts notes into pieces a grid step long, from their starts.
+pub fn chop(selected: &[(NoteId, &Note)], tick_grid: &dyn TickGrid) -> SequenceChange {
+    let mut changes = vec![];
+    for &(id, note) in selected {
+        let step = max(1, tick_grid.grid_size(note.tick));
+        if note.length <= step {
+            continue;
+        }
+
+        let (first, mut rest) = split_note(note, step);
+        changes.push(SequenceChange::Update(id, first));
+        while rest.length > step {
+            let (piece, remainder) = split_note(&rest, step);
+            changes.push(SequenceChange::Add(piece));
+            rest = remainder;
+        }
+        changes.push(SequenceChange::Add(rest));
+    }
+
+    SequenceChange::Batch(changes)
+}
+
+/// The note before and after `offset` ticks into it. Both halves keep the
+/// bend and expression they had either side of the cut.
+pub fn split_note(note: &Note, offset: i32) -> (Note, Note) {
+    let mut first = Note { length: offset, ..note.clone() };
+    let mut second = Note { tick: note.tick + offset, length: note.length - offset, ..note.clone() };
+
+    let bend = note.pitch_envelope.bend_at(offset);
+    first.pitch_envelope.points.retain(|(tick, _)| *tick < offset);
+    second.pitch_envelope.points.retain(|(tick, _)| *tick > offset);
+    if let Some(bend) = bend {
+        first.pitch_envelope.points.push((offset, bend.clone()));
+        second.pitch_envelope.points.insert(0, (offset, bend));
+    }
+    for point in &mut second.pitch_envelope.points {
+        point.0 -= offset;
+    }
+    if let Some(vibrato) = &mut second.pitch_envelope.vibrato {
+        vibrato.delay = max(0, vibrato.delay - offset);
+    }
+
+    split_expression(&note.expression.pressure, &mut first.expression.pressure, &mut second.expression.pressure, offset);
+    split_expression(&note.expression.timbre, &mut first.expression.timbre, &mut second.expression.timbre, offset);
+
+    (first, second)
+}
+
+/// Extends a note over a later one, taking on its bend and expression from
+/// where it starts.
+pub fn append(note: &mut Note, later: &Note) {
+    let offset = later.tick - note.tick;
+    note.length = max(note.end_tick(), later.end_tick()) - note.tick;
+
+    let points = &mut note.pitch_envelope.points;
+    if !later.pitch_envelope.points.is_empty() {
+        points.retain(|(tick, _)| *tick < offset);
+        points.extend(later.pitch_envelope.points.iter().map(|(tick, bend)| (tick + offset, bend.clone())));
+    } else if !points.is_empty() {
+        // the later note wasn't bent
+        points.retain(|(tick, _)| *tick < offset);
+        points.push((offset, Pitch::default()));
+    }
+
+    append_expression(&mut note.expression.pressure, &later.expression.pressure, offset);
+    append_expression(&mut note.expression.timbre, &later.expression.timbre, offset);
+}
+
+fn split_expression(envelope: &ExpressionEnvelope, first: &mut ExpressionEnvelope, second: &mut ExpressionEnvelope, offset: i32) {
+    if let Some(value) = envelope.value_at(offset as f32) {
+        first.points.retain(|(tick, _)| *tick < offset);
+        first.points.push((offset, value));
+        second.points = Some((0, value)).into_iter()
+            .chain(envelope.points.iter().filter(|(tick, _)| *tick > offset).map(|&(tick, value)| (tick - offset, value)))
+            .collect();
+    }
+}
+
+fn append_expression(envelope: &mut ExpressionEnvelope, later: &ExpressionEnvelope, offset: i32) {
+    if !later.is_empty() {
+        envelope.points.retain(|(tick, _)| *tick < offset);
+        envelope.points.extend(later.points.iter().map(|&(tick, value)| (tick + offset, value)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sequence::Vibrato;
+
+    fn sequence(notes: Vec<Note>) -> Sequence {
+        let mut sequence = Sequence::new();
+        for note in notes {
+            sequence.update_sequence(SequenceChange::Add(note));
+        }
+        sequence
+    }
+
+    /// (tick, semitone, length) of each note, sorted
+    fn summary(notes: &Sequence) -> Vec<(i32, i32, i32)> {
+        let mut result: Vec<(i32, i32, i32)> = notes.iter()
+            .map(|(_, note)| (note.tick, (note.pitch.0 * 12).to_integer(), note.length))
+            .collect();
+        result.sort();
+        result
+    }
+
+    #[test]
+    fn split_note_interpolates_bend_and_expression_at_the_cut() {
+        let mut note = Note::new(96, Pitch::new(0, 12), 96);
+        note.pitch_envelope.points = vec![(0, Pitch::new(0, 12)), (96, Pitch::new(4, 12))].into();
+        note.pitch_envelope.vibrato = Some(Vibrato { delay: 24, period: 12, depth: 0.01 });
+        note.expression.pressure.points = vec![(0, 0.0), (96, 1.0)].into();
+
+        let (first, second) = split_note(&note, 48);
+
+        assert_eq!((first.tick, first.length), (96, 48));
+        assert_eq!(*first.pitch_envelope.points, vec![(0, Pitch::new(0, 12)), (48, Pitch::new(2, 12))]);
+        assert_eq!(*first.expression.pressure.points, vec![(0, 0.0), (48, 0.5)]);
+        assert_eq!(first.pitch_envelope.vibrato.unwrap().delay, 24);
+
+        assert_eq!((second.tick, second.length), (144, 48));
+        assert_eq!(*second.pitch_envelope.points, vec![(0, Pitch::new(2, 12)), (48, Pitch::new(4, 12))]);
+        assert_eq!(*second.expression.pressure.points, vec![(0, 0.5), (48, 1.0)]);
+        assert_eq!(second.pitch_envelope.vibrato.unwrap().delay, 0);
+        assert!(second.expression.timbre.is_empty());
+    }
+
+    #[test]
+    fn glue_joins_touching_notes_of_the_same_pitch_only() {
+        let mut notes = sequence(vec![
+            Note::new(0, Pitch::new(0, 12), 48),
+            Note::new(48, Pitch::new(0, 12), 48),
+            Note::new(200, Pitch::new(0, 12), 48),
+            Note::new(24, Pitch::new(7, 12), 48),
+        ]);
+        let first = notes.iter().find(|(_, note)| note.tick == 0).unwrap().0;
+
+        let change = {
+            let selected: Vec<(NoteId, &Note)> = notes.iter().collect();
+            glue(&selected)
+        };
+        notes.update_sequence(change);
+
+        assert_eq!(summary(&notes), vec![(0, 0, 96), (24, 7, 48), (200, 0, 48)]);
+        assert_eq!(notes.get(first).unwrap().length, 96);
+    }
+
+    #[test]
+    fn glue_takes_on_the_later_note_bend() {
+        let mut later = Note::new(48, Pitch::new(0, 12), 48);
+        later.pitch_envelope.points = vec![(0, Pitch::new(0, 12)), (48, Pitch::new(1, 12))].into();
+        let mut earlier = Note::new(0, Pitch::new(0, 12), 64);
+        earlier.expression.pressure.points = vec![(0, 0.5)].into();
+        let mut notes = sequence(vec![earlier, later]);
+
+        let change = {
+            let selected: Vec<(NoteId, &Note)> = notes.iter().collect();
+            glue(&selected)
+        };
+        notes.update_sequence(change);
+
+        let glued: Vec<&Note> = notes.iter().map(|(_, note)| note).collect();
+        assert_eq!(glued.len(), 1);
+        assert_eq!((glued[0].tick, glued[0].length), (0, 96));
+        assert_eq!(*glued[0].pitch_envelope.points, vec![(48, Pitch::new(0, 12)), (96, Pitch::new(1, 12))]);
+        assert_eq!(*glued[0].expression.pressure.points, vec![(0, 0.5)]);
+    }
+}
@@ -0,0 +1,122 @@
+use std::cmp::{max, min};
+use std::ops::{Deref, DerefMut};
+
+use serde::{Deserialize, Serialize};
+
+/// A point an `Envelope` passes through.
+pub trait EnvelopePoint: Clone {
+    type Value;
+
+    fn tick(&self) -> i32;
+    /// the value `position` of the way to the next point, from 0 to 1
+    fn towards(&self, next: &Self, position: f32) -> Self::Value;
+}
+
+/// Breakpoints kept sorted by tick, shared by pitch bends, note expression
+/// and automation lanes. Between two points the value moves along the
+/// segment; before the first and after the last it holds.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Envelope<P> {
+    points: Vec<P>,
+}
+
+impl<P> Default for Envelope<P> {
+    fn default() -> Self {
+        Self { points: vec![] }
+    }
+}
+
+impl<P> From<Vec<P>> for Envelope<P> {
+    fn from(points: Vec<P>) -> Self {
+        Self { points }
+    }
+}
+
+impl<P> std::iter::FromIterator<P> for Envelope<P> {
+    fn from_iter<I: IntoIterator<Item = P>>(iter: I) -> Self {
+        Self { points: iter.into_iter().collect() }
+    }
+}
+
+impl<P> Deref for Envelope<P> {
+    type Target = Vec<P>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.points
+    }
+}
+
+impl<P> DerefMut for Envelope<P> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.points
+    }
+}
+
+impl<'a, P> IntoIterator for &'a Envelope<P> {
+    type Item = &'a P;
+    type IntoIter = std::slice::Iter<'a, P>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.points.iter()
+    }
+}
+
+impl<'a, P> IntoIterator for &'a mut Envelope<P> {
+    type Item = &'a mut P;
+    type IntoIter = std::slice::IterMut<'a, P>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.points.iter_mut()
+    }
+}
+
+impl<P: EnvelopePoint> Envelope<P> {
+    /// The points either side of `tick` and how far between them it is.
+    /// Outside the points, both are the nearest one.
+    pub fn segment_at(&self, tick: f32) -> Option<(&P, &P, f32)> {
+        match self.points.iter().position(|point| point.tick() as f32 > tick) {
+            None => self.points.last().map(|last| (last, last, 0.0)),
+            Some(0) => Some((&self.points[0], &self.points[0], 0.0)),
+            Some(i) => {
+                let (from, to) = (&self.points[i - 1], &self.points[i]);
+                let position = (tick - from.tick() as f32) / (to.tick() - from.tick()) as f32;
+                Some((from, to, position))
+            }
+        }
+    }
+
+    /// the value at `tick`, if there are any points
+    pub fn value_at(&self, tick: f32) -> Option<P::Value> {
+        self.segment_at(tick).map(|(from, to, position)| from.towards(to, position))
+    }
+
+    /// Puts a point in, replacing any drawn over since `from_tick`.
+    pub fn draw(&mut self, from_tick: i32, point: P) {
+        let to_tick = point.tick();
+        let (low, high) = (min(from_tick, to_tick), max(from_tick, to_tick));
+        self.points.retain(|point| {
+            let tick = point.tick();
+            tick < low || tick > high || (tick == from_tick && from_tick != to_tick)
+        });
+        self.insert_point(point);
+    }
+
+    /// Puts a point in after any at the same tick.
+    pub fn insert_point(&mut self, point: P) {
+        let index = self.points.iter().position(|p| p.tick() > point.tick()).unwrap_or(self.points.len());
+        self.points.insert(index, point);
+    }
+}
+
+impl EnvelopePoint for (i32, f32) {
+    type Value = f32;
+
+    fn tick(&self) -> i32 {
+        self.0
+    }
+
+    fn towards(&self, next: &Self, position: f32) -> f32 {
+        self.1 + (next.1 - self.1) * position
+    }
+}
@@ -0,0 +1,360 @@
+use crate::sequence::{Note, NoteId, Sequence, SequenceChange};
+
+/// A note as it was before and after an edit, `None` where it didn't exist.
+#[derive(Debug, Clone)]
+struct Step {
+    id: NoteId,
+    before: Option<Note>,
+    after: Option<Note>,
+}
+
+/// Edits to the notes that can be undone and redone. Each undo step keeps
+/// the notes it touched as they were before and after, so a batch like a
+/// split or a glue goes back in one.
+#[derive(Debug, Default)]
+pub struct History {
+    undo: Vec<Vec<Step>>,
+    redo: Vec<Vec<Step>>,
+    /// the step a mouse gesture underway adds to
+    gesture: Option<usize>,
+    /// the step a recording take underway adds to. Edits made while it's
+    /// recording get steps of their own.
+    take: Option<usize>,
+}
+
+impl History {
+    /// Applies a change as its own undo step, or as part of the gesture
+    /// underway.
+    pub fn edit(&mut self, notes: &mut Sequence, change: SequenceChange) {
+        match self.gesture {
+            Some(step) => self.apply(notes, change, step),
+            None => {
+                let step = self.open();
+                self.apply(notes, change, step);
+                self.close(step);
+            }
+        }
+    }
+
+    /// Applies a change from a mouse gesture. Everything up to
+    /// `end_gesture` is undone together.
+    pub fn gesture(&mut self, notes: &mut Sequence, change: SequenceChange) {
+        let step = match self.gesture {
+            Some(step) => step,
+            None => {
+                let step = self.open();
+                self.gesture = Some(step);
+                step
+            }
+        };
+        self.apply(notes, change, step);
+    }
+
+    pub fn end_gesture(&mut self) {
+        if let Some(step) = self.gesture.take() {
+            self.close(step);
+        }
+    }
+
+    /// Ends the gesture underway, first applying what `resolve` makes of
+    /// the notes it left as part of it.
+    pub fn end_gesture_resolved(&mut self, notes: &mut Sequence, resolve: impl FnOnce(&Sequence, SequenceChange) -> SequenceChange) {
+        let step = match self.gesture {
+            Some(step) => step,
+            None => return,
+        };
+
+        let edited: Vec<SequenceChange> = self.undo[step].iter()
+            .filter_map(|step| notes.get(step.id).map(|note| SequenceChange::Update(step.id, note.clone())))
+            .collect();
+        if !edited.is_empty() {
+            let change = resolve(notes, SequenceChange::Batch(edited));
+            self.apply(notes, change, step);
+        }
+        self.end_gesture();
+    }
+
+    /// Applies a change from the recording take underway. Everything up to
+    /// `end_take` is undone together, whatever else is edited meanwhile.
+    pub fn take(&mut self, notes: &mut Sequence, change: SequenceChange) {
+        let step = match self.take {
+            Some(step) => step,
+            None => {
+                let step = self.open();
+                self.take = Some(step);
+                step
+            }
+        };
+        self.apply(notes, change, step);
+    }
+
+    pub fn end_take(&mut self) {
+        if let Some(step) = self.take.take() {
+            self.close(step);
+        }
+    }
+
+    /// Puts back the notes from before the last step, returning whether
+    /// there was one. Undoing the take underway ends it.
+    pub fn undo(&mut self, notes: &mut Sequence) -> bool {
+        self.end_gesture();
+        if self.take.is_some() && self.take == self.undo.len().checked_sub(1) {
+            self.end_take();
+        }
+        let mut steps = match self.undo.pop() {
+            Some(steps) => steps,
+            None => return false,
+        };
+
+        for i in (0..steps.len()).rev() {
+            let (id, before) = (steps[i].id, steps[i].before.clone());
+            if let Some(new_id) = restore(notes, id, before) {
+                self.remap(&mut steps, id, new_id);
+            }
+        }
+        self.redo.push(steps);
+        true
+    }
+
+    /// Makes the last undone step again, returning whether there was one.
+    pub fn redo(&mut self, notes: &mut Sequence) -> bool {
+        self.end_gesture();
+        let mut steps = match self.redo.pop() {
+            Some(steps) => steps,
+            None => return false,
+        };
+
+        for i in 0..steps.len() {
+            let (id, after) = (steps[i].id, steps[i].after.clone());
+            if let Some(new_id) = restore(notes, id, after) {
+                self.remap(&mut steps, id, new_id);
+            }
+        }
+        self.undo.push(steps);
+        true
+    }
+
+    /// Forgets everything, as when the notes are replaced.
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    fn open(&mut self) -> usize {
+        self.undo.push(vec![]);
+        self.undo.len() - 1
+    }
+
+    fn apply(&mut self, notes: &mut Sequence, change: SequenceChange, step: usize) {
+        self.redo.clear();
+        let steps = &mut self.undo[step];
+        record(notes, change, steps);
+        steps.retain(|step| step.before != step.after);
+    }
+
+    /// Drops a step that's no longer open if it changed nothing.
+    fn close(&mut self, step: usize) {
+        if self.undo[step].is_empty() {
+            self.undo.remove(step);
+            for open in self.gesture.iter_mut().chain(self.take.iter_mut()) {
+                if *open > step {
+                    *open -= 1;
+                }
+            }
+        }
+    }
+
+    /// A note put back gets a new id, which the other steps follow.
+    fn remap(&mut self, current: &mut [Step], from: NoteId, to: NoteId) {
+        let others = self.undo.iter_mut().chain(self.redo.iter_mut()).flatten();
+        for step in current.iter_mut().chain(others) {
+            if step.id == from {
+                step.id = to;
+            }
+        }
+    }
+}
+
+/// Applies a change, noting each note it touches as it was first and is now.
+fn record(notes: &mut Sequence, change: SequenceChange, steps: &mut Vec<Step>) {
+    let id = match &change {
+        SequenceChange::Batch(_) => None,
+        SequenceChange::Add(_) => None,
+        SequenceChange::Remove(id) | SequenceChange::Update(id, _) => Some(*id),
+    };
+
+    let change = match change {
+        SequenceChange::Batch(changes) => {
+            for change in changes {
+                record(notes, change, steps);
+            }
+            return;
+        }
+        change => change,
+    };
+
+    let before = id.and_then(|id| notes.get(id).cloned());
+    notes.update_sequence(change);
+    let id = match id.or_else(|| notes.last_added().map(|(id, _)| id)) {
+        Some(id) => id,
+        None => return,
+    };
+    let after = notes.get(id).cloned();
+
+    match steps.iter_mut().find(|step| step.id == id) {
+        Some(step) => step.after = after,
+        None => steps.push(Step { id, before, after }),
+    }
+}
+
+/// Sets a note back to how it was, returning its new id if it had to be
+/// added again.
+fn restore(notes: &mut Sequence, id: NoteId, note: Option<Note>) -> Option<NoteId> {
+    match (notes.get(id).is_some(), note) {
+        (true, Some(note)) => notes.update_sequence(SequenceChange::Update(id, note)),
+        (true, None) => notes.update_sequence(SequenceChange::Remove(id)),
+        (false, Some(note)) => {
+            notes.update_sequence(SequenceChange::Add(note));
+            return notes.last_added().map(|(id, _)| id);
+        }
+        (false, None) => {}
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::overlap::{OverlapPolicy, OverlapSettings};
+    use crate::sequence::Pitch;
+
+    fn sorted(notes: &Sequence) -> Vec<(i32, i32)> {
+        let mut notes: Vec<(i32, i32)> = notes.iter().map(|(_, note)| (note.tick, note.length)).collect();
+        notes.sort();
+        notes
+    }
+
+    #[test]
+    fn batch_undoes_in_one_step() {
+        let mut notes = Sequence::new();
+        let mut history = History::default();
+        history.edit(&mut notes, SequenceChange::Add(Note::new(0, Pitch::new(0, 1), 96)));
+        let (id, note) = notes.last_added().map(|(id, note)| (id, note.clone())).unwrap();
+
+        // a split: the first half updated and the second added
+        history.edit(&mut notes, SequenceChange::Batch(vec![
+            SequenceChange::Update(id, Note { length: 48, ..note.clone() }),
+            SequenceChange::Add(Note { tick: 48, length: 48, ..note }),
+        ]));
+        assert_eq!(sorted(&notes), vec![(0, 48), (48, 48)]);
+
+        assert!(history.undo(&mut notes));
+        assert_eq!(sorted(&notes), vec![(0, 96)]);
+        assert!(history.redo(&mut notes));
+        assert_eq!(sorted(&notes), vec![(0, 48), (48, 48)]);
+    }
+
+    #[test]
+    fn removed_notes_come_back_for_earlier_steps() {
+        let mut notes = Sequence::new();
+        let mut history = History::default();
+        history.edit(&mut notes, SequenceChange::Add(Note::new(0, Pitch::new(0, 1), 96)));
+        let (id, note) = notes.last_added().map(|(id, note)| (id, note.clone())).unwrap();
+        history.edit(&mut notes, SequenceChange::Update(id, Note { length: 24, ..note }));
+        history.edit(&mut notes, SequenceChange::Remove(id));
+        assert!(notes.iter().next().is_none());
+
+        // the note comes back with a new id, and the resize still undoes
+        history.undo(&mut notes);
+        history.undo(&mut notes);
+        assert_eq!(sorted(&notes), vec![(0, 96)]);
+        history.undo(&mut notes);
+        assert!(notes.iter().next().is_none());
+        assert!(!history.undo(&mut notes));
+    }
+
+    #[test]
+    fn gesture_is_one_step() {
+        let mut notes = Sequence::new();
+        let mut history = History::default();
+        history.gesture(&mut notes, SequenceChange::Add(Note::new(0, Pitch::new(0, 1), 24)));
+        let (id, note) = notes.last_added().map(|(id, note)| (id, note.clone())).unwrap();
+        for length in 25..30 {
+            history.gesture(&mut notes, SequenceChange::Update(id, Note { length, ..note.clone() }));
+        }
+        history.end_gesture();
+
+        history.undo(&mut notes);
+        assert!(notes.iter().next().is_none());
+        assert!(!history.undo(&mut notes));
+    }
+
+    fn dragged_onto(policy: OverlapPolicy) -> (Sequence, History) {
+        let mut notes = Sequence::new();
+        let mut history = History::default();
+        history.edit(&mut notes, SequenceChange::Add(Note::new(0, Pitch::new(0, 1), 96)));
+        history.edit(&mut notes, SequenceChange::Add(Note::new(192, Pitch::new(0, 1), 96)));
+        let (id, note) = notes.last_added().map(|(id, note)| (id, note.clone())).unwrap();
+
+        // left overlapping mid-drag, and resolved on release
+        for tick in &[144, 96, 48] {
+            history.gesture(&mut notes, SequenceChange::Update(id, Note { tick: *tick, ..note.clone() }));
+        }
+        assert_eq!(sorted(&notes), vec![(0, 96), (48, 96)]);
+        let settings = OverlapSettings { policy, monophonic: false };
+        history.end_gesture_resolved(&mut notes, |notes, change| settings.resolve(notes, change));
+        (notes, history)
+    }
+
+    #[test]
+    fn released_drag_truncates_the_note_under_it() {
+        let (mut notes, mut history) = dragged_onto(OverlapPolicy::Truncate);
+        assert_eq!(sorted(&notes), vec![(0, 48), (48, 96)]);
+
+        // the drag and what it cut off undo together
+        history.undo(&mut notes);
+        assert_eq!(sorted(&notes), vec![(0, 96), (192, 96)]);
+    }
+
+    #[test]
+    fn released_drag_merges_with_the_note_under_it() {
+        let (notes, _history) = dragged_onto(OverlapPolicy::Merge);
+        assert_eq!(sorted(&notes), vec![(0, 144)]);
+    }
+
+    #[test]
+    fn take_is_one_step_whatever_is_edited_meanwhile() {
+        let mut notes = Sequence::new();
+        let mut history = History::default();
+        history.edit(&mut notes, SequenceChange::Add(Note::new(192, Pitch::new(0, 1), 96)));
+        let (id, note) = notes.last_added().map(|(id, note)| (id, note.clone())).unwrap();
+
+        history.take(&mut notes, SequenceChange::Add(Note::new(0, Pitch::new(1, 12), 24)));
+        // a click, a drag and a key edit while recording
+        history.end_gesture();
+        history.gesture(&mut notes, SequenceChange::Update(id, Note { length: 48, ..note.clone() }));
+        history.end_gesture();
+        history.edit(&mut notes, SequenceChange::Update(id, Note { tick: 240, length: 48, ..note }));
+        history.take(&mut notes, SequenceChange::Add(Note::new(48, Pitch::new(1, 12), 24)));
+        history.end_take();
+        assert_eq!(sorted(&notes), vec![(0, 24), (48, 24), (240, 48)]);
+
+        history.undo(&mut notes);
+        assert_eq!(sorted(&notes), vec![(0, 24), (48, 24), (192, 48)]);
+        history.undo(&mut notes);
+        assert_eq!(sorted(&notes), vec![(0, 24), (48, 24), (192, 96)]);
+        history.undo(&mut notes);
+        assert_eq!(sorted(&notes), vec![(192, 96)]);
+    }
+
+    #[test]
+    fn no_op_leaves_no_step() {
+        let mut notes = Sequence::new();
+        let mut history = History::default();
+        history.edit(&mut notes, SequenceChange::Add(Note::new(0, Pitch::new(0, 1), 24)));
+        let (id, note) = notes.last_added().map(|(id, note)| (id, note.clone())).unwrap();
+        history.edit(&mut notes, SequenceChange::Update(id, note));
+
+        history.undo(&mut notes);
+        assert!(notes.iter().next().is_none());
+    }
+}
@@ -0,0 +1,515 @@
+use std::convert::TryFrom;
+use std::fmt::{self, Display};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use iced_native::keyboard::{KeyCode, Modifiers};
+use serde::{Deserialize, Serialize};
+
+/// Something a key can be bound to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Action {
+    PlayOrStop,
+    Undo,
+    Redo,
+    SelectAll,
+    Delete,
+    /// copies the selection to just after it ends
+    DuplicateAfter,
+    NudgeLeft,
+    NudgeRight,
+    NudgeLeftFine,
+    NudgeRightFine,
+    Shorten,
+    Lengthen,
+    ShortenFine,
+    LengthenFine,
+    ScaleStepUp,
+    ScaleStepDown,
+    OctaveUp,
+    OctaveDown,
+    /// step entry only
+    StepRest,
+    /// step entry only
+    StepBack,
+    /// step entry only
+    StepOctaveDown,
+    /// step entry only
+    StepOctaveUp,
+    /// lengthens selected notes to meet the next
+    Legato,
+    /// joins touching selected notes of the same pitch
+    Glue,
+    SplitAtPlayhead,
+    SplitAtMouse,
+    /// cuts selected notes into grid steps
+    Chop,
+}
+
+impl Action {
+    pub const ALL: [Action; 27] = [
+        Action::PlayOrStop,
+        Action::Undo,
+        Action::Redo,
+        Action::SelectAll,
+        Action::Delete,
+        Action::DuplicateAfter,
+        Action::NudgeLeft,
+        Action::NudgeRight,
+        Action::NudgeLeftFine,
+        Action::NudgeRightFine,
+        Action::Shorten,
+        Action::Lengthen,
+        Action::ShortenFine,
+        Action::LengthenFine,
+        Action::ScaleStepUp,
+        Action::ScaleStepDown,
+        Action::OctaveUp,
+        Action::OctaveDown,
+        Action::StepRest,
+        Action::StepBack,
+        Action::StepOctaveDown,
+        Action::StepOctaveUp,
+        Action::Legato,
+        Action::Glue,
+        Action::SplitAtPlayhead,
+        Action::SplitAtMouse,
+        Action::Chop,
+    ];
+
+    /// whether it plays what it changes until the key is let go
+    pub fn previews(self) -> bool {
+        matches!(self, Action::ScaleStepUp | Action::ScaleStepDown | Action::OctaveUp | Action::OctaveDown)
+    }
+
+    /// Whether it only does anything during step entry. These come before
+    /// other actions on the same chord while step entry is on, so a key
+    /// can be shared with one used the rest of the time.
+    pub fn step_only(self) -> bool {
+        matches!(self, Action::StepRest | Action::StepBack | Action::StepOctaveDown | Action::StepOctaveUp)
+    }
+}
+
+impl Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Action::PlayOrStop => "Play or stop",
+            Action::Undo => "Undo",
+            Action::Redo => "Redo",
+            Action::SelectAll => "Select all",
+            Action::Delete => "Delete selection",
+            Action::DuplicateAfter => "Duplicate after",
+            Action::NudgeLeft => "Nudge left",
+            Action::NudgeRight => "Nudge right",
+            Action::NudgeLeftFine => "Nudge left (fine)",
+            Action::NudgeRightFine => "Nudge right (fine)",
+            Action::Shorten => "Shorten",
+            Action::Lengthen => "Lengthen",
+            Action::ShortenFine => "Shorten (fine)",
+            Action::LengthenFine => "Lengthen (fine)",
+            Action::ScaleStepUp => "Scale step up",
+            Action::ScaleStepDown => "Scale step down",
+            Action::OctaveUp => "Octave up",
+            Action::OctaveDown => "Octave down",
+            Action::StepRest => "Step entry: rest",
+            Action::StepBack => "Step entry: back",
+            Action::StepOctaveDown => "Step entry: octave down",
+            Action::StepOctaveUp => "Step entry: octave up",
+            Action::Legato => "Legato",
+            Action::Glue => "Glue",
+            Action::SplitAtPlayhead => "Split at playhead",
+            Action::SplitAtMouse => "Split at mouse",
+            Action::Chop => "Chop to grid",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+const DEFAULT_BINDINGS: [(Action, &str); 28] = [
+    (Action::PlayOrStop, "Space"),
+    (Action::Undo, "Ctrl+Z"),
+    (Action::Redo, "Ctrl+Shift+Z"),
+    (Action::SelectAll, "Ctrl+A"),
+    (Action::Delete, "Delete"),
+    (Action::Delete, "Backspace"),
+    (Action::DuplicateAfter, "Ctrl+D"),
+    (Action::NudgeLeft, "Left"),
+    (Action::NudgeRight, "Right"),
+    (Action::NudgeLeftFine, "Alt+Left"),
+    (Action::NudgeRightFine, "Alt+Right"),
+    (Action::Shorten, "Shift+Left"),
+    (Action::Lengthen, "Shift+Right"),
+    (Action::ShortenFine, "Shift+Alt+Left"),
+    (Action::LengthenFine, "Shift+Alt+Right"),
+    (Action::ScaleStepUp, "Up"),
+    (Action::ScaleStepDown, "Down"),
+    (Action::OctaveUp, "Shift+Up"),
+    (Action::OctaveDown, "Shift+Down"),
+    (Action::StepRest, "Tab"),
+    (Action::StepBack, "Backspace"),
+    (Action::StepOctaveDown, "Minus"),
+    (Action::StepOctaveUp, "Equals"),
+    (Action::Legato, "Ctrl+L"),
+    (Action::Glue, "Ctrl+J"),
+    (Action::SplitAtPlayhead, "Ctrl+Shift+E"),
+    (Action::SplitAtMouse, "Ctrl+E"),
+    (Action::Chop, "Ctrl+K"),
+];
+
+/// Semitones from C, tracker style: the bottom two rows play one octave
+/// and the top two the next. Without Ctrl these play notes during step
+/// entry, before any binding.
+pub const STEP_KEYS: [(KeyCode, i32); 29] = [
+    (KeyCode::Z, 0), (KeyCode::S, 1), (KeyCode::X, 2), (KeyCode::D, 3), (KeyCode::C, 4),
+    (KeyCode::V, 5), (KeyCode::G, 6), (KeyCode::B, 7), (KeyCode::H, 8), (KeyCode::N, 9),
+    (KeyCode::J, 10), (KeyCode::M, 11), (KeyCode::Comma, 12), (KeyCode::L, 13), (KeyCode::Period, 14),
+    (KeyCode::Q, 12), (KeyCode::Key2, 13), (KeyCode::W, 14), (KeyCode::Key3, 15), (KeyCode::E, 16),
+    (KeyCode::R, 17), (KeyCode::Key5, 18), (KeyCode::T, 19), (KeyCode::Key6, 20), (KeyCode::Y, 21),
+    (KeyCode::Key7, 22), (KeyCode::U, 23), (KeyCode::I, 24), (KeyCode::O, 26),
+];
+
+/// keys that can be named in a keymap file
+const KEYS: [KeyCode; 74] = [
+    KeyCode::A, KeyCode::B, KeyCode::C, KeyCode::D, KeyCode::E, KeyCode::F, KeyCode::G,
+    KeyCode::H, KeyCode::I, KeyCode::J, KeyCode::K, KeyCode::L, KeyCode::M, KeyCode::N,
+    KeyCode::O, KeyCode::P, KeyCode::Q, KeyCode::R, KeyCode::S, KeyCode::T, KeyCode::U,
+    KeyCode::V, KeyCode::W, KeyCode::X, KeyCode::Y, KeyCode::Z,
+    KeyCode::Key0, KeyCode::Key1, KeyCode::Key2, KeyCode::Key3, KeyCode::Key4,
+    KeyCode::Key5, KeyCode::Key6, KeyCode::Key7, KeyCode::Key8, KeyCode::Key9,
+    KeyCode::F1, KeyCode::F2, KeyCode::F3, KeyCode::F4, KeyCode::F5, KeyCode::F6,
+    KeyCode::F7, KeyCode::F8, KeyCode::F9, KeyCode::F10, KeyCode::F11, KeyCode::F12,
+    KeyCode::Left, KeyCode::Right, KeyCode::Up, KeyCode::Down,
+    KeyCode::Space, KeyCode::Tab, KeyCode::Enter, KeyCode::Escape, KeyCode::Backspace,
+    KeyCode::Delete, KeyCode::Insert, KeyCode::Home, KeyCode::End, KeyCode::PageUp, KeyCode::PageDown,
+    KeyCode::Minus, KeyCode::Equals, KeyCode::Comma, KeyCode::Period, KeyCode::Slash,
+    KeyCode::Semicolon, KeyCode::Apostrophe, KeyCode::LBracket, KeyCode::RBracket,
+    KeyCode::Backslash, KeyCode::Grave,
+];
+
+fn key_name(key: KeyCode) -> String {
+    let name = format!("{:?}", key);
+    // Key1 etc. are written as just the digit
+    match name.strip_prefix("Key") {
+        Some(digit) => digit.to_string(),
+        None => name,
+    }
+}
+
+/// A key with the modifiers held with it, written like `Ctrl+Shift+Left`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Chord {
+    pub key: KeyCode,
+    pub modifiers: Modifiers,
+}
+
+impl Display for Chord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (held, name) in [
+            (self.modifiers.control, "Ctrl"),
+            (self.modifiers.shift, "Shift"),
+            (self.modifiers.alt, "Alt"),
+            (self.modifiers.logo, "Logo"),
+        ].iter() {
+            if *held {
+                write!(f, "{}+", name)?;
+            }
+        }
+        write!(f, "{}", key_name(self.key))
+    }
+}
+
+impl TryFrom<String> for Chord {
+    type Error = String;
+
+    fn try_from(text: String) -> Result<Self, Self::Error> {
+        let mut parts: Vec<&str> = text.split('+').map(str::trim).collect();
+        let key_part = parts.pop().unwrap_or_default();
+
+        let key = KEYS.iter()
+            .find(|&&key| key_name(key).eq_ignore_ascii_case(key_part))
+            .copied()
+            .ok_or_else(|| format!("unknown key \"{}\" in \"{}\"", key_part, text))?;
+
+        let mut modifiers = Modifiers::default();
+        for part in parts {
+            match part.to_ascii_lowercase().as_str() {
+                "ctrl" | "control" => modifiers.control = true,
+                "shift" => modifiers.shift = true,
+                "alt" => modifiers.alt = true,
+                "logo" | "super" | "cmd" => modifiers.logo = true,
+                _ => return Err(format!("unknown modifier \"{}\" in \"{}\"", part, text)),
+            }
+        }
+
+        Ok(Chord { key, modifiers })
+    }
+}
+
+impl From<Chord> for String {
+    fn from(chord: Chord) -> Self {
+        chord.to_string()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Modifier {
+    Shift,
+    Ctrl,
+    Alt,
+    Logo,
+}
+
+impl Modifier {
+    pub fn held(self, modifiers: Modifiers) -> bool {
+        match self {
+            Modifier::Shift => modifiers.shift,
+            Modifier::Ctrl => modifiers.control,
+            Modifier::Alt => modifiers.alt,
+            Modifier::Logo => modifiers.logo,
+        }
+    }
+}
+
+/// Which modifier changes what a click or drag does in the piano roll and
+/// timeline.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MouseModifiers {
+    /// drags out a selection, or a loop on the timeline
+    pub select: Modifier,
+    /// with select, adds what's dragged over to the selection
+    pub select_add: Modifier,
+    /// with select, takes what's dragged over out of the selection
+    pub select_subtract: Modifier,
+    /// clicking empty space draws out a note's length instead of moving it
+    pub create_resizing: Modifier,
+    /// dragging notes leaves copies behind
+    pub duplicate: Modifier,
+    /// off the grid
+    pub unquantized: Modifier,
+    /// with the bend tool, drags a slide
+    pub slide: Modifier,
+    /// with the bend tool, drags vibrato depth
+    pub vibrato: Modifier,
+    /// the wheel zooms rather than scrolls
+    pub wheel_zoom: Modifier,
+    /// the wheel scrolls time rather than pitch, or zooms pitch rather than
+    /// time
+    pub wheel_axis: Modifier,
+}
+
+impl Default for MouseModifiers {
+    fn default() -> Self {
+        Self {
+            select: Modifier::Ctrl,
+            select_add: Modifier::Shift,
+            select_subtract: Modifier::Logo,
+            create_resizing: Modifier::Shift,
+            duplicate: Modifier::Shift,
+            unquantized: Modifier::Alt,
+            slide: Modifier::Shift,
+            vibrato: Modifier::Ctrl,
+            wheel_zoom: Modifier::Ctrl,
+            wheel_axis: Modifier::Shift,
+        }
+    }
+}
+
+impl MouseModifiers {
+    /// roles used in the same gesture, which need different modifiers. A
+    /// select is dragged off the grid on the timeline, so adding to or taking
+    /// from one can't share the unquantized modifier either.
+    fn exclusive(&self) -> [((&str, Modifier), (&str, Modifier)); 12] {
+        let select = ("select", self.select);
+        let select_add = ("select_add", self.select_add);
+        let select_subtract = ("select_subtract", self.select_subtract);
+        let create_resizing = ("create_resizing", self.create_resizing);
+        let duplicate = ("duplicate", self.duplicate);
+        let unquantized = ("unquantized", self.unquantized);
+        [
+            (select, select_add),
+            (select, select_subtract),
+            (select_add, select_subtract),
+            (select, create_resizing),
+            (select, duplicate),
+            (select, unquantized),
+            (select_add, unquantized),
+            (select_subtract, unquantized),
+            (create_resizing, unquantized),
+            (duplicate, unquantized),
+            (("slide", self.slide), ("vibrato", self.vibrato)),
+            (("wheel_zoom", self.wheel_zoom), ("wheel_axis", self.wheel_axis)),
+        ]
+    }
+}
+
+/// Key bindings and mouse modifiers. A keymap file only needs the actions it
+/// rebinds; the rest keep their defaults.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Keymap {
+    #[serde(default)]
+    pub bindings: Vec<(Action, Chord)>,
+    #[serde(default)]
+    pub mouse: MouseModifiers,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self {
+            bindings: DEFAULT_BINDINGS.iter()
+                .map(|(action, chord)| (*action, Chord::try_from(chord.to_string()).expect("bad default binding")))
+                .collect(),
+            mouse: MouseModifiers::default(),
+        }
+    }
+}
+
+impl Keymap {
+    /// `keymap.ron` in the user's config directory
+    pub fn user_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("piano_roll").join("keymap.ron"))
+    }
+
+    /// The user's keymap, or the defaults if they haven't written one.
+    pub fn load_user() -> Result<Self, String> {
+        match Self::user_path() {
+            Some(path) if path.exists() => Self::load(&path),
+            _ => Ok(Self::default()),
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|err| format!("couldn't read {}: {}", path.display(), err))?;
+        let file: Keymap = ron::from_str(&text)
+            .map_err(|err| format!("couldn't parse {}: {}", path.display(), err))?;
+
+        let mut keymap = Self::default();
+        keymap.bindings.retain(|(action, _)| !file.bindings.iter().any(|(rebound, _)| rebound == action));
+        keymap.bindings.extend(file.bindings);
+        keymap.mouse = file.mouse;
+        Ok(keymap)
+    }
+
+    /// the first action bound to the chord, passing over step entry ones
+    /// if it has another
+    pub fn action(&self, key: KeyCode, modifiers: Modifiers) -> Option<Action> {
+        let chord = Chord { key, modifiers };
+        self.bindings.iter()
+            .filter(|(_, bound)| *bound == chord)
+            .min_by_key(|(action, _)| action.step_only())
+            .map(|(action, _)| *action)
+    }
+
+    /// the first step entry action bound to the chord
+    pub fn step_action(&self, key: KeyCode, modifiers: Modifiers) -> Option<Action> {
+        let chord = Chord { key, modifiers };
+        self.bindings.iter()
+            .find(|(action, bound)| *bound == chord && action.step_only())
+            .map(|(action, _)| *action)
+    }
+
+    pub fn chords(&self, action: Action) -> Vec<Chord> {
+        self.bindings.iter()
+            .filter(|(bound, _)| *bound == action)
+            .map(|(_, chord)| *chord)
+            .collect()
+    }
+
+    /// whether letting go of the key should stop a preview, whatever
+    /// modifiers are still held
+    pub fn previews_key(&self, key: KeyCode) -> bool {
+        self.bindings.iter().any(|(action, chord)| chord.key == key && action.previews())
+    }
+
+    /// Chords bound to more than one action, where only the first is ever
+    /// used, chords taken by the step entry notes, and mouse modifiers that
+    /// clash. A step entry action can share a chord with one that isn't.
+    pub fn conflicts(&self) -> Vec<String> {
+        let mut conflicts = vec![];
+
+        for (i, (action, chord)) in self.bindings.iter().enumerate() {
+            let shadowed = self.bindings[i + 1..].iter()
+                .filter(|(other, other_chord)| {
+                    other_chord == chord && other != action && other.step_only() == action.step_only()
+                });
+            for (other, _) in shadowed {
+                conflicts.push(format!("{} is bound to both {} and {}", chord, action, other));
+            }
+
+            if !chord.modifiers.control && STEP_KEYS.iter().any(|(key, _)| *key == chord.key) {
+                conflicts.push(match action.step_only() {
+                    true => format!("{} plays a note in step entry, so {} is never used", chord, action),
+                    false => format!("{} plays a note in step entry, so {} only works outside it", chord, action),
+                });
+            }
+        }
+
+        for ((name, modifier), (other_name, other_modifier)) in self.mouse.exclusive().iter() {
+            if modifier == other_modifier {
+                conflicts.push(format!("{:?} is used for both {} and {}", modifier, name, other_name));
+            }
+        }
+
+        conflicts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chord(text: &str) -> Chord {
+        Chord::try_from(text.to_string()).unwrap()
+    }
+
+    #[test]
+    fn chord_parses_modifiers_in_any_case_and_order() {
+        let parsed = chord("shift + ctrl+left");
+        assert_eq!(parsed.key, KeyCode::Left);
+        assert_eq!(parsed.modifiers, Modifiers { control: true, shift: true, ..Modifiers::default() });
+        // written back the way the defaults are
+        assert_eq!(parsed.to_string(), "Ctrl+Shift+Left");
+    }
+
+    #[test]
+    fn chord_names_digits_without_prefix() {
+        assert_eq!(chord("Alt+5").key, KeyCode::Key5);
+        assert_eq!(chord("Alt+5").to_string(), "Alt+5");
+    }
+
+    #[test]
+    fn chord_rejects_unknown_names() {
+        assert!(Chord::try_from("Ctrl+Nope".to_string()).is_err());
+        assert!(Chord::try_from("Hyper+A".to_string()).is_err());
+        assert!(Chord::try_from(String::new()).is_err());
+    }
+
+    #[test]
+    fn defaults_have_no_conflicts() {
+        assert!(Keymap::default().conflicts().is_empty());
+    }
+
+    #[test]
+    fn conflicts_report_shared_chords_and_step_keys() {
+        let mut keymap = Keymap::default();
+        keymap.bindings.push((Action::Glue, chord("Ctrl+L")));
+        assert_eq!(keymap.conflicts(), vec!["Ctrl+L is bound to both Legato and Glue".to_string()]);
+
+        let mut keymap = Keymap::default();
+        keymap.bindings.push((Action::Undo, chord("Z")));
+        assert_eq!(keymap.conflicts(), vec!["Z plays a note in step entry, so Undo only works outside it".to_string()]);
+    }
+
+    #[test]
+    fn conflicts_report_clashing_mouse_modifiers() {
+        let mut keymap = Keymap::default();
+        keymap.mouse.wheel_axis = Modifier::Ctrl;
+        assert_eq!(keymap.conflicts(), vec!["Ctrl is used for both wheel_zoom and wheel_axis".to_string()]);
+
+        let mut keymap = Keymap::default();
+        keymap.mouse.select_subtract = Modifier::Alt;
+        assert_eq!(keymap.conflicts(), vec!["Alt is used for both select_subtract and unquantized".to_string()]);
+    }
+}
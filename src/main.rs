@@ -1,23 +1,54 @@
-use std::{fmt::Debug, sync::{Arc, Mutex}};
+use std::{fmt::Debug, path::Path, sync::{Arc, Mutex}};
 use std::thread;
+use std::time::Duration;
 
-use iced::{Application, Column, Element, Error, futures::{self, channel::mpsc::Sender}, Row, Settings, Subscription};
+use crossbeam::channel::Sender;
+use iced::{Application, Checkbox, Column, Element, Error, futures, PickList, pick_list, Row, Settings, Slider, slider, Subscription, TextInput, text_input};
 use iced_native::{Button, Text, Length, subscription, keyboard, Event};
 use iced_native::widget::button;
 
 use audio::Status;
 use widgets::piano_roll::PianoRollSettings;
 
-use crate::audio::{SynthCommand, Synth, PlaybackState};
-use crate::sequence::{Sequence, SequenceChange};
+use crate::history::History;
+use crate::keymap::{Action, Keymap};
+use crate::automation::{Automation, AutomationChange, AutomationRecorder, AutomationTarget};
+use crate::audio::{SynthCommand, Synth, PlaybackState, LatencyReport, MidiPort, SynthOutput, MetronomeSettings, EffectState, RackId, ChannelState, SynthEffects, MeterReport, ParameterId};
+use crate::overlap::{self, OverlapPolicy, OverlapSettings};
+use crate::project::Project;
+use crate::recording::{RecordSettings, RecordMode};
+use crate::sequence::{NoteId, Pitch, Sequence, SequenceChange};
 use iced::keyboard::KeyCode;
+use crate::widgets::effect_rack::{EffectRack, EffectRackMessage, EffectRackSelfMessage};
+use crate::widgets::key_bindings::key_bindings;
+use crate::widgets::meters::Meters;
+use crate::widgets::mixer::MixerStrips;
+use crate::widgets::spectrum::Spectrum;
+use crate::widgets::synth_panel::SynthPanel;
+use crate::widgets::piano_roll::state::{PianoRollSelfMessage, Tool};
 use crate::widgets::sequence_editor::{SequenceEditor, SequenceEditorSelfMessage, SequenceEditorMessage};
 
 mod audio;
 mod sequence;
+mod envelope;
 mod widgets;
 mod scroll_zoom;
 mod helpers;
+mod recording;
+mod project;
+mod automation;
+mod keymap;
+mod quantize;
+mod transform;
+mod edit;
+mod history;
+mod overlap;
+
+const LOOKAHEAD: Duration = Duration::from_millis(50);
+const MIDI_BEND_RANGES: [u32; 4] = [2, 12, 24, 48];
+const COUNT_IN_BARS: [u32; 3] = [0, 1, 2];
+/// in ms, the lookaheads to pick from when the default can't keep up
+const LOOKAHEADS: [u32; 5] = [10, 20, 50, 100, 200];
 
 pub fn main() -> Result<(), Error> {
     App::run(Settings::default())
@@ -25,21 +56,91 @@ pub fn main() -> Result<(), Error> {
 
 struct App {
     notes: Arc<Mutex<Sequence>>,
+    history: History,
+    automation: Arc<Mutex<Automation>>,
+    automation_recorder: AutomationRecorder,
     settings: PianoRollSettings,
     play_button: button::State,
     stop_button: button::State,
     synth_channel: Option<Sender<SynthCommand>>,
     playback_state: PlaybackState,
+    latency: Option<LatencyReport>,
+    /// in ms
+    lookahead: u32,
+    lookahead_pick_list: pick_list::State<u32>,
     sequence_editor: SequenceEditor,
+    record_settings: RecordSettings,
+    /// where replacing started, once the take's first note came in
+    replace_from: Option<i32>,
+    /// the notes the take underway recorded, which replacing leaves alone
+    take_notes: Vec<NoteId>,
+    midi_input_ports: Vec<MidiPort>,
+    midi_input_port: Option<MidiPort>,
+    midi_input_pick_list: pick_list::State<MidiPort>,
+    midi_output_ports: Vec<MidiPort>,
+    midi_output_port: Option<MidiPort>,
+    midi_output_pick_list: pick_list::State<MidiPort>,
+    midi_bend_range: u32,
+    midi_bend_range_pick_list: pick_list::State<u32>,
+    metronome_settings: MetronomeSettings,
+    metronome_volume_slider: slider::State,
+    count_in_pick_list: pick_list::State<u32>,
+    master_effects: Vec<EffectState>,
+    master_rack: EffectRack,
+    send_effects: Vec<EffectState>,
+    send_rack: EffectRack,
+    mixer_channels: Vec<ChannelState>,
+    mixer_strips: MixerStrips,
+    synth_effects: SynthEffects,
+    synth_panel: SynthPanel,
+    project_path: String,
+    project_path_input: text_input::State,
+    save_button: button::State,
+    open_button: button::State,
+    overlap_pick_list: pick_list::State<OverlapPolicy>,
+    remove_duplicates_button: button::State,
+    project_error: Option<String>,
+    /// the last thing the engine couldn't do
+    synth_error: Option<String>,
+    meters: Option<MeterReport>,
+    meter_clipped: [bool; 2],
+    /// held on the MIDI input, shown on the keyboard
+    input_keys: Vec<u8>,
+    keymap_error: Option<String>,
+    show_key_bindings: bool,
+    key_bindings_button: button::State,
 }
 
 #[derive(Debug, Clone)]
 enum Message {
     Sequence(SequenceChange),
+    Gesture(SequenceChange),
+    GestureEnd,
+    Undo,
+    Redo,
     SynthCommand(SynthCommand),
     SynthStatus(Status),
     PlayOrStop,
+    KeyPressed(KeyCode, keyboard::Modifiers),
+    ToggleKeyBindings,
     SequenceEditorMessage(SequenceEditorSelfMessage),
+    Automation(AutomationChange),
+    SetAutomationArmed(bool),
+    SelectMidiInput(MidiPort),
+    SelectMidiOutput(MidiPort),
+    SelectMidiBendRange(u32),
+    SelectLookahead(u32),
+    SetInputQuantize(bool),
+    SetRecordMode(RecordMode),
+    SetMetronome(MetronomeSettings),
+    EffectRack(RackId, EffectRackSelfMessage),
+    SetSynthEffects(SynthEffects),
+    SetProjectPath(String),
+    SaveProject,
+    OpenProject,
+    SetOverlap(OverlapSettings),
+    RemoveDuplicates,
+    ResetClip,
 }
 
 impl Application for App {
@@ -48,15 +149,63 @@ impl Application for App {
     type Flags = ();
 
     fn new(_flags: ()) -> (Self, iced::Command<Message>) {
+        let keymap = Keymap::load_user();
+        let mut settings = PianoRollSettings::default();
+        if let Ok(keymap) = &keymap {
+            settings.keymap = keymap.clone();
+        }
+
         (
             App {
                 notes: Arc::new(Mutex::new(Sequence::new())),
-                settings: PianoRollSettings::default(),
+                history: History::default(),
+                automation: Arc::new(Mutex::new(Automation::default())),
+                automation_recorder: AutomationRecorder::default(),
+                settings,
                 play_button: button::State::new(),
                 stop_button: button::State::new(),
                 synth_channel: None,
                 playback_state: PlaybackState::new(),
+                latency: None,
+                lookahead: LOOKAHEAD.as_millis() as u32,
+                lookahead_pick_list: Default::default(),
                 sequence_editor: Default::default(),
+                record_settings: RecordSettings::default(),
+                replace_from: None,
+                take_notes: vec![],
+                midi_input_ports: vec![],
+                midi_input_port: None,
+                midi_input_pick_list: Default::default(),
+                midi_output_ports: vec![],
+                midi_output_port: None,
+                midi_output_pick_list: Default::default(),
+                midi_bend_range: MIDI_BEND_RANGES[0],
+                midi_bend_range_pick_list: Default::default(),
+                metronome_settings: MetronomeSettings::default(),
+                metronome_volume_slider: Default::default(),
+                count_in_pick_list: Default::default(),
+                master_effects: vec![],
+                master_rack: EffectRack::new(RackId::Master),
+                send_effects: vec![],
+                send_rack: EffectRack::new(RackId::Send),
+                mixer_channels: vec![],
+                mixer_strips: Default::default(),
+                synth_effects: SynthEffects::default(),
+                synth_panel: Default::default(),
+                project_path: String::new(),
+                project_path_input: Default::default(),
+                save_button: button::State::new(),
+                open_button: button::State::new(),
+                overlap_pick_list: Default::default(),
+                remove_duplicates_button: button::State::new(),
+                project_error: None,
+                synth_error: None,
+                meters: None,
+                meter_clipped: [false; 2],
+                input_keys: vec![],
+                keymap_error: keymap.err(),
+                show_key_bindings: false,
+                key_bindings_button: button::State::new(),
             },
             iced::Command::none(),
         )
@@ -70,32 +219,246 @@ impl Application for App {
         match message {
             Message::Sequence(change) => {
                 let mut notes = self.notes.lock().unwrap();
-                notes.update_sequence(change);
+                let change = self.settings.overlap.resolve(&notes, change);
+                self.history.edit(&mut notes, change);
+            },
+            Message::Gesture(change) => {
+                // resolved once the mouse is let go
+                self.history.gesture(&mut self.notes.lock().unwrap(), change);
+            },
+            Message::GestureEnd => {
+                let overlap = self.settings.overlap;
+                self.history.end_gesture_resolved(&mut self.notes.lock().unwrap(), |notes, change| overlap.resolve(notes, change));
+            },
+            Message::Undo => {
+                self.history.undo(&mut self.notes.lock().unwrap());
+            },
+            Message::Redo => {
+                self.history.redo(&mut self.notes.lock().unwrap());
+            },
+            Message::Automation(change) => {
+                self.automation.lock().unwrap().update(change);
+            },
+            Message::SetAutomationArmed(armed) => {
+                self.automation_recorder.armed = armed;
             },
             Message::SynthCommand(command) => {
-                if let Some(channel) = self.synth_channel.as_mut() {
-                    channel.try_send(command);
+                self.record_automation(&command);
+                if let Some(channel) = self.synth_channel.as_ref() {
+                    let _ = channel.try_send(command);
                 }
             },
             Message::SynthStatus(status) => match status {
-                Status::CommandChannel(mut channel) => {
-                    channel.try_send(SynthCommand::SetNotes(self.notes.clone()));
+                Status::CommandChannel(channel) => {
+                    let _ = channel.try_send(SynthCommand::SetNotes(self.notes.clone()));
+                    let _ = channel.try_send(SynthCommand::SetAutomation(self.automation.clone()));
+                    let _ = channel.try_send(SynthCommand::SetTickGrid(self.settings.tick_grid.clone()));
+                    let _ = channel.try_send(SynthCommand::SetMetronome(self.metronome_settings));
+                    let _ = channel.try_send(SynthCommand::SetSynthEffects(self.synth_effects));
+                    let _ = channel.try_send(SynthCommand::SetLookahead(Duration::from_millis(self.lookahead as u64)));
                     self.synth_channel = Some(channel);
                 },
                 Status::PlaybackStateUpdated(state) => {
+                    if state.recording && state.playing {
+                        if let Some(replace_from) = self.replace_from {
+                            let from_tick = if state.playback_cursor < self.playback_state.playback_cursor {
+                                // wrapped around the loop, which the take now covers
+                                let start = state.looping.map_or(state.playback_cursor, |(start, _end)| start);
+                                self.replace_from = Some(start);
+                                start
+                            } else {
+                                replace_from.max(self.playback_state.playback_cursor)
+                            };
+
+                            let mut notes = self.notes.lock().unwrap();
+                            let replaced = self.record_settings.replaced_notes(&notes, from_tick, state.playback_cursor, &self.take_notes);
+                            // an empty step would still clear the redo stack
+                            if !replaced.is_empty() {
+                                self.history.take(&mut notes, SequenceChange::Batch(replaced));
+                            }
+                        }
+                    } else {
+                        self.end_take();
+                    }
+                    self.sequence_editor.follow_playhead(&state);
+                    if state.playing {
+                        self.automation_recorder.advance(&mut self.automation.lock().unwrap(), state.playback_cursor);
+                    } else {
+                        self.automation_recorder.stop();
+                    }
                     self.playback_state = state;
                 }
+                Status::SchedulingLatency(report) => {
+                    self.latency = Some(report);
+                }
+                Status::MidiInputPorts(ports) => {
+                    self.midi_input_ports = ports;
+                }
+                Status::MidiOutputPorts(ports) => {
+                    self.midi_output_ports = ports;
+                }
+                Status::NoteRecorded(note) => {
+                    let note = self.record_settings.recorded_note(note, &*self.settings.tick_grid);
+                    let mut notes = self.notes.lock().unwrap();
+                    let change = self.settings.overlap.resolve(&notes, SequenceChange::Add(note));
+                    // notes flushed on stop come in before the state saying so
+                    if self.playback_state.recording && self.playback_state.playing {
+                        let last_added = notes.last_added().map(|(id, _note)| id);
+                        self.history.take(&mut notes, change);
+                        // resolving the overlaps may have removed it again
+                        if let Some((id, _note)) = notes.last_added().filter(|(id, _note)| Some(*id) != last_added) {
+                            self.take_notes.push(id);
+                        }
+                    } else {
+                        self.history.edit(&mut notes, change);
+                    }
+                }
+                Status::EffectRack(RackId::Master, effects) => {
+                    self.master_effects = effects;
+                }
+                Status::EffectRack(RackId::Send, effects) => {
+                    self.send_effects = effects;
+                }
+                Status::MixerChannels(channels) => {
+                    self.mixer_channels = channels;
+                }
+                Status::Meters(report) => {
+                    for (clipped, &clipped_now) in self.meter_clipped.iter_mut().zip(&report.clipped) {
+                        *clipped |= clipped_now;
+                    }
+                    self.meters = Some(report);
+                }
+                Status::Error(err) => {
+                    self.synth_error = Some(err);
+                }
+                Status::InputNote { key, on } => {
+                    self.input_keys.retain(|&held| held != key);
+                    if on {
+                        self.input_keys.push(key);
+                        if self.playback_state.recording && self.playback_state.playing && self.replace_from.is_none() {
+                            self.replace_from = Some(self.playback_state.playback_cursor);
+                        }
+                    }
+                }
             }
             Message::PlayOrStop => {
-                if let Some(channel) = self.synth_channel.as_mut() {
-                    match self.playback_state.playing {
+                if let Some(channel) = self.synth_channel.as_ref() {
+                    let _ = match self.playback_state.playing {
                         true => channel.try_send(SynthCommand::Stop),
                         false => channel.try_send(SynthCommand::Play)
                     };
                 }
             }
+            Message::KeyPressed(key_code, modifiers) => {
+                match self.settings.keymap.action(key_code, modifiers) {
+                    Some(Action::PlayOrStop) => return self.update(Message::PlayOrStop),
+                    Some(Action::Undo) => return self.update(Message::Undo),
+                    Some(Action::Redo) => return self.update(Message::Redo),
+                    _ => {}
+                }
+            }
+            Message::ToggleKeyBindings => {
+                self.show_key_bindings = !self.show_key_bindings;
+            }
             Message::SequenceEditorMessage(message) => {
-                self.sequence_editor.update(message, &self.notes);
+                let changes = self.sequence_editor.update(message, &self.notes, &self.settings);
+                if !changes.is_empty() {
+                    let mut notes = self.notes.lock().unwrap();
+                    let change = self.settings.overlap.resolve(&notes, SequenceChange::Batch(changes));
+                    self.history.edit(&mut notes, change);
+                }
+            }
+            Message::SelectMidiInput(port) => {
+                if let Some(channel) = self.synth_channel.as_ref() {
+                    let _ = channel.try_send(SynthCommand::OpenMidiInput(port.clone()));
+                }
+                self.midi_input_port = Some(port);
+            }
+            Message::SelectMidiOutput(port) => {
+                if let Some(channel) = self.synth_channel.as_ref() {
+                    let _ = channel.try_send(SynthCommand::OpenMidiOutput(port.clone()));
+                }
+                self.midi_output_port = Some(port);
+            }
+            Message::SelectMidiBendRange(bend_range) => {
+                if let Some(channel) = self.synth_channel.as_ref() {
+                    let _ = channel.try_send(SynthCommand::SetMidiBendRange(bend_range as f32));
+                }
+                self.midi_bend_range = bend_range;
+            }
+            Message::SelectLookahead(lookahead) => {
+                if let Some(channel) = self.synth_channel.as_ref() {
+                    let _ = channel.try_send(SynthCommand::SetLookahead(Duration::from_millis(lookahead as u64)));
+                }
+                self.lookahead = lookahead;
+            }
+            Message::SetInputQuantize(quantize) => {
+                self.record_settings.quantize = quantize;
+            }
+            Message::SetRecordMode(mode) => {
+                self.record_settings.mode = mode;
+            }
+            Message::SetMetronome(settings) => {
+                if let Some(channel) = self.synth_channel.as_ref() {
+                    let _ = channel.try_send(SynthCommand::SetMetronome(settings));
+                }
+                self.metronome_settings = settings;
+            }
+            Message::EffectRack(RackId::Master, message) => self.master_rack.update(message),
+            Message::EffectRack(RackId::Send, message) => self.send_rack.update(message),
+            Message::SetSynthEffects(effects) => {
+                if let Some(channel) = self.synth_channel.as_ref() {
+                    let _ = channel.try_send(SynthCommand::SetSynthEffects(effects));
+                }
+                self.synth_effects = effects;
+            }
+            Message::SetProjectPath(path) => {
+                self.project_path = path;
+            }
+            Message::SaveProject => {
+                let project = Project::new(
+                    &self.notes.lock().unwrap(),
+                    &self.automation.lock().unwrap(),
+                    self.synth_effects,
+                    self.settings.overlap,
+                    &self.master_effects,
+                    &self.send_effects,
+                );
+                self.project_error = project.save(Path::new(&self.project_path)).err();
+            }
+            Message::OpenProject => match Project::load(Path::new(&self.project_path)) {
+                Ok(project) => {
+                    let mut notes = self.notes.lock().unwrap();
+                    project.replace_notes(&mut notes);
+                    let change = project.overlap.resolve_all(&notes);
+                    notes.update_sequence(change);
+                    drop(notes);
+                    self.history.clear();
+                    self.settings.overlap = project.overlap;
+                    self.automation.lock().unwrap().set_lanes(project.automation.clone());
+                    if let Some(channel) = self.synth_channel.as_ref() {
+                        let racks = [(RackId::Master, &project.master_rack), (RackId::Send, &project.send_rack)];
+                        for (rack, effects) in racks.iter() {
+                            if let Some(effects) = effects {
+                                let _ = channel.try_send(SynthCommand::SetRack { rack: *rack, effects: effects.clone() });
+                            }
+                        }
+                    }
+                    self.project_error = None;
+                    return self.update(Message::SetSynthEffects(project.synth_effects));
+                }
+                Err(err) => self.project_error = Some(err),
+            }
+            Message::SetOverlap(overlap) => {
+                self.settings.overlap = overlap;
+            }
+            Message::RemoveDuplicates => {
+                let mut notes = self.notes.lock().unwrap();
+                let change = overlap::remove_duplicates(&notes);
+                self.history.edit(&mut notes, change);
+            }
+            Message::ResetClip => {
+                self.meter_clipped = [false; 2];
             }
         }
         iced::Command::none()
@@ -106,10 +469,7 @@ impl Application for App {
             Subscription::from_recipe(SynthThread("main synth thread")).map(|x| Message::SynthStatus(x)),
             subscription::events_with(|event, _status| {
                 match event {
-                    Event::Keyboard(keyboard::Event::KeyPressed { key_code, .. }) => match key_code {
-                        KeyCode::Space => Some(Message::PlayOrStop),
-                        _ => None
-                    }
+                    Event::Keyboard(keyboard::Event::KeyPressed { key_code, modifiers }) => Some(Message::KeyPressed(key_code, modifiers)),
                     _ => None
                 }
             })
@@ -117,13 +477,30 @@ impl Application for App {
     }
 
     fn view(&mut self) -> Element<Self::Message> {
+        let metronome = self.metronome_settings;
+        let overlap = self.settings.overlap;
+        let tool = self.sequence_editor.tool();
+        let step_entry = self.sequence_editor.step_entry();
+        let step_start = self.settings.tick_grid.quantize_tick(self.playback_state.playback_start_cursor);
+        let lane_names: Vec<String> = self.automation.lock().unwrap().lanes().iter()
+            .map(|lane| match lane.target {
+                AutomationTarget::Parameter(id) => self.parameter_name(id),
+                target => target.to_string(),
+            })
+            .collect();
+
+        let held_pitches = self.input_keys.iter().map(|&key| Pitch::new(key as i32 - 69, 12)).collect();
+
         Column::new()
             .push(self.sequence_editor.view(
-                &self.notes, &self.settings, &self.playback_state
+                &self.notes, &self.automation, lane_names, &self.settings, &self.playback_state, held_pitches,
             ).map(move |message| {
                 match message {
                     SequenceEditorMessage::SelfMessage(content) => Message::SequenceEditorMessage(content),
                     SequenceEditorMessage::SequenceChange(content) => Message::Sequence(content),
+                    SequenceEditorMessage::Gesture(content) => Message::Gesture(content),
+                    SequenceEditorMessage::GestureEnd => Message::GestureEnd,
+                    SequenceEditorMessage::AutomationChange(content) => Message::Automation(content),
                     SequenceEditorMessage::SynthCommand(content) => Message::SynthCommand(content),
                 }
             }))
@@ -132,12 +509,195 @@ impl Application for App {
                     .on_press(Message::SynthCommand(SynthCommand::Play)))
                 .push(Button::new(&mut self.stop_button, Text::new("Stop"))
                     .on_press(Message::SynthCommand(SynthCommand::Stop)))
+                .push(Checkbox::new(tool == Tool::Bend, "Bend tool", |bend| {
+                    let tool = if bend { Tool::Bend } else { Tool::Notes };
+                    Message::SequenceEditorMessage(SequenceEditorSelfMessage::PianoRoll(PianoRollSelfMessage::SetTool(tool)))
+                }))
+                .push(Checkbox::new(step_entry, "Step entry", move |on| {
+                    let tick = if on { Some(step_start) } else { None };
+                    Message::SequenceEditorMessage(SequenceEditorSelfMessage::PianoRoll(PianoRollSelfMessage::SetStepEntry(tick)))
+                }))
+                .push(Checkbox::new(self.playback_state.chase, "Chase", |chase| {
+                    Message::SynthCommand(SynthCommand::SetChase(chase))
+                }))
+                .push(Checkbox::new(self.playback_state.recording, "Record", |recording| {
+                    Message::SynthCommand(SynthCommand::SetRecording(recording))
+                }))
+                .push(Checkbox::new(self.automation_recorder.armed, "Arm automation", Message::SetAutomationArmed))
+                .push(Checkbox::new(self.record_settings.quantize, "Quantize input", Message::SetInputQuantize))
+                .push(Checkbox::new(self.record_settings.mode == RecordMode::Replace, "Replace", |replace| {
+                    Message::SetRecordMode(if replace { RecordMode::Replace } else { RecordMode::Overdub })
+                }))
+                .push(PickList::new(
+                    &mut self.midi_input_pick_list,
+                    &self.midi_input_ports,
+                    self.midi_input_port.clone(),
+                    Message::SelectMidiInput,
+                ))
+                .push(Checkbox::new(self.playback_state.output == SynthOutput::Midi, "MIDI out", |midi| {
+                    Message::SynthCommand(SynthCommand::SetOutput(if midi { SynthOutput::Midi } else { SynthOutput::Internal }))
+                }))
+                .push(PickList::new(
+                    &mut self.midi_output_pick_list,
+                    &self.midi_output_ports,
+                    self.midi_output_port.clone(),
+                    Message::SelectMidiOutput,
+                ))
+                .push(PickList::new(
+                    &mut self.midi_bend_range_pick_list,
+                    &MIDI_BEND_RANGES[..],
+                    Some(self.midi_bend_range),
+                    Message::SelectMidiBendRange,
+                ))
+                .push(Checkbox::new(metronome.enabled, "Metronome", move |enabled| {
+                    Message::SetMetronome(MetronomeSettings { enabled, ..metronome })
+                }))
+                .push(Slider::new(&mut self.metronome_volume_slider, 0.0..=1.0, metronome.volume, move |volume| {
+                    Message::SetMetronome(MetronomeSettings { volume, ..metronome })
+                }).step(0.01).width(Length::Units(80)))
+                .push(Text::new("Count-in"))
+                .push(PickList::new(
+                    &mut self.count_in_pick_list,
+                    &COUNT_IN_BARS[..],
+                    Some(metronome.count_in_bars),
+                    move |count_in_bars| Message::SetMetronome(MetronomeSettings { count_in_bars, ..metronome }),
+                ))
+                .push(Text::new(match &self.latency {
+                    Some(report) => format!(
+                        "latency {:.1} ms avg, {:.1} ms worst ({} ms lookahead)",
+                        report.average.as_secs_f32() * 1000.0,
+                        report.worst.as_secs_f32() * 1000.0,
+                        report.lookahead.as_millis(),
+                    ),
+                    None => String::new(),
+                }))
+                .push(Text::new("Lookahead"))
+                .push(PickList::new(
+                    &mut self.lookahead_pick_list,
+                    &LOOKAHEADS[..],
+                    Some(self.lookahead),
+                    Message::SelectLookahead,
+                ))
                 .height(Length::Shrink)
             )
+            .push(Row::new()
+                .spacing(4)
+                .push(TextInput::new(&mut self.project_path_input, "project file", &self.project_path, Message::SetProjectPath)
+                    .width(Length::Units(240))
+                    .on_submit(Message::OpenProject))
+                .push(Button::new(&mut self.save_button, Text::new("Save")).on_press(Message::SaveProject))
+                .push(Button::new(&mut self.open_button, Text::new("Open")).on_press(Message::OpenProject))
+                .push(Text::new(self.project_error.clone().unwrap_or_default()))
+                .push(Text::new(self.synth_error.clone().unwrap_or_default()))
+                .push(PickList::new(
+                    &mut self.overlap_pick_list,
+                    &OverlapPolicy::ALL[..],
+                    Some(overlap.policy),
+                    move |policy| Message::SetOverlap(OverlapSettings { policy, ..overlap }),
+                ))
+                .push(Checkbox::new(overlap.monophonic, "Monophonic", move |monophonic| {
+                    Message::SetOverlap(OverlapSettings { monophonic, ..overlap })
+                }))
+                .push(Button::new(&mut self.remove_duplicates_button, Text::new("Remove duplicates")).on_press(Message::RemoveDuplicates))
+                .push(Button::new(&mut self.key_bindings_button, Text::new("Key bindings")).on_press(Message::ToggleKeyBindings))
+            )
+            .push(match self.show_key_bindings {
+                true => key_bindings(&self.settings.keymap, self.keymap_error.as_deref()),
+                false => Column::new().into(),
+            })
+            .push(self.synth_panel.view(self.synth_effects).map(Message::SetSynthEffects))
+            .push(Row::new()
+                .spacing(16)
+                .push(self.mixer_strips.view(&self.mixer_channels).map(Message::SynthCommand))
+                .push(Meters::new(self.meters.as_ref(), self.meter_clipped, Message::ResetClip))
+                .push(Spectrum::new(self.meters.as_ref().map_or(&[][..], |report| &report.spectrum)))
+            )
+            .push(Row::new()
+                .spacing(32)
+                .push(Column::new()
+                    .push(Text::new("Master"))
+                    .push(self.master_rack.view(&self.master_effects).map(|message| effect_rack_message(RackId::Master, message))))
+                .push(Column::new()
+                    .push(Text::new("Send"))
+                    .push(self.send_rack.view(&self.send_effects).map(|message| effect_rack_message(RackId::Send, message))))
+            )
             .into()
     }
 }
 
+impl App {
+    /// Closes the take's undo step, once playback or recording stops.
+    fn end_take(&mut self) {
+        self.history.end_take();
+        self.take_notes.clear();
+        self.replace_from = None;
+    }
+
+    fn effects(&self, rack: RackId) -> &[EffectState] {
+        match rack {
+            RackId::Master => &self.master_effects,
+            RackId::Send => &self.send_effects,
+        }
+    }
+
+    /// Writes mixer and effect moves into automation lanes while armed and
+    /// playing.
+    fn record_automation(&mut self, command: &SynthCommand) {
+        if !self.automation_recorder.armed || !self.playback_state.playing {
+            return;
+        }
+
+        let (id, value) = match *command {
+            SynthCommand::SetChannel { channel, parameter, value } => {
+                let (min, max) = parameter.range();
+                (ParameterId::Channel { channel, parameter }, (value - min) / (max - min))
+            }
+            SynthCommand::SetEffectParameter { rack, effect, parameter, value } => {
+                let state = match self.effects(rack).get(effect) {
+                    Some(state) => state,
+                    None => return,
+                };
+                let descriptor = match state.parameters.get(parameter) {
+                    Some(descriptor) => descriptor,
+                    None => return,
+                };
+                let id = ParameterId::Effect { rack, effect: state.id, parameter };
+                (id, (value - descriptor.min) / (descriptor.max - descriptor.min))
+            }
+            _ => return,
+        };
+
+        self.automation_recorder.record(
+            &mut self.automation.lock().unwrap(),
+            AutomationTarget::Parameter(id),
+            self.playback_state.playback_cursor,
+            value as f32,
+        );
+    }
+
+    fn parameter_name(&self, id: ParameterId) -> String {
+        let name = match id {
+            ParameterId::Channel { channel, parameter } => self.mixer_channels.get(channel)
+                .map(|state| format!("{} {:?}", state.name, parameter)),
+            ParameterId::Effect { rack, effect, parameter } => self.effects(rack).iter()
+                .find(|state| state.id == effect)
+                .and_then(|state| {
+                    let descriptor = state.parameters.get(parameter)?;
+                    Some(format!("{:?} {} {}", rack, state.kind, descriptor.name))
+                }),
+        };
+
+        name.unwrap_or_else(|| id.to_string())
+    }
+}
+
+fn effect_rack_message(rack: RackId, message: EffectRackMessage) -> Message {
+    match message {
+        EffectRackMessage::SynthCommand(command) => Message::SynthCommand(command),
+        EffectRackMessage::SelfMessage(message) => Message::EffectRack(rack, message),
+    }
+}
+
 struct SynthThread(&'static str);
 
 impl<H, I> iced_native::subscription::Recipe<H, I> for SynthThread
@@ -157,7 +717,7 @@ where
         self: Box<Self>,
         _input: futures::stream::BoxStream<'static, I>,
     ) -> futures::stream::BoxStream<'static, Self::Output> {
-        let (status_channel, synth) = Synth::create();
+        let (status_channel, synth) = Synth::create(LOOKAHEAD);
         thread::spawn(move|| {
             synth.run();
         });
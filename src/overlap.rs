@@ -0,0 +1,362 @@
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Display};
+
+use serde::{Deserialize, Serialize};
+
+use crate::edit;
+use crate::sequence::{Note, NoteId, Sequence, SequenceChange};
+
+/// What happens when an edit leaves notes overlapping on the same pitch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OverlapPolicy {
+    /// cuts the earlier note off where the later one starts
+    Truncate,
+    /// joins them into one note
+    Merge,
+    /// leaves them. The synth plays both on one key, so whichever ends
+    /// first stops the other.
+    Allow,
+}
+
+impl OverlapPolicy {
+    pub const ALL: [OverlapPolicy; 3] = [OverlapPolicy::Truncate, OverlapPolicy::Merge, OverlapPolicy::Allow];
+}
+
+impl Default for OverlapPolicy {
+    fn default() -> Self {
+        OverlapPolicy::Truncate
+    }
+}
+
+impl Display for OverlapPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            OverlapPolicy::Truncate => "Truncate overlaps",
+            OverlapPolicy::Merge => "Merge overlaps",
+            OverlapPolicy::Allow => "Allow overlaps",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct OverlapSettings {
+    pub policy: OverlapPolicy,
+    /// one note at a time whatever the pitch, for bass and lead lines. A
+    /// note starting cuts off the one before, and of notes starting
+    /// together only one is kept.
+    pub monophonic: bool,
+}
+
+/// where a note in a resolution came from
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Origin {
+    Existing(NoteId),
+    Updated(NoteId),
+    Added,
+}
+
+struct Entry {
+    origin: Origin,
+    note: Note,
+    changed: bool,
+    removed: bool,
+}
+
+impl Entry {
+    fn edited(&self) -> bool {
+        !matches!(self.origin, Origin::Existing(_))
+    }
+}
+
+impl OverlapSettings {
+    /// The change, along with whatever it takes to resolve the overlaps it
+    /// makes, as one batch. Only overlaps involving the notes it adds or
+    /// updates are touched, and those notes win over the ones already there:
+    /// a note already there is cut off where an edited one starts, and
+    /// starts again after an edited one ends.
+    pub fn resolve(&self, notes: &Sequence, change: SequenceChange) -> SequenceChange {
+        if self.policy == OverlapPolicy::Allow && !self.monophonic {
+            return change;
+        }
+
+        let mut removed_ids = HashSet::new();
+        let mut edited = vec![];
+        flatten(change, &mut removed_ids, &mut edited);
+        let edited = last_edits(edited, &removed_ids);
+        let updated_ids: HashSet<NoteId> = edited.iter()
+            .filter_map(|(origin, _)| match origin {
+                Origin::Updated(id) => Some(*id),
+                _ => None,
+            })
+            .collect();
+
+        let mut entries: Vec<Entry> = notes.iter()
+            .filter(|(id, _note)| !removed_ids.contains(id) && !updated_ids.contains(id))
+            .map(|(id, note)| Entry { origin: Origin::Existing(id), note: note.clone(), changed: false, removed: false })
+            .collect();
+        entries.extend(edited.into_iter().map(|(origin, note)| Entry { origin, note, changed: false, removed: false }));
+
+        self.sweep(&mut entries);
+
+        let mut changes: Vec<SequenceChange> = removed_ids.into_iter().map(SequenceChange::Remove).collect();
+        for entry in entries {
+            match (entry.origin, entry.removed) {
+                (Origin::Existing(id), true) | (Origin::Updated(id), true) => changes.push(SequenceChange::Remove(id)),
+                (Origin::Existing(id), false) if entry.changed => changes.push(SequenceChange::Update(id, entry.note)),
+                (Origin::Existing(_), false) | (Origin::Added, true) => {}
+                (Origin::Updated(id), false) => changes.push(SequenceChange::Update(id, entry.note)),
+                (Origin::Added, false) => changes.push(SequenceChange::Add(entry.note)),
+            }
+        }
+        SequenceChange::Batch(changes)
+    }
+
+    /// Resolves every overlap in the sequence, as when a project is opened.
+    pub fn resolve_all(&self, notes: &Sequence) -> SequenceChange {
+        let all = notes.iter()
+            .map(|(id, note)| SequenceChange::Update(id, note.clone()))
+            .collect();
+        self.resolve(notes, SequenceChange::Batch(all))
+    }
+
+    /// Works along each voice in time, keeping the note that is sounding.
+    /// Cutting the start off a note moves it later, so it goes round again
+    /// until nothing more is cut.
+    fn sweep(&self, entries: &mut Vec<Entry>) {
+        let monophonic = self.monophonic;
+        loop {
+            // at the same start, edited notes come first so they're the ones kept
+            entries.sort_by(|a, b| {
+                let voice = match monophonic {
+                    true => Ordering::Equal,
+                    false => a.note.pitch.cmp(&b.note.pitch),
+                };
+                voice.then(a.note.tick.cmp(&b.note.tick))
+                    .then(b.edited().cmp(&a.edited()))
+                    .then(b.note.length.cmp(&a.note.length))
+            });
+
+            let mut moved = false;
+            let mut sounding: Option<usize> = None;
+            for i in 0..entries.len() {
+                if entries[i].removed {
+                    continue;
+                }
+                let current = match sounding {
+                    Some(current) => current,
+                    None => {
+                        sounding = Some(i);
+                        continue;
+                    }
+                };
+
+                let (before, after) = entries.split_at_mut(i);
+                let (held, next) = (&mut before[current], &mut after[0]);
+                let same_voice = monophonic || held.note.pitch == next.note.pitch;
+                if !same_voice || next.note.tick >= held.note.end_tick() || !(held.edited() || held.changed || next.edited()) {
+                    sounding = Some(i);
+                    continue;
+                }
+
+                if next.note.tick == held.note.tick {
+                    // a duplicate, or a chord in a monophonic line
+                    next.removed = true;
+                } else if self.policy == OverlapPolicy::Merge && held.note.pitch == next.note.pitch {
+                    edit::append(&mut held.note, &next.note);
+                    held.changed = true;
+                    next.removed = true;
+                } else if held.edited() && !next.edited() {
+                    // the note already there gives way, keeping what's left after
+                    let offset = held.note.end_tick() - next.note.tick;
+                    match offset < next.note.length {
+                        true => {
+                            next.note = edit::split_note(&next.note, offset).1;
+                            moved = true;
+                        }
+                        false => next.removed = true,
+                    }
+                    next.changed = true;
+                } else {
+                    held.note.length = next.note.tick - held.note.tick;
+                    held.changed = true;
+                    sounding = Some(i);
+                }
+            }
+
+            if !moved {
+                break;
+            }
+        }
+    }
+}
+
+fn flatten(change: SequenceChange, removed_ids: &mut HashSet<NoteId>, edited: &mut Vec<(Origin, Note)>) {
+    match change {
+        SequenceChange::Add(note) => edited.push((Origin::Added, note)),
+        SequenceChange::Remove(id) => {
+            removed_ids.insert(id);
+        }
+        SequenceChange::Update(id, note) => edited.push((Origin::Updated(id), note)),
+        SequenceChange::Batch(changes) => {
+            for change in changes {
+                flatten(change, removed_ids, edited);
+            }
+        }
+    }
+}
+
+/// Keeps only the last update to each note, and none to notes removed.
+fn last_edits(edited: Vec<(Origin, Note)>, removed_ids: &HashSet<NoteId>) -> Vec<(Origin, Note)> {
+    let last: HashMap<NoteId, usize> = edited.iter().enumerate()
+        .filter_map(|(i, (origin, _))| match origin {
+            Origin::Updated(id) => Some((*id, i)),
+            _ => None,
+        })
+        .collect();
+
+    edited.into_iter().enumerate()
+        .filter(|(i, (origin, _))| match origin {
+            Origin::Updated(id) => !removed_ids.contains(id) && last[id] == *i,
+            _ => true,
+        })
+        .map(|(_, edit)| edit)
+        .collect()
+}
+
+/// Removes notes that start together on the same pitch as another, keeping
+/// the longest.
+pub fn remove_duplicates(notes: &Sequence) -> SequenceChange {
+    let mut sorted: Vec<(NoteId, &Note)> = notes.iter().collect();
+    sorted.sort_by(|(_, a), (_, b)| {
+        a.pitch.cmp(&b.pitch)
+            .then(a.tick.cmp(&b.tick))
+            .then(b.length.cmp(&a.length))
+    });
+
+    let changes = sorted.windows(2)
+        .filter(|pair| pair[0].1.pitch == pair[1].1.pitch && pair[0].1.tick == pair[1].1.tick)
+        .map(|pair| SequenceChange::Remove(pair[1].0))
+        .collect();
+
+    SequenceChange::Batch(changes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sequence::Pitch;
+
+    fn sequence(notes: &[(i32, i32, i32)]) -> Sequence {
+        let mut sequence = Sequence::new();
+        for &(tick, semitone, length) in notes {
+            sequence.update_sequence(SequenceChange::Add(Note::new(tick, Pitch::new(semitone, 12), length)));
+        }
+        sequence
+    }
+
+    fn id_at(notes: &Sequence, tick: i32) -> NoteId {
+        notes.iter().find(|(_, note)| note.tick == tick).unwrap().0
+    }
+
+    /// (tick, semitone, length) of each note after resolving a change
+    fn resolved(settings: OverlapSettings, notes: &mut Sequence, change: SequenceChange) -> Vec<(i32, i32, i32)> {
+        let change = settings.resolve(notes, change);
+        notes.update_sequence(change);
+        let mut result: Vec<(i32, i32, i32)> = notes.iter()
+            .map(|(_, note)| (note.tick, (note.pitch.0 * 12).to_integer(), note.length))
+            .collect();
+        result.sort();
+        result
+    }
+
+    fn truncate() -> OverlapSettings {
+        OverlapSettings::default()
+    }
+
+    #[test]
+    fn sweep_truncates_note_already_sounding() {
+        let mut notes = sequence(&[(0, 0, 96)]);
+        let change = SequenceChange::Add(Note::new(48, Pitch::new(0, 12), 96));
+        assert_eq!(resolved(truncate(), &mut notes, change), vec![(0, 0, 48), (48, 0, 96)]);
+    }
+
+    #[test]
+    fn sweep_leaves_other_pitches_and_untouched_overlaps() {
+        let mut notes = sequence(&[(0, 0, 96), (48, 0, 96), (200, 0, 10)]);
+        let change = SequenceChange::Add(Note::new(24, Pitch::new(2, 12), 96));
+        assert_eq!(
+            resolved(truncate(), &mut notes, change),
+            vec![(0, 0, 96), (24, 2, 96), (48, 0, 96), (200, 0, 10)],
+        );
+    }
+
+    #[test]
+    fn sweep_lets_edited_note_win_over_later_one() {
+        let mut notes = sequence(&[(0, 0, 48), (96, 0, 48), (200, 0, 48)]);
+        let id = id_at(&notes, 0);
+
+        // resized across the start of the next note, which starts after it instead
+        let change = SequenceChange::Update(id, Note::new(0, Pitch::new(0, 12), 120));
+        assert_eq!(resolved(truncate(), &mut notes, change), vec![(0, 0, 120), (120, 0, 24), (200, 0, 48)]);
+
+        // and covering it removes it
+        let change = SequenceChange::Update(id, Note::new(0, Pitch::new(0, 12), 210));
+        assert_eq!(resolved(truncate(), &mut notes, change), vec![(0, 0, 210), (210, 0, 38)]);
+    }
+
+    #[test]
+    fn sweep_merges() {
+        let settings = OverlapSettings { policy: OverlapPolicy::Merge, monophonic: false };
+        let mut notes = sequence(&[(0, 0, 48), (96, 0, 48)]);
+        let change = SequenceChange::Add(Note::new(24, Pitch::new(0, 12), 96));
+        assert_eq!(resolved(settings, &mut notes, change), vec![(0, 0, 144)]);
+    }
+
+    #[test]
+    fn sweep_keeps_one_note_at_a_time_when_monophonic() {
+        let settings = OverlapSettings { policy: OverlapPolicy::Allow, monophonic: true };
+        let mut notes = sequence(&[(0, 0, 96)]);
+        let change = SequenceChange::Batch(vec![
+            SequenceChange::Add(Note::new(48, Pitch::new(3, 12), 48)),
+            SequenceChange::Add(Note::new(48, Pitch::new(7, 12), 24)),
+        ]);
+        assert_eq!(resolved(settings, &mut notes, change), vec![(0, 0, 48), (48, 3, 48)]);
+    }
+
+    #[test]
+    fn flatten_keeps_last_update_and_drops_removed() {
+        let notes = sequence(&[(0, 0, 48), (96, 0, 48)]);
+        let (first, second) = (id_at(&notes, 0), id_at(&notes, 96));
+        let change = SequenceChange::Batch(vec![
+            SequenceChange::Update(first, Note::new(0, Pitch::new(0, 12), 24)),
+            SequenceChange::Batch(vec![SequenceChange::Update(first, Note::new(0, Pitch::new(0, 12), 36))]),
+            SequenceChange::Update(second, Note::new(96, Pitch::new(0, 12), 24)),
+            SequenceChange::Remove(second),
+            SequenceChange::Add(Note::new(200, Pitch::new(0, 12), 24)),
+        ]);
+
+        let mut removed_ids = HashSet::new();
+        let mut edited = vec![];
+        flatten(change, &mut removed_ids, &mut edited);
+        let edited = last_edits(edited, &removed_ids);
+
+        assert_eq!(removed_ids.into_iter().collect::<Vec<_>>(), vec![second]);
+        assert_eq!(edited.len(), 2);
+        assert_eq!(edited[0].0, Origin::Updated(first));
+        assert_eq!(edited[0].1.length, 36);
+        assert_eq!(edited[1].0, Origin::Added);
+    }
+
+    #[test]
+    fn remove_duplicates_keeps_longest() {
+        let mut notes = sequence(&[(0, 0, 24), (0, 0, 48), (0, 2, 24), (48, 0, 24)]);
+        let change = remove_duplicates(&notes);
+        notes.update_sequence(change);
+
+        let mut left: Vec<(i32, i32)> = notes.iter().map(|(_, note)| (note.tick, note.length)).collect();
+        left.sort();
+        assert_eq!(left, vec![(0, 24), (0, 48), (48, 24)]);
+    }
+}
@@ -0,0 +1,73 @@
+use std::{fs, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::audio::{EffectState, SavedEffect, SynthEffects};
+use crate::automation::{Automation, AutomationLane};
+use crate::overlap::OverlapSettings;
+use crate::sequence::{Note, Sequence, SequenceChange};
+
+/// Everything saved to a project file.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Project {
+    pub notes: Vec<Note>,
+    #[serde(default)]
+    pub synth_effects: SynthEffects,
+    #[serde(default)]
+    pub automation: Vec<AutomationLane>,
+    #[serde(default)]
+    pub overlap: OverlapSettings,
+    /// `None` in projects saved before the racks were, which keep the
+    /// default racks
+    #[serde(default)]
+    pub master_rack: Option<Vec<SavedEffect>>,
+    #[serde(default)]
+    pub send_rack: Option<Vec<SavedEffect>>,
+}
+
+impl Project {
+    pub fn new(
+        sequence: &Sequence,
+        automation: &Automation,
+        synth_effects: SynthEffects,
+        overlap: OverlapSettings,
+        master_effects: &[EffectState],
+        send_effects: &[EffectState],
+    ) -> Self {
+        let mut notes: Vec<Note> = sequence.iter().map(|(_, note)| note.clone()).collect();
+        notes.sort_by(|a, b| a.tick.cmp(&b.tick).then_with(|| a.pitch.cmp(&b.pitch)));
+
+        Self {
+            notes,
+            synth_effects,
+            automation: automation.lanes().to_vec(),
+            overlap,
+            master_rack: Some(master_effects.iter().map(SavedEffect::from).collect()),
+            send_rack: Some(send_effects.iter().map(SavedEffect::from).collect()),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let text = ron::ser::to_string_pretty(self, Default::default())
+            .map_err(|err| format!("couldn't serialize project: {}", err))?;
+        fs::write(path, text).map_err(|err| format!("couldn't write {}: {}", path.display(), err))
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|err| format!("couldn't read {}: {}", path.display(), err))?;
+        ron::from_str(&text).map_err(|err| format!("couldn't parse {}: {}", path.display(), err))
+    }
+
+    /// Replaces the notes in `sequence`. They're removed one by one rather
+    /// than starting a new sequence, so ids held elsewhere go stale.
+    pub fn replace_notes(&self, sequence: &mut Sequence) {
+        let ids: Vec<_> = sequence.iter().map(|(id, _)| id).collect();
+        for id in ids {
+            sequence.update_sequence(SequenceChange::Remove(id));
+        }
+        for note in &self.notes {
+            sequence.update_sequence(SequenceChange::Add(note.clone()));
+        }
+    }
+}
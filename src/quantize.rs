@@ -0,0 +1,285 @@
+use std::cmp::max;
+
+use crate::sequence::{Note, NoteId, SequenceChange};
+use crate::widgets::tick_grid::TickGrid;
+
+/// Moves notes that have already been placed towards the tick grid.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QuantizeSettings {
+    pub starts: bool,
+    /// moves the end without the start, so the length changes
+    pub ends: bool,
+    /// rounds lengths to whole grid steps, unless ends are quantized
+    pub lengths: bool,
+    /// how far towards the grid, from 0 to 1
+    pub strength: f32,
+    /// how late every other grid line is pushed, from 0 to 1 of half a step
+    pub swing: f32,
+    /// only ticks within this fraction of a grid step of their line move
+    pub window: f32,
+}
+
+impl Default for QuantizeSettings {
+    fn default() -> Self {
+        Self {
+            starts: true,
+            ends: false,
+            lengths: false,
+            strength: 1.0,
+            swing: 0.0,
+            window: 1.0,
+        }
+    }
+}
+
+impl QuantizeSettings {
+    /// Where a tick moves to.
+    pub fn snap(&self, tick: i32, grid: &dyn TickGrid) -> i32 {
+        let step = max(1, grid.grid_size(tick));
+        let nearest = grid.quantize_tick(tick);
+
+        // swing can put a neighbouring line closer
+        let line = [nearest - step, nearest, nearest + step].iter()
+            .map(|&line| line + self.swing_offset(line, step))
+            .min_by_key(|&line| (line - tick).abs())
+            .unwrap();
+
+        let offset = line - tick;
+        match offset.abs() as f32 > self.window * step as f32 {
+            true => tick,
+            false => tick + (offset as f32 * self.strength).round() as i32,
+        }
+    }
+
+    fn swing_offset(&self, line: i32, step: i32) -> i32 {
+        match line.div_euclid(step).rem_euclid(2) {
+            0 => 0,
+            _ => (self.swing * step as f32 / 2.0).round() as i32,
+        }
+    }
+
+    pub fn quantize(&self, note: &Note, grid: &dyn TickGrid) -> Note {
+        let tick = match self.starts {
+            true => max(0, self.snap(note.tick, grid)),
+            false => note.tick,
+        };
+
+        let length = if self.ends {
+            self.snap(note.end_tick(), grid) - tick
+        } else if self.lengths {
+            let step = max(1, grid.grid_size(tick));
+            let whole = max(1, (note.length as f32 / step as f32).round() as i32) * step;
+            note.length + ((whole - note.length) as f32 * self.strength).round() as i32
+        } else {
+            note.length
+        };
+
+        Note { tick, length: max(1, length), ..note.clone() }
+    }
+}
+
+/// Random nudges to notes' timing and pressure.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HumanizeSettings {
+    /// the most ticks a start moves either way
+    pub timing: i32,
+    /// the most ticks a length changes either way
+    pub length: i32,
+    /// the most a pressure envelope moves up or down. Notes have no
+    /// velocity, and ones without an envelope are left alone.
+    pub pressure: f32,
+    pub seed: u32,
+}
+
+impl Default for HumanizeSettings {
+    fn default() -> Self {
+        Self {
+            timing: 4,
+            length: 0,
+            pressure: 0.0,
+            seed: 1,
+        }
+    }
+}
+
+impl HumanizeSettings {
+    /// The same seed gives the same nudges to the same notes, whatever
+    /// order they come in.
+    pub fn humanize(&self, notes: &[(NoteId, &Note)]) -> Vec<SequenceChange> {
+        let mut notes = notes.to_vec();
+        notes.sort_by(|(_, a), (_, b)| a.tick.cmp(&b.tick).then_with(|| a.pitch.cmp(&b.pitch)));
+
+        let mut random = Random::new(self.seed);
+        notes.into_iter()
+            .map(|(id, note)| {
+                let mut new_note = note.clone();
+                new_note.tick = max(0, note.tick + (random.next() * self.timing as f32).round() as i32);
+                new_note.length = max(1, note.length + (random.next() * self.length as f32).round() as i32);
+
+                let pressure = random.next() * self.pressure;
+                for point in &mut new_note.expression.pressure.points {
+                    point.1 = (point.1 + pressure).max(0.0).min(1.0);
+                }
+
+                SequenceChange::Update(id, new_note)
+            })
+            .collect()
+    }
+}
+
+/// xorshift, from -1 to 1
+struct Random(u32);
+
+impl Random {
+    fn new(seed: u32) -> Self {
+        match seed ^ 0x2545f491 {
+            0 => Random(1),
+            state => Random(state),
+        }
+    }
+
+    fn next(&mut self) -> f32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        (self.0 as f64 / u32::MAX as f64 * 2.0 - 1.0) as f32
+    }
+}
+
+/// How far played notes fall from the grid, taken from one passage to give
+/// another the same feel.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Groove {
+    /// whole bars, in ticks
+    pub length: i32,
+    /// (grid line from the start of the groove's first bar, average ticks
+    /// off it), in order
+    pub offsets: Vec<(i32, i32)>,
+}
+
+impl Groove {
+    pub fn extract(notes: &[&Note], grid: &dyn TickGrid) -> Option<Groove> {
+        let start = notes.iter().map(|note| note.tick).min()?;
+        let end = notes.iter().map(|note| note.tick).max()?;
+        let bar = max(1, grid.bar_length());
+        let length = (end.div_euclid(bar) - start.div_euclid(bar) + 1) * bar;
+        let first_bar = start.div_euclid(bar) * bar;
+
+        // line, total offset and count of the notes near each line
+        let mut lines: Vec<(i32, i32, i32)> = vec![];
+        for note in notes {
+            let line = grid.quantize_tick(note.tick);
+            let position = (line - first_bar).rem_euclid(length);
+            match lines.iter_mut().find(|(at, _, _)| *at == position) {
+                Some((_, total, count)) => {
+                    *total += note.tick - line;
+                    *count += 1;
+                }
+                None => lines.push((position, note.tick - line, 1)),
+            }
+        }
+
+        let mut offsets: Vec<(i32, i32)> = lines.into_iter()
+            .map(|(position, total, count)| (position, (total as f32 / count as f32).round() as i32))
+            .collect();
+        offsets.sort();
+
+        Some(Groove { length, offsets })
+    }
+
+    /// Moves notes towards their grid lines plus the groove's offsets there,
+    /// or just the lines where the groove has none. The groove starts at the
+    /// bar of the first note.
+    pub fn apply(&self, notes: &[(NoteId, &Note)], grid: &dyn TickGrid, strength: f32) -> Vec<SequenceChange> {
+        let start = match notes.iter().map(|(_, note)| note.tick).min() {
+            Some(start) => start,
+            None => return vec![],
+        };
+        let bar = max(1, grid.bar_length());
+        let first_bar = start.div_euclid(bar) * bar;
+
+        notes.iter()
+            .map(|(id, note)| {
+                let line = grid.quantize_tick(note.tick);
+                let position = (line - first_bar).rem_euclid(self.length);
+                let target = match self.offsets.iter().find(|(at, _)| *at == position) {
+                    Some((_, offset)) => line + offset,
+                    None => line,
+                };
+
+                let tick = note.tick + ((target - note.tick) as f32 * strength).round() as i32;
+                SequenceChange::Update(*id, Note { tick: max(0, tick), ..(*note).clone() })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sequence::{Pitch, Sequence};
+    use crate::widgets::tick_grid::SimpleGrid;
+
+    const GRID: SimpleGrid = SimpleGrid { ticks_per_16th: 24 };
+
+    fn settings(swing: f32, window: f32, strength: f32) -> QuantizeSettings {
+        QuantizeSettings { swing, window, strength, ..QuantizeSettings::default() }
+    }
+
+    #[test]
+    fn snap_moves_to_nearest_line() {
+        assert_eq!(settings(0.0, 1.0, 1.0).snap(26, &GRID), 24);
+        assert_eq!(settings(0.0, 1.0, 1.0).snap(40, &GRID), 48);
+        // halfway
+        assert_eq!(settings(0.0, 1.0, 0.5).snap(10, &GRID), 5);
+    }
+
+    #[test]
+    fn snap_swings_every_other_line() {
+        // the odd line moves 6 ticks later, so it's now the nearest
+        assert_eq!(settings(0.5, 1.0, 1.0).snap(26, &GRID), 30);
+        // even lines stay put
+        assert_eq!(settings(0.5, 1.0, 1.0).snap(46, &GRID), 48);
+    }
+
+    #[test]
+    fn snap_leaves_ticks_outside_window() {
+        // a quarter step is 6 ticks
+        assert_eq!(settings(0.0, 0.25, 1.0).snap(10, &GRID), 10);
+        assert_eq!(settings(0.0, 0.25, 1.0).snap(4, &GRID), 0);
+    }
+
+    fn ticks(changes: Vec<SequenceChange>) -> Vec<i32> {
+        let mut ticks: Vec<i32> = changes.into_iter()
+            .filter_map(|change| match change {
+                SequenceChange::Update(_, note) => Some(note.tick),
+                _ => None,
+            })
+            .collect();
+        ticks.sort();
+        ticks
+    }
+
+    #[test]
+    fn groove_follows_the_bars_it_was_taken_from() {
+        let bar = 384;
+        // the third and fourth bars: one note on the first line, one late and one early
+        let played: Vec<Note> = [2 * bar, 2 * bar + 96 + 5, 3 * bar + 192 - 3].iter()
+            .map(|&tick| Note::new(tick, Pitch::new(0, 1), 24))
+            .collect();
+        let groove = Groove::extract(&played.iter().collect::<Vec<_>>(), &GRID).unwrap();
+        assert_eq!(groove.length, 2 * bar);
+        assert_eq!(groove.offsets, vec![(0, 0), (96, 5), (bar + 192, -3)]);
+
+        // applied from the fourth bar, an odd one out of the groove's two
+        let mut sequence = Sequence::new();
+        for &tick in [3 * bar + 96, 4 * bar + 192, 3 * bar + 48].iter() {
+            sequence.update_sequence(SequenceChange::Add(Note::new(tick, Pitch::new(0, 1), 24)));
+        }
+        let notes: Vec<(NoteId, &Note)> = sequence.iter().collect();
+        assert_eq!(
+            ticks(groove.apply(&notes, &GRID, 1.0)),
+            vec![3 * bar + 48, 3 * bar + 96 + 5, 4 * bar + 192 - 3],
+        );
+    }
+}
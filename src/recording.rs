@@ -0,0 +1,54 @@
+use std::cmp::max;
+
+use crate::sequence::{Note, NoteId, Sequence, SequenceChange};
+use crate::widgets::tick_grid::TickGrid;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RecordMode {
+    /// recorded notes are layered over the existing ones
+    Overdub,
+    /// existing notes are removed as the playhead passes over them, from
+    /// the first note played
+    Replace,
+}
+
+pub struct RecordSettings {
+    pub quantize: bool,
+    pub mode: RecordMode,
+}
+
+impl Default for RecordSettings {
+    fn default() -> Self {
+        Self {
+            quantize: false,
+            mode: RecordMode::Overdub,
+        }
+    }
+}
+
+impl RecordSettings {
+    pub fn recorded_note(&self, note: Note, tick_grid: &dyn TickGrid) -> Note {
+        if !self.quantize {
+            return note;
+        }
+
+        let tick = tick_grid.quantize_tick(note.tick);
+        Note {
+            tick,
+            length: max(1, note.end_tick() - tick),
+            ..note
+        }
+    }
+
+    /// Removes notes starting in the range the playhead just swept over,
+    /// other than those in `take`, the ones this take recorded.
+    pub fn replaced_notes(&self, notes: &Sequence, from_tick: i32, to_tick: i32, take: &[NoteId]) -> Vec<SequenceChange> {
+        match self.mode {
+            RecordMode::Overdub => vec![],
+            RecordMode::Replace => notes.get_notes_in_range(from_tick, to_tick).into_iter()
+                .filter(|(id, _note)| !take.contains(id))
+                .map(|(id, _note)| SequenceChange::Remove(id))
+                .collect(),
+        }
+    }
+}
@@ -1,4 +1,14 @@
-use iced_native::{Point, Rectangle};
+use std::fmt::{self, Display};
+
+use iced_native::{Point, Rectangle, Vector};
+use iced_native::mouse::ScrollDelta;
+
+/// how far one click of a wheel moves, in pixels
+const LINE_PIXELS: f32 = 40.0;
+/// wheel movement, in pixels, that zooms by a factor of two
+const ZOOM_PIXELS: f32 = 240.0;
+/// the view can't be zoomed in past this much of the content
+const MIN_VIEW_PROPORTION: f32 = 1.0 / 512.0;
 
 pub struct ScrollScaleAxis {
     pub view_start: f32,
@@ -44,6 +54,85 @@ impl ScrollScaleAxis {
     pub fn inner_to_screen(&self, pos: f32, bounds_offset: f32, bounds_size: f32) -> f32 {
         (pos - self.scroll()) * self.scale(bounds_size) + bounds_offset
     }
+
+    /// The view moved along with `pixels` of wheel movement.
+    pub fn scrolled(&self, pixels: f32, bounds_size: f32) -> ScrollScaleAxisChange {
+        let delta = -pixels / self.scale(bounds_size);
+        self.clamped(self.view_start + delta, self.view_end + delta)
+    }
+
+    /// The view zoomed in by `pixels` of wheel movement, keeping what's under
+    /// `pos` still.
+    pub fn zoomed(&self, pixels: f32, pos: f32, bounds_offset: f32, bounds_size: f32) -> ScrollScaleAxisChange {
+        let anchor = self.screen_to_inner(pos, bounds_offset, bounds_size);
+        let position = (anchor - self.view_start) / self.view_width();
+        let width = (self.view_width() * 2f32.powf(-pixels / ZOOM_PIXELS))
+            .max(self.content_size * MIN_VIEW_PROPORTION)
+            .min(self.content_size);
+
+        let start = anchor - width * position;
+        self.clamped(start, start + width)
+    }
+
+    /// Where the view should move to keep `position` in sight, if anywhere.
+    pub fn follow(&self, position: f32, mode: FollowMode) -> Option<ScrollScaleAxisChange> {
+        let width = self.view_width();
+        let start = match mode {
+            FollowMode::Off => return None,
+            FollowMode::Page if position < self.view_start || position >= self.view_end => position,
+            FollowMode::Page => return None,
+            // holds the position in the middle once it gets there
+            FollowMode::Continuous if position < self.view_start || position > self.view_start + width / 2.0 => {
+                position - width / 2.0
+            }
+            FollowMode::Continuous => return None,
+        };
+
+        let change = self.clamped(start, start + width);
+        match change {
+            ScrollScaleAxisChange::View(start, _) if start == self.view_start => None,
+            change => Some(change),
+        }
+    }
+
+    fn clamped(&self, start: f32, end: f32) -> ScrollScaleAxisChange {
+        let width = (end - start).min(self.content_size);
+        let start = start.max(self.content_start).min(self.content_start + self.content_size - width);
+        ScrollScaleAxisChange::View(start, start + width)
+    }
+}
+
+/// Wheel movement in pixels, whether it came from a mouse wheel's lines or a
+/// trackpad.
+pub fn wheel_pixels(delta: ScrollDelta) -> Vector {
+    match delta {
+        ScrollDelta::Lines { x, y } => Vector::new(x * LINE_PIXELS, y * LINE_PIXELS),
+        ScrollDelta::Pixels { x, y } => Vector::new(x, y),
+    }
+}
+
+/// How the view keeps up with the playhead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FollowMode {
+    Off,
+    /// turns to the next page when the playhead goes off the end
+    Page,
+    /// scrolls along under it
+    Continuous,
+}
+
+impl FollowMode {
+    pub const ALL: [FollowMode; 3] = [FollowMode::Off, FollowMode::Page, FollowMode::Continuous];
+}
+
+impl Display for FollowMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FollowMode::Off => write!(f, "Don't follow"),
+            FollowMode::Page => write!(f, "Follow by page"),
+            FollowMode::Continuous => write!(f, "Follow continuously"),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -51,6 +140,8 @@ pub enum ScrollScaleAxisChange {
     ContentSize(f32),
     Left(f32),
     Right(f32),
+    /// both ends at once
+    View(f32, f32),
 }
 
 pub struct ScrollZoomState {
@@ -82,3 +173,74 @@ impl ScrollZoomState {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// a 100 pixel wide view of the first 100 of 1000 units
+    fn axis(view_start: f32) -> ScrollScaleAxis {
+        ScrollScaleAxis::new(view_start, 100.0, 0.0, 1000.0)
+    }
+
+    fn view(change: ScrollScaleAxisChange) -> (f32, f32) {
+        match change {
+            ScrollScaleAxisChange::View(start, end) => (start, end),
+            change => panic!("expected a view change, got {:?}", change),
+        }
+    }
+
+    fn assert_view(change: ScrollScaleAxisChange, start: f32, end: f32) {
+        let (actual_start, actual_end) = view(change);
+        assert!((actual_start - start).abs() < 1e-3, "start {} != {}", actual_start, start);
+        assert!((actual_end - end).abs() < 1e-3, "end {} != {}", actual_end, end);
+    }
+
+    #[test]
+    fn zoom_keeps_the_point_under_the_cursor_still() {
+        let axis = axis(0.0);
+        let change = axis.zoomed(ZOOM_PIXELS, 25.0, 0.0, 100.0);
+        assert_view(change, 12.5, 62.5);
+
+        let (start, end) = view(change);
+        let zoomed = ScrollScaleAxis::new(start, end - start, 0.0, 1000.0);
+        assert!((zoomed.screen_to_inner(25.0, 0.0, 100.0) - 25.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn zoom_out_is_clamped_to_the_content() {
+        assert_view(axis(0.0).zoomed(-ZOOM_PIXELS, 50.0, 0.0, 100.0), 0.0, 200.0);
+        assert_view(axis(0.0).zoomed(-ZOOM_PIXELS * 20.0, 50.0, 0.0, 100.0), 0.0, 1000.0);
+    }
+
+    #[test]
+    fn zoom_in_stops_at_the_smallest_view() {
+        let (start, end) = view(axis(0.0).zoomed(ZOOM_PIXELS * 20.0, 0.0, 0.0, 100.0));
+        assert_eq!(start, 0.0);
+        assert!((end - 1000.0 * MIN_VIEW_PROPORTION).abs() < 1e-3);
+    }
+
+    #[test]
+    fn scroll_is_clamped_at_both_ends() {
+        assert_view(axis(0.0).scrolled(50.0, 100.0), 0.0, 100.0);
+        assert_view(axis(0.0).scrolled(-50.0, 100.0), 50.0, 150.0);
+        assert_view(axis(880.0).scrolled(-50.0, 100.0), 900.0, 1000.0);
+    }
+
+    #[test]
+    fn page_follow_turns_the_page_only_off_the_end() {
+        let axis = axis(0.0);
+        assert!(axis.follow(50.0, FollowMode::Page).is_none());
+        assert_view(axis.follow(150.0, FollowMode::Page).unwrap(), 150.0, 250.0);
+        assert_view(axis.follow(990.0, FollowMode::Page).unwrap(), 900.0, 1000.0);
+        assert!(axis.follow(150.0, FollowMode::Off).is_none());
+    }
+
+    #[test]
+    fn continuous_follow_holds_the_position_in_the_middle() {
+        let axis = axis(0.0);
+        assert!(axis.follow(40.0, FollowMode::Continuous).is_none());
+        assert_view(axis.follow(60.0, FollowMode::Continuous).unwrap(), 10.0, 110.0);
+        assert!(self::axis(900.0).follow(990.0, FollowMode::Continuous).is_none());
+    }
+}
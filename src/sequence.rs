@@ -6,6 +6,9 @@ use num_bigint::BigInt;
 use slotmap::{new_key_type, SlotMap};
 use slotmap::basic::Iter;
 use num_traits::Zero;
+use serde::{Deserialize, Serialize};
+
+use crate::envelope::{Envelope, EnvelopePoint};
 
 new_key_type! {
     pub struct NoteId;
@@ -23,6 +26,8 @@ pub enum SequenceChange {
     Add(Note),
     Remove(NoteId),
     Update(NoteId, Note),
+    /// applied in order, as one edit
+    Batch(Vec<SequenceChange>),
 }
 
 impl Sequence {
@@ -70,6 +75,11 @@ impl Sequence {
                     self.note_starts.insert(start_idx, (new_note.tick, id));
                 }
             },
+            SequenceChange::Batch(changes) => {
+                for change in changes {
+                    self.update_sequence(change);
+                }
+            },
         }
     }
 
@@ -94,6 +104,29 @@ impl Sequence {
         self.note_starts[start_idx..end_idx].iter().map(|(_tick, id)| (*id, self.slotmap.get(*id).unwrap().clone())).collect()
     }
 
+    /// notes that start before the tick and are still sounding at it
+    pub fn get_notes_overlapping(&self, tick: i32) -> Vec<(NoteId, Note)> {
+        let end_idx = self.note_starts
+            .binary_search_by_key(&tick, |(tick, _id)| *tick)
+            .unwrap_or_else(|idx| idx);
+
+        self.note_starts[..end_idx].iter()
+            .filter(|(start, _id)| *start < tick)
+            .map(|(_tick, id)| (*id, self.slotmap.get(*id).unwrap()))
+            .filter(|(_id, note)| note.end_tick() > tick)
+            .map(|(id, note)| (id, note.clone()))
+            .collect()
+    }
+
+    /// where the first note starting after the tick starts
+    pub fn next_start(&self, tick: i32) -> Option<i32> {
+        let idx = self.note_starts
+            .binary_search_by_key(&(tick + 1), |(tick, _id)| *tick)
+            .unwrap_or_else(|idx| idx);
+
+        self.note_starts.get(idx).map(|(start, _id)| *start)
+    }
+
     pub fn last_added(&self) -> Option<(NoteId, &Note)> {
         self.last_added.and_then(|key| {
             self.slotmap.get(key).map(|note| (key, note))
@@ -109,16 +142,52 @@ impl Sequence {
     }
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Note {
     pub tick: i32,
     pub pitch: Pitch,
     pub length: i32,
+    #[serde(default)]
+    pub pitch_envelope: PitchEnvelope,
+    #[serde(default)]
+    pub expression: NoteExpression,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct Pitch(pub Rational32);
 
+/// Bends a note away from its pitch. Points are (tick from the note's start,
+/// interval from its pitch), and the bend is linear between them.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct PitchEnvelope {
+    pub points: Envelope<(i32, Pitch)>,
+    pub vibrato: Option<Vibrato>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Vibrato {
+    /// ticks from the note's start, after which it fades in over a period
+    pub delay: i32,
+    /// in ticks
+    pub period: i32,
+    /// in octaves either side of the bend
+    pub depth: f32,
+}
+
+/// Per-note pressure and timbre (CC74), played the way MPE synths expect.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct NoteExpression {
+    pub pressure: ExpressionEnvelope,
+    pub timbre: ExpressionEnvelope,
+}
+
+/// Points are (tick from the note's start, value from 0 to 1), and the value
+/// is linear between them.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct ExpressionEnvelope {
+    pub points: Envelope<(i32, f32)>,
+}
+
 impl Add for Pitch {
     type Output = Self;
 
@@ -186,13 +255,105 @@ impl Pitch {
         (rounded as u32, pitch_bend)
     }
 
+    /// the bend that takes `key` to this pitch moved by `offset` octaves,
+    /// clamped to the bend range
+    pub fn midi_bend_from(&self, key: u32, offset: f32, pitch_bend_range: f32) -> u32 {
+        let midi_pitch = (self.to_f32() + offset) * 12.0 + 69.0;
+        ((midi_pitch - key as f32) * 8192.0 / pitch_bend_range + 8192.0).max(0.0).min(16383.0) as u32
+    }
+
     pub fn to_f32(&self) -> f32 {
         self.0.to_f32().unwrap()
     }
 }
 
 impl Note {
+    pub fn new(tick: i32, pitch: Pitch, length: i32) -> Self {
+        Self { tick, pitch, length, pitch_envelope: PitchEnvelope::default(), expression: NoteExpression::default() }
+    }
+
     pub fn end_tick(&self) -> i32 {
         self.tick + self.length
     }
-}
\ No newline at end of file
+}
+
+impl NoteExpression {
+    pub fn is_empty(&self) -> bool {
+        self.pressure.is_empty() && self.timbre.is_empty()
+    }
+}
+
+impl ExpressionEnvelope {
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    /// the value `tick` ticks into the note, if there are any points
+    pub fn value_at(&self, tick: f32) -> Option<f32> {
+        self.points.value_at(tick)
+    }
+
+    /// Puts a point at `to_tick`, replacing any drawn over since `from_tick`.
+    pub fn draw(&mut self, from_tick: i32, to_tick: i32, value: f32) {
+        self.points.draw(from_tick, (to_tick, value));
+    }
+}
+
+impl PitchEnvelope {
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty() && self.vibrato.is_none()
+    }
+
+    /// the bend in octaves, `tick` ticks into the note
+    pub fn offset_at(&self, tick: f32) -> f32 {
+        let bend = self.points.value_at(tick).unwrap_or(0.0);
+
+        let vibrato = match self.vibrato {
+            Some(vibrato) if tick >= vibrato.delay as f32 && vibrato.period > 0 => {
+                let cycles = (tick - vibrato.delay as f32) / vibrato.period as f32;
+                let fade = cycles.min(1.0);
+                (cycles * std::f32::consts::TAU).sin() * vibrato.depth * fade
+            }
+            _ => 0.0,
+        };
+
+        bend + vibrato
+    }
+
+    /// Puts a point at `to_tick`, replacing any drawn over since `from_tick`.
+    pub fn draw(&mut self, from_tick: i32, to_tick: i32, offset: Pitch) {
+        self.points.draw(from_tick, (to_tick, offset));
+    }
+
+    /// The bend exactly `tick` ticks into the note, if there are any points.
+    pub fn bend_at(&self, tick: i32) -> Option<Pitch> {
+        self.points.segment_at(tick as f32).map(|((from_tick, from), (to_tick, to), _)| match from_tick == to_tick {
+            true => from.clone(),
+            false => {
+                let position = Rational32::new(tick - from_tick, to_tick - from_tick);
+                Pitch::from_octave(from.0 + (to.0 - from.0) * position)
+            }
+        })
+    }
+
+    /// Replaces the points with a straight slide from the note's pitch.
+    pub fn slide(&mut self, from_tick: i32, to_tick: i32, offset: Pitch) {
+        self.points = vec![(from_tick, Pitch::default()), (to_tick, offset)].into();
+        if to_tick < from_tick {
+            self.points.reverse();
+        }
+    }
+}
+
+/// bends are in octaves
+impl EnvelopePoint for (i32, Pitch) {
+    type Value = f32;
+
+    fn tick(&self) -> i32 {
+        self.0
+    }
+
+    fn towards(&self, next: &Self, position: f32) -> f32 {
+        self.1.to_f32() + (next.1.to_f32() - self.1.to_f32()) * position
+    }
+}
@@ -0,0 +1,157 @@
+use std::cmp::max;
+
+use num_rational::Rational32;
+
+use crate::sequence::{Note, NoteId, Pitch, SequenceChange};
+use crate::widgets::pitch_grid::PitchGrid;
+
+/// Operations over a selection of notes, the way a sequencer's MIDI
+/// transforms work.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Transform {
+    Transpose(Pitch),
+    /// by steps of the pitch grid's scale, skipping its black lines
+    DiatonicTranspose(i32),
+    /// mirrors pitches and bends around a pitch
+    Invert(Pitch),
+    /// reverses the notes in time, within the ticks they cover
+    Retrograde,
+    /// scales times and lengths, keeping the anchor still, or the start of
+    /// the notes if there isn't one
+    Stretch { factor: Rational32, anchor: Option<i32> },
+}
+
+impl Transform {
+    pub fn apply(&self, notes: &[(NoteId, &Note)], pitch_grid: &dyn PitchGrid) -> Vec<SequenceChange> {
+        let start = notes.iter().map(|(_, note)| note.tick).min().unwrap_or_default();
+        let end = notes.iter().map(|(_, note)| note.end_tick()).max().unwrap_or_default();
+
+        notes.iter()
+            .map(|&(id, note)| {
+                let mut new_note = note.clone();
+                match self {
+                    Transform::Transpose(interval) => {
+                        new_note.pitch = note.pitch.clone() + interval.clone();
+                    }
+                    Transform::DiatonicTranspose(steps) => {
+                        for _ in 0..steps.abs() {
+                            new_note.pitch = pitch_grid.scale_step(new_note.pitch, *steps > 0);
+                        }
+                    }
+                    Transform::Invert(axis) => {
+                        new_note.pitch = axis.clone() + axis.clone() - note.pitch.clone();
+                        for point in &mut new_note.pitch_envelope.points {
+                            point.1 = -point.1.clone();
+                        }
+                    }
+                    Transform::Retrograde => {
+                        new_note.tick = start + end - note.end_tick();
+                        map_ticks(&mut new_note, |tick| note.length - tick, true);
+                    }
+                    Transform::Stretch { factor, anchor } => {
+                        let anchor = anchor.unwrap_or(start);
+                        let scale = |tick: i32| (Rational32::from_integer(tick) * *factor).round().to_integer();
+
+                        new_note.tick = max(0, anchor + scale(note.tick - anchor));
+                        new_note.length = max(1, anchor + scale(note.end_tick() - anchor) - new_note.tick);
+                        map_ticks(&mut new_note, scale, false);
+                        if let Some(vibrato) = &mut new_note.pitch_envelope.vibrato {
+                            vibrato.delay = scale(vibrato.delay);
+                            vibrato.period = max(1, scale(vibrato.period));
+                        }
+                    }
+                }
+                SequenceChange::Update(id, new_note)
+            })
+            .collect()
+    }
+}
+
+/// Moves the points of a note's envelopes, which are in ticks from its
+/// start, keeping them sorted.
+fn map_ticks(note: &mut Note, map: impl Fn(i32) -> i32, reverses: bool) {
+    for point in &mut note.pitch_envelope.points {
+        point.0 = map(point.0);
+    }
+    for point in note.expression.pressure.points.iter_mut().chain(&mut note.expression.timbre.points) {
+        point.0 = map(point.0);
+    }
+
+    if reverses {
+        note.pitch_envelope.points.reverse();
+        note.expression.pressure.points.reverse();
+        note.expression.timbre.points.reverse();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sequence::{Sequence, Vibrato};
+    use crate::widgets::pitch_grid::{LineType, TetGrid};
+
+    /// the notes sorted by tick after applying a transform to all of them
+    fn transformed(transform: Transform, notes: Vec<Note>) -> Vec<Note> {
+        let mut sequence = Sequence::new();
+        for note in notes {
+            sequence.update_sequence(SequenceChange::Add(note));
+        }
+
+        let grid = TetGrid::new(12, vec![LineType::White; 12]);
+        let changes = {
+            let selected: Vec<(NoteId, &Note)> = sequence.iter().collect();
+            transform.apply(&selected, &grid)
+        };
+        sequence.update_sequence(SequenceChange::Batch(changes));
+
+        let mut result: Vec<Note> = sequence.iter().map(|(_, note)| note.clone()).collect();
+        result.sort_by_key(|note| note.tick);
+        result
+    }
+
+    fn bent_note(tick: i32, length: i32) -> Note {
+        let mut note = Note::new(tick, Pitch::new(0, 12), length);
+        note.pitch_envelope.points = vec![(0, Pitch::new(0, 12)), (64, Pitch::new(1, 12))].into();
+        note.pitch_envelope.vibrato = Some(Vibrato { delay: 32, period: 24, depth: 0.01 });
+        note.expression.pressure.points = vec![(0, 0.2), (48, 1.0)].into();
+        note.expression.timbre.points = vec![(32, 0.5)].into();
+        note
+    }
+
+    #[test]
+    fn retrograde_mirrors_notes_and_reverses_their_envelopes() {
+        let result = transformed(Transform::Retrograde, vec![bent_note(0, 96), Note::new(96, Pitch::new(7, 12), 48)]);
+
+        assert_eq!(result[0], Note::new(0, Pitch::new(7, 12), 48));
+        let note = &result[1];
+        assert_eq!((note.tick, note.length), (48, 96));
+        assert_eq!(*note.pitch_envelope.points, vec![(32, Pitch::new(1, 12)), (96, Pitch::new(0, 12))]);
+        assert_eq!(*note.expression.pressure.points, vec![(48, 1.0), (96, 0.2)]);
+        assert_eq!(*note.expression.timbre.points, vec![(64, 0.5)]);
+    }
+
+    #[test]
+    fn stretch_scales_envelopes_and_vibrato_from_the_first_note() {
+        let stretch = Transform::Stretch { factor: Rational32::new(3, 2), anchor: None };
+        let result = transformed(stretch, vec![bent_note(48, 96), Note::new(144, Pitch::new(7, 12), 48)]);
+
+        let note = &result[0];
+        assert_eq!((note.tick, note.length), (48, 144));
+        assert_eq!(*note.pitch_envelope.points, vec![(0, Pitch::new(0, 12)), (96, Pitch::new(1, 12))]);
+        assert_eq!(note.pitch_envelope.vibrato, Some(Vibrato { delay: 48, period: 36, depth: 0.01 }));
+        assert_eq!(*note.expression.pressure.points, vec![(0, 0.2), (72, 1.0)]);
+        assert_eq!(*note.expression.timbre.points, vec![(48, 0.5)]);
+        assert_eq!((result[1].tick, result[1].length), (192, 72));
+    }
+
+    #[test]
+    fn stretch_keeps_the_anchor_still() {
+        let stretch = Transform::Stretch { factor: Rational32::new(1, 2), anchor: Some(0) };
+        let result = transformed(stretch, vec![bent_note(48, 96)]);
+
+        let note = &result[0];
+        assert_eq!((note.tick, note.length), (24, 48));
+        assert_eq!(*note.pitch_envelope.points, vec![(0, Pitch::new(0, 12)), (32, Pitch::new(1, 12))]);
+        assert_eq!(note.pitch_envelope.vibrato, Some(Vibrato { delay: 16, period: 12, depth: 0.01 }));
+    }
+}
@@ -0,0 +1,260 @@
+use std::fmt::{self, Display};
+use std::sync::Mutex;
+
+use iced::Element;
+use iced_native::{Background, Clipboard, Event, Hasher, Layout, Length, Point, Rectangle, Size, Vector, Widget, mouse};
+use iced_native::event::Status;
+use iced_native::layout::{Limits, Node};
+use iced_native::mouse::Interaction;
+use iced_wgpu::{Color, Defaults, Primitive, Renderer};
+use iced_graphics::widget::canvas::{Frame, Path, Stroke};
+
+use crate::automation::{Automation, AutomationChange, AutomationLane};
+use crate::scroll_zoom::ScrollScaleAxis;
+
+const HEIGHT: u16 = 60;
+const POINT_SIZE: f32 = 5.0;
+/// how close a right click has to be to remove a point, in pixels
+const REMOVE_DISTANCE: f32 = 6.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AutomationTool {
+    /// draws freehand
+    Pencil,
+    /// drags out a straight line
+    Line,
+    /// bends the segment under the cursor by dragging up or down
+    Curve,
+}
+
+impl AutomationTool {
+    pub const ALL: [AutomationTool; 3] = [AutomationTool::Pencil, AutomationTool::Line, AutomationTool::Curve];
+}
+
+impl Display for AutomationTool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AutomationTool::Pencil => write!(f, "Pencil"),
+            AutomationTool::Line => write!(f, "Line"),
+            AutomationTool::Curve => write!(f, "Curve"),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct AutomationLaneState {
+    action: Action,
+}
+
+enum Action {
+    None,
+    /// from the last tick drawn at
+    Drawing(i32),
+    /// from a point, over the lane as it was before
+    Line((i32, f32), AutomationLane),
+    Curving { tick: i32, start_y: f32, start_curve: f32 },
+}
+
+impl Default for Action {
+    fn default() -> Self {
+        Action::None
+    }
+}
+
+/// One lane's breakpoints, drawn on the same horizontal scale as the piano
+/// roll.
+pub struct AutomationLaneView<'a, Message> {
+    state: &'a mut AutomationLaneState,
+    automation: &'a Mutex<Automation>,
+    index: usize,
+    scroll: &'a ScrollScaleAxis,
+    tool: AutomationTool,
+    on_change: Box<dyn Fn(AutomationChange) -> Message + 'a>,
+}
+
+impl<'a, Message> AutomationLaneView<'a, Message> {
+    pub fn new<F>(
+        state: &'a mut AutomationLaneState,
+        automation: &'a Mutex<Automation>,
+        index: usize,
+        scroll: &'a ScrollScaleAxis,
+        tool: AutomationTool,
+        on_change: F,
+    ) -> Self
+        where
+            F: 'a + Fn(AutomationChange) -> Message,
+    {
+        Self { state, automation, index, scroll, tool, on_change: Box::new(on_change) }
+    }
+
+    fn cursor(&self, cursor_position: Point, bounds: Rectangle) -> (i32, f32) {
+        let tick = self.scroll.screen_to_inner(cursor_position.x, bounds.x, bounds.width).max(0.0) as i32;
+        let value = 1.0 - (cursor_position.y - bounds.y) / bounds.height;
+        (tick, value.max(0.0).min(1.0))
+    }
+
+    fn value_to_y(value: f32, bounds: Rectangle) -> f32 {
+        (1.0 - value) * bounds.height
+    }
+}
+
+impl<'a, Message> Widget<Message, Renderer> for AutomationLaneView<'a, Message> {
+    fn width(&self) -> Length {
+        Length::Fill
+    }
+
+    fn height(&self) -> Length {
+        Length::Units(HEIGHT)
+    }
+
+    fn layout(&self, _renderer: &Renderer, limits: &Limits) -> Node {
+        Node::new(limits.height(Length::Units(HEIGHT)).max())
+    }
+
+    fn draw(
+        &self,
+        _renderer: &mut Renderer,
+        _defaults: &Defaults,
+        layout: Layout<'_>,
+        cursor_position: Point,
+        _viewport: &Rectangle,
+    ) -> (Primitive, Interaction) {
+        let bounds = layout.bounds();
+        let automation = self.automation.lock().unwrap();
+
+        let mut frame = Frame::new(Size::new(bounds.width, bounds.height));
+        let mut points = vec![];
+
+        if let Some(lane) = automation.lanes().get(self.index) {
+            if !lane.points.is_empty() {
+                let path = Path::new(|path| {
+                    // about every other pixel
+                    let mut x = 0.0;
+                    while x <= bounds.width {
+                        let tick = self.scroll.screen_to_inner(x + bounds.x, bounds.x, bounds.width);
+                        let y = Self::value_to_y(lane.value_at(tick).unwrap_or_default(), bounds);
+                        match x == 0.0 {
+                            true => path.move_to(Point::new(x, y)),
+                            false => path.line_to(Point::new(x, y)),
+                        }
+                        x += 2.0;
+                    }
+                });
+                frame.stroke(&path, Stroke::default().with_width(2.0).with_color(Color::from_rgb(1.0, 0.8, 0.4)));
+            }
+
+            points = lane.points.iter()
+                .map(|point| Primitive::Quad {
+                    bounds: Rectangle {
+                        x: self.scroll.inner_to_screen(point.tick as f32, bounds.x, bounds.width) - POINT_SIZE / 2.0,
+                        y: bounds.y + Self::value_to_y(point.value, bounds) - POINT_SIZE / 2.0,
+                        width: POINT_SIZE,
+                        height: POINT_SIZE,
+                    },
+                    background: Background::Color(Color::WHITE),
+                    border_radius: 0.0,
+                    border_width: 0.0,
+                    border_color: Color::BLACK,
+                })
+                .collect();
+        }
+
+        (
+            Primitive::Clip {
+                bounds,
+                offset: Vector::default(),
+                content: Box::new(Primitive::Group {
+                    primitives: vec![
+                        Primitive::Quad {
+                            bounds,
+                            background: Background::Color(Color::from_rgb(0.25, 0.25, 0.25)),
+                            border_radius: 0.0,
+                            border_width: 1.0,
+                            border_color: Color::BLACK,
+                        },
+                        Primitive::Translate {
+                            translation: Vector::new(bounds.x, bounds.y),
+                            content: Box::new(frame.into_geometry().into_primitive()),
+                        },
+                        Primitive::Group { primitives: points },
+                    ]
+                }),
+            },
+            match (bounds.contains(cursor_position), self.tool) {
+                (false, _) => Interaction::Idle,
+                (true, AutomationTool::Curve) => Interaction::ResizingVertically,
+                (true, _) => Interaction::Crosshair,
+            },
+        )
+    }
+
+    fn hash_layout(&self, _state: &mut Hasher) {
+
+    }
+
+    fn on_event(&mut self, event: Event, layout: Layout<'_>, cursor_position: Point, messages: &mut Vec<Message>, _renderer: &Renderer, _clipboard: Option<&dyn Clipboard>) -> Status {
+        let bounds = layout.bounds();
+        let (tick, value) = self.cursor(cursor_position, bounds);
+
+        let mut lane = match self.automation.lock().unwrap().lanes().get(self.index) {
+            Some(lane) => lane.clone(),
+            None => return Status::Ignored,
+        };
+
+        match event {
+            Event::Mouse(mouse::Event::ButtonPressed(mouse::Button::Left)) if bounds.contains(cursor_position) => {
+                self.state.action = match self.tool {
+                    AutomationTool::Pencil => {
+                        lane.draw(tick, tick, value);
+                        Action::Drawing(tick)
+                    }
+                    AutomationTool::Line => {
+                        let original = lane.clone();
+                        lane.line((tick, value), (tick, value));
+                        Action::Line((tick, value), original)
+                    }
+                    AutomationTool::Curve => {
+                        Action::Curving { tick, start_y: cursor_position.y, start_curve: lane.curve_at(tick) }
+                    }
+                };
+            }
+            Event::Mouse(mouse::Event::ButtonPressed(mouse::Button::Right)) if bounds.contains(cursor_position) => {
+                let distance = REMOVE_DISTANCE / self.scroll.scale(bounds.width);
+                lane.remove_near(tick, distance.ceil() as i32);
+            }
+            Event::Mouse(mouse::Event::CursorMoved { .. }) => match &self.state.action {
+                Action::None => return Status::Ignored,
+                Action::Drawing(last_tick) => {
+                    lane.draw(*last_tick, tick, value);
+                    self.state.action = Action::Drawing(tick);
+                }
+                Action::Line(start, original) => {
+                    lane = original.clone();
+                    lane.line(*start, (tick, value));
+                }
+                Action::Curving { tick: curve_tick, start_y, start_curve } => {
+                    let curve = start_curve + (start_y - cursor_position.y) / bounds.height * 2.0;
+                    lane.set_curve(*curve_tick, curve);
+                }
+            },
+            Event::Mouse(mouse::Event::ButtonReleased(_)) => {
+                self.state.action = Action::None;
+                return Status::Ignored;
+            }
+            _ => return Status::Ignored,
+        }
+
+        messages.push((self.on_change)(AutomationChange::UpdateLane(self.index, lane)));
+        Status::Captured
+    }
+}
+
+impl<'a, Message> Into<Element<'a, Message>>
+for AutomationLaneView<'a, Message>
+    where
+        Message: 'a,
+{
+    fn into(self) -> Element<'a, Message> {
+        Element::new(self)
+    }
+}
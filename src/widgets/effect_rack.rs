@@ -0,0 +1,144 @@
+use std::path::PathBuf;
+
+use iced::{Button, Column, Element, Length, PickList, Row, Slider, Text, TextInput, button, pick_list, slider, text_input};
+
+use crate::audio::{EffectKind, EffectState, ParameterDescriptor, RackId, SynthCommand};
+
+/// Lists the effects in a rack and edits their parameters, whatever they are.
+pub struct EffectRack {
+    rack: RackId,
+    add_pick_list: pick_list::State<EffectKind>,
+    slots: Vec<EffectSlot>,
+}
+
+#[derive(Default)]
+struct EffectSlot {
+    up_button: button::State,
+    down_button: button::State,
+    remove_button: button::State,
+    sliders: Vec<slider::State>,
+    path: String,
+    path_input: text_input::State,
+    load_button: button::State,
+}
+
+#[derive(Debug, Clone)]
+pub enum EffectRackMessage {
+    SynthCommand(SynthCommand),
+    SelfMessage(EffectRackSelfMessage),
+}
+
+#[derive(Debug, Clone)]
+pub enum EffectRackSelfMessage {
+    SetPath(usize, String),
+}
+
+impl EffectRack {
+    pub fn new(rack: RackId) -> Self {
+        Self {
+            rack,
+            add_pick_list: Default::default(),
+            slots: vec![],
+        }
+    }
+
+    pub fn update(&mut self, message: EffectRackSelfMessage) {
+        match message {
+            EffectRackSelfMessage::SetPath(index, path) => {
+                if let Some(slot) = self.slots.get_mut(index) {
+                    slot.path = path;
+                }
+            }
+        }
+    }
+
+    pub fn view<'a>(&'a mut self, effects: &'a [EffectState]) -> Element<'a, EffectRackMessage> {
+        use EffectRackMessage::SynthCommand as Command;
+
+        self.slots.resize_with(effects.len(), Default::default);
+
+        let rack = self.rack;
+        let count = effects.len();
+        let mut column = Column::new()
+            .spacing(4)
+            .push(PickList::new(
+                &mut self.add_pick_list,
+                &EffectKind::ALL[..],
+                None,
+                move |kind| Command(SynthCommand::InsertEffect { rack, index: count, kind }),
+            ));
+
+        for (index, (slot, effect)) in self.slots.iter_mut().zip(effects).enumerate() {
+            slot.sliders.resize_with(effect.parameters.len(), Default::default);
+
+            let mut header = Row::new()
+                .spacing(4)
+                .push(Text::new(effect.kind.to_string()).width(Length::Units(80)));
+            let mut up_button = Button::new(&mut slot.up_button, Text::new("Up"));
+            if index > 0 {
+                up_button = up_button.on_press(Command(SynthCommand::MoveEffect { rack, from: index, to: index - 1 }));
+            }
+            let mut down_button = Button::new(&mut slot.down_button, Text::new("Down"));
+            if index + 1 < count {
+                down_button = down_button.on_press(Command(SynthCommand::MoveEffect { rack, from: index, to: index + 1 }));
+            }
+            header = header
+                .push(up_button)
+                .push(down_button)
+                .push(Button::new(&mut slot.remove_button, Text::new("Remove"))
+                    .on_press(Command(SynthCommand::RemoveEffect { rack, index })));
+
+            if effect.kind == EffectKind::Reverb {
+                let load = Command(SynthCommand::LoadImpulseResponse {
+                    rack,
+                    effect: index,
+                    path: PathBuf::from(&slot.path),
+                });
+
+                header = header
+                    .push(TextInput::new(&mut slot.path_input, "impulse response (.wav)", &slot.path, move |path| {
+                        EffectRackMessage::SelfMessage(EffectRackSelfMessage::SetPath(index, path))
+                    })
+                        .width(Length::Units(240))
+                        .on_submit(load.clone()))
+                    .push(Button::new(&mut slot.load_button, Text::new("Load")).on_press(load));
+            }
+
+            let mut parameters = Row::new().spacing(8);
+            for (parameter, ((state, descriptor), &value)) in slot.sliders.iter_mut()
+                .zip(effect.parameters)
+                .zip(&effect.values)
+                .enumerate()
+            {
+                parameters = parameters.push(Column::new()
+                    .width(Length::Units(120))
+                    .push(Text::new(label(descriptor, value)).size(14))
+                    .push(Slider::new(state, descriptor.min..=descriptor.max, value, move |value| {
+                        Command(SynthCommand::SetEffectParameter { rack, effect: index, parameter, value })
+                    }).step(step(descriptor))));
+            }
+
+            column = column.push(header).push(parameters);
+        }
+
+        column.into()
+    }
+}
+
+fn step(descriptor: &ParameterDescriptor) -> f64 {
+    if descriptor.labels.is_empty() {
+        (descriptor.max - descriptor.min) / 1000.0
+    } else {
+        1.0
+    }
+}
+
+fn label(descriptor: &ParameterDescriptor, value: f64) -> String {
+    if let Some(name) = descriptor.labels.get(value.round() as usize) {
+        format!("{} {}", descriptor.name, name)
+    } else if descriptor.unit.is_empty() {
+        format!("{} {:.2}", descriptor.name, value)
+    } else {
+        format!("{} {:.0} {}", descriptor.name, value, descriptor.unit)
+    }
+}
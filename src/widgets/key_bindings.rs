@@ -0,0 +1,58 @@
+use iced::{Color, Column, Element, Length, Row, Text};
+
+use crate::keymap::{Action, Keymap};
+
+/// Lists what each action is bound to, and anything wrong with the keymap.
+pub fn key_bindings<'a, Message: 'a>(keymap: &Keymap, error: Option<&str>) -> Element<'a, Message> {
+    let error_colour = Color::from_rgb(1.0, 0.4, 0.4);
+    let mut column = Column::new().spacing(2);
+
+    if let Some(path) = Keymap::user_path() {
+        column = column.push(Text::new(format!("Keymap file: {}", path.display())).size(14));
+    }
+    if let Some(error) = error {
+        column = column.push(Text::new(error).size(14).color(error_colour));
+    }
+    for conflict in keymap.conflicts() {
+        column = column.push(Text::new(conflict).size(14).color(error_colour));
+    }
+
+    for &action in Action::ALL.iter() {
+        let chords: Vec<String> = keymap.chords(action).iter().map(ToString::to_string).collect();
+        column = column.push(Row::new()
+            .push(Text::new(action.to_string()).size(14).width(Length::Units(200)))
+            .push(Text::new(match chords.is_empty() {
+                true => "unbound".to_string(),
+                false => chords.join(", "),
+            }).size(14))
+        );
+    }
+
+    let mouse = keymap.mouse;
+    for (name, modifier) in [
+        ("Select (drag)", mouse.select),
+        ("Add to selection (with select)", mouse.select_add),
+        ("Take from selection (with select)", mouse.select_subtract),
+        ("Draw out a new note", mouse.create_resizing),
+        ("Duplicate (drag)", mouse.duplicate),
+        ("Off the grid", mouse.unquantized),
+        ("Bend tool: slide", mouse.slide),
+        ("Bend tool: vibrato", mouse.vibrato),
+    ].iter() {
+        column = column.push(Row::new()
+            .push(Text::new(*name).size(14).width(Length::Units(200)))
+            .push(Text::new(format!("{:?} + click", modifier)).size(14))
+        );
+    }
+    for (name, modifier) in [
+        ("Zoom", mouse.wheel_zoom),
+        ("Other axis", mouse.wheel_axis),
+    ].iter() {
+        column = column.push(Row::new()
+            .push(Text::new(*name).size(14).width(Length::Units(200)))
+            .push(Text::new(format!("{:?} + wheel", modifier)).size(14))
+        );
+    }
+
+    column.into()
+}
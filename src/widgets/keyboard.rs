@@ -0,0 +1,206 @@
+use iced::Element;
+use iced_native::{Background, Clipboard, Event, Hasher, HorizontalAlignment, Layout, Length, Point, Rectangle, Vector, VerticalAlignment, Widget, mouse};
+use iced_native::event::Status;
+use iced_native::layout::{Limits, Node};
+use iced_native::mouse::Interaction;
+use iced_wgpu::{Color, Defaults, Primitive, Renderer};
+
+use crate::audio::SynthCommand;
+use crate::scroll_zoom::ScrollScaleAxis;
+use crate::sequence::Pitch;
+use crate::widgets::piano_roll::PianoRollSettings;
+use crate::widgets::pitch_grid::LineType;
+
+pub const WIDTH: u16 = 60;
+/// keys shorter than this, in pixels, only get a label on the tonic
+const LABEL_HEIGHT: f32 = 12.0;
+
+#[derive(Default)]
+pub struct KeyboardState {
+    /// the key being auditioned with the mouse
+    pressed: Option<Pitch>,
+}
+
+/// A key for each line of the pitch grid, lined up with the piano roll
+/// beside it. Clicking a key auditions it, dragging glides between them.
+pub struct PianoKeyboard<'a, Message> {
+    state: &'a mut KeyboardState,
+    scroll: &'a ScrollScaleAxis,
+    settings: &'a PianoRollSettings,
+    /// held on the MIDI input
+    held: Vec<Pitch>,
+    on_preview: Box<dyn Fn(SynthCommand) -> Message + 'a>,
+}
+
+impl<'a, Message> PianoKeyboard<'a, Message> {
+    pub fn new<F>(
+        state: &'a mut KeyboardState,
+        scroll: &'a ScrollScaleAxis,
+        settings: &'a PianoRollSettings,
+        held: Vec<Pitch>,
+        on_preview: F,
+    ) -> Self
+        where
+            F: 'a + Fn(SynthCommand) -> Message,
+    {
+        Self { state, scroll, settings, held, on_preview: Box::new(on_preview) }
+    }
+
+    fn pitch_at(&self, y: f32, bounds: Rectangle) -> Pitch {
+        let inner_y = self.scroll.screen_to_inner(y, bounds.y, bounds.height);
+        self.settings.pitch_grid.quantize_pitch(Pitch::from_octave_f32(-inner_y))
+    }
+
+    fn screen_y(&self, pitch: f32, bounds: Rectangle) -> f32 {
+        self.scroll.inner_to_screen(-pitch, bounds.y, bounds.height)
+    }
+
+    fn is_lit(&self, pitch: &Pitch) -> bool {
+        self.state.pressed.as_ref() == Some(pitch)
+            || self.held.iter().any(|held| self.settings.pitch_grid.quantize_pitch(held.clone()) == *pitch)
+    }
+}
+
+impl<'a, Message> Widget<Message, Renderer> for PianoKeyboard<'a, Message> {
+    fn width(&self) -> Length {
+        Length::Units(WIDTH)
+    }
+
+    fn height(&self) -> Length {
+        Length::Fill
+    }
+
+    fn layout(&self, _renderer: &Renderer, limits: &Limits) -> Node {
+        Node::new(limits.width(Length::Units(WIDTH)).max())
+    }
+
+    fn draw(
+        &self,
+        _renderer: &mut Renderer,
+        _defaults: &Defaults,
+        layout: Layout<'_>,
+        cursor_position: Point,
+        _viewport: &Rectangle,
+    ) -> (Primitive, Interaction) {
+        let bounds = layout.bounds();
+
+        // a little past the view, so the keys at the edges know their neighbours
+        let margin = self.scroll.view_width() / 4.0;
+        let lines = self.settings.pitch_grid.get_grid_lines(
+            Pitch::from_octave_f32(-self.scroll.view_end - margin),
+            Pitch::from_octave_f32(-self.scroll.view_start + margin),
+        );
+        let pitches: Vec<f32> = lines.iter().map(|line| line.pitch.to_f32()).collect();
+
+        let mut keys = vec![];
+        let mut labels = vec![];
+        for (i, line) in lines.iter().enumerate() {
+            let pitch = pitches[i];
+            let below = match i {
+                0 => pitches.get(1).map_or(pitch, |next| pitch - (next - pitch)),
+                _ => pitches[i - 1],
+            };
+            let above = pitches.get(i + 1).cloned().unwrap_or(pitch + (pitch - below));
+
+            let top = self.screen_y((pitch + above) / 2.0, bounds);
+            let bottom = self.screen_y((below + pitch) / 2.0, bounds);
+            let height = bottom - top;
+
+            let (background, text) = match (self.is_lit(&line.pitch), line.line_type) {
+                (true, _) => (Color::from_rgb(0.6, 0.9, 1.0), Color::BLACK),
+                (false, LineType::Black) => (Color::from_rgb(0.15, 0.15, 0.15), Color::WHITE),
+                (false, LineType::White) => (Color::from_rgb(0.9, 0.9, 0.9), Color::BLACK),
+                (false, LineType::Tonic) => (Color::from_rgb(0.8, 1.0, 0.8), Color::BLACK),
+            };
+            keys.push(Primitive::Quad {
+                bounds: Rectangle { x: bounds.x, y: top.round(), width: bounds.width, height: height.round().max(1.0) },
+                background: Background::Color(background),
+                border_radius: 0.0,
+                border_width: 1.0,
+                border_color: Color::from_rgb(0.4, 0.4, 0.4),
+            });
+
+            if height >= LABEL_HEIGHT || (line.line_type == LineType::Tonic && height >= LABEL_HEIGHT / 2.0) {
+                labels.push(Primitive::Text {
+                    content: self.settings.pitch_grid.label(&line.pitch),
+                    bounds: Rectangle {
+                        x: bounds.x + 3.0,
+                        y: self.screen_y(pitch, bounds),
+                        width: bounds.width - 6.0,
+                        height,
+                    },
+                    color: text,
+                    size: height.min(14.0).max(10.0),
+                    font: Default::default(),
+                    horizontal_alignment: HorizontalAlignment::Left,
+                    vertical_alignment: VerticalAlignment::Center,
+                });
+            }
+        }
+
+        (
+            Primitive::Clip {
+                bounds,
+                offset: Vector::default(),
+                content: Box::new(Primitive::Group {
+                    primitives: vec![
+                        Primitive::Quad {
+                            bounds,
+                            background: Background::Color(Color::from_rgb(0.25, 0.25, 0.25)),
+                            border_radius: 0.0,
+                            border_width: 0.0,
+                            border_color: Color::BLACK,
+                        },
+                        Primitive::Group { primitives: keys },
+                        Primitive::Group { primitives: labels },
+                    ]
+                }),
+            },
+            match bounds.contains(cursor_position) {
+                true => Interaction::Pointer,
+                false => Interaction::Idle,
+            },
+        )
+    }
+
+    fn hash_layout(&self, _state: &mut Hasher) {
+
+    }
+
+    fn on_event(&mut self, event: Event, layout: Layout<'_>, cursor_position: Point, messages: &mut Vec<Message>, _renderer: &Renderer, _clipboard: Option<&dyn Clipboard>) -> Status {
+        let bounds = layout.bounds();
+
+        match event {
+            Event::Mouse(mouse::Event::ButtonPressed(mouse::Button::Left)) if bounds.contains(cursor_position) => {
+                let pitch = self.pitch_at(cursor_position.y, bounds);
+                self.state.pressed = Some(pitch.clone());
+                messages.push((self.on_preview)(SynthCommand::StartPreview(pitch)));
+                Status::Captured
+            }
+            Event::Mouse(mouse::Event::CursorMoved { .. }) if self.state.pressed.is_some() => {
+                let pitch = self.pitch_at(cursor_position.y, bounds);
+                if self.state.pressed.as_ref() != Some(&pitch) {
+                    self.state.pressed = Some(pitch.clone());
+                    messages.push((self.on_preview)(SynthCommand::StartPreview(pitch)));
+                }
+                Status::Captured
+            }
+            Event::Mouse(mouse::Event::ButtonReleased(mouse::Button::Left)) if self.state.pressed.is_some() => {
+                self.state.pressed = None;
+                messages.push((self.on_preview)(SynthCommand::StopPreview));
+                Status::Captured
+            }
+            _ => Status::Ignored,
+        }
+    }
+}
+
+impl<'a, Message> Into<Element<'a, Message>>
+for PianoKeyboard<'a, Message>
+    where
+        Message: 'a,
+{
+    fn into(self) -> Element<'a, Message> {
+        Element::new(self)
+    }
+}
@@ -0,0 +1,127 @@
+use iced::Element;
+use iced_native::{Background, Clipboard, Event, Hasher, Layout, Length, Point, Rectangle, Widget, mouse};
+use iced_native::event::Status;
+use iced_native::layout::{Limits, Node};
+use iced_native::mouse::Interaction;
+use iced_wgpu::{Color, Defaults, Primitive, Renderer};
+
+use crate::audio::{MeterReport, SILENCE_DB};
+
+/// the top of the scale, in dB
+const CEILING_DB: f32 = 6.0;
+/// the bottom of the scale, in dB
+const FLOOR_DB: f32 = -60.0;
+const CLIP_HEIGHT: f32 = 8.0;
+
+/// Peak and RMS bars per channel, with clip lights that stay on until
+/// clicked.
+pub struct Meters<'a, Message> {
+    report: Option<&'a MeterReport>,
+    clipped: [bool; 2],
+    on_reset: Message,
+}
+
+impl<'a, Message> Meters<'a, Message> {
+    pub fn new(report: Option<&'a MeterReport>, clipped: [bool; 2], on_reset: Message) -> Self {
+        Self { report, clipped, on_reset }
+    }
+}
+
+fn level_height(db: f32, height: f32) -> f32 {
+    let db = if db <= SILENCE_DB { FLOOR_DB } else { db };
+    ((db - FLOOR_DB) / (CEILING_DB - FLOOR_DB)).max(0.0).min(1.0) * height
+}
+
+fn quad(bounds: Rectangle, colour: Color) -> Primitive {
+    Primitive::Quad {
+        bounds,
+        background: Background::Color(colour),
+        border_radius: 0.0,
+        border_width: 0.0,
+        border_color: Color::BLACK,
+    }
+}
+
+impl<'a, Message: Clone> Widget<Message, Renderer> for Meters<'a, Message> {
+    fn width(&self) -> Length {
+        Length::Units(24)
+    }
+
+    fn height(&self) -> Length {
+        Length::Units(120)
+    }
+
+    fn layout(&self, _renderer: &Renderer, limits: &Limits) -> Node {
+        Node::new(limits.width(self.width()).height(self.height()).max())
+    }
+
+    fn draw(
+        &self,
+        _renderer: &mut Renderer,
+        _defaults: &Defaults,
+        layout: Layout<'_>,
+        _cursor_position: Point,
+        _viewport: &Rectangle,
+    ) -> (Primitive, Interaction) {
+        let bounds = layout.bounds();
+        let bar_width = bounds.width / 2.0;
+        let meter_height = bounds.height - CLIP_HEIGHT;
+        let zero_db = bounds.y + bounds.height - level_height(0.0, meter_height);
+
+        let mut primitives = vec![quad(bounds, Color::from_rgb(0.15, 0.15, 0.15))];
+
+        for channel in 0..2 {
+            let x = bounds.x + channel as f32 * bar_width;
+            let bar = |db: f32| {
+                let height = level_height(db, meter_height);
+                Rectangle { x: x + 1.0, y: bounds.y + bounds.height - height, width: bar_width - 2.0, height }
+            };
+
+            if let Some(report) = self.report {
+                primitives.push(quad(bar(report.peak[channel]), Color::from_rgb(0.2, 0.5, 0.2)));
+                primitives.push(quad(bar(report.rms[channel]), Color::from_rgb(0.3, 0.8, 0.3)));
+            }
+
+            let clip_colour = if self.clipped[channel] {
+                Color::from_rgb(0.9, 0.1, 0.1)
+            } else {
+                Color::from_rgb(0.3, 0.1, 0.1)
+            };
+            primitives.push(quad(
+                Rectangle { x: x + 1.0, y: bounds.y, width: bar_width - 2.0, height: CLIP_HEIGHT - 1.0 },
+                clip_colour,
+            ));
+        }
+
+        primitives.push(quad(
+            Rectangle { x: bounds.x, y: zero_db, width: bounds.width, height: 1.0 },
+            Color::from_rgb(0.6, 0.6, 0.6),
+        ));
+
+        (Primitive::Group { primitives }, Interaction::Idle)
+    }
+
+    fn hash_layout(&self, _state: &mut Hasher) {
+
+    }
+
+    fn on_event(&mut self, event: Event, layout: Layout<'_>, cursor_position: Point, messages: &mut Vec<Message>, _renderer: &Renderer, _clipboard: Option<&dyn Clipboard>) -> Status {
+        match event {
+            Event::Mouse(mouse::Event::ButtonPressed(mouse::Button::Left)) if layout.bounds().contains(cursor_position) => {
+                messages.push(self.on_reset.clone());
+                Status::Captured
+            }
+            _ => Status::Ignored,
+        }
+    }
+}
+
+impl<'a, Message> Into<Element<'a, Message>>
+for Meters<'a, Message>
+    where
+        Message: 'a + Clone,
+{
+    fn into(self) -> Element<'a, Message> {
+        Element::new(self)
+    }
+}
@@ -0,0 +1,48 @@
+use iced::{Checkbox, Column, Element, Length, Row, Slider, Text, slider};
+
+use crate::audio::{ChannelParameter, ChannelState, SynthCommand};
+
+const PARAMETERS: [(ChannelParameter, &str); 3] = [
+    (ChannelParameter::Gain, "Gain"),
+    (ChannelParameter::Pan, "Pan"),
+    (ChannelParameter::Send, "Send"),
+];
+
+/// A strip of level, pan and send controls per mixer channel, and a switch
+/// for the one keying the master sidechain.
+#[derive(Default)]
+pub struct MixerStrips {
+    sliders: Vec<[slider::State; 3]>,
+}
+
+impl MixerStrips {
+    pub fn view<'a>(&'a mut self, channels: &'a [ChannelState]) -> Element<'a, SynthCommand> {
+        self.sliders.resize_with(channels.len(), Default::default);
+
+        let mut row = Row::new().spacing(16);
+        for (channel, (sliders, state)) in self.sliders.iter_mut().zip(channels).enumerate() {
+            let mut strip = Column::new()
+                .width(Length::Units(120))
+                .push(Text::new(state.name.clone()));
+
+            for (slider, &(parameter, name)) in sliders.iter_mut().zip(PARAMETERS.iter()) {
+                if let Some(value) = state.get(parameter) {
+                    let (min, max) = parameter.range();
+                    strip = strip
+                        .push(Text::new(format!("{} {:.2}", name, value)).size(14))
+                        .push(Slider::new(slider, min..=max, value, move |value| {
+                            SynthCommand::SetChannel { channel, parameter, value }
+                        }).step(0.01));
+                }
+            }
+
+            strip = strip.push(Checkbox::new(state.sidechain, "Sidechain", move |on| {
+                SynthCommand::SetSidechain(if on { Some(channel) } else { None })
+            }).text_size(14));
+
+            row = row.push(strip);
+        }
+
+        row.into()
+    }
+}
@@ -3,4 +3,15 @@ pub mod piano_roll;
 pub mod tick_grid;
 pub mod pitch_grid;
 pub mod timeline;
-pub mod sequence_editor;
\ No newline at end of file
+pub mod sequence_editor;
+pub mod automation_lane;
+pub mod effect_rack;
+pub mod meters;
+pub mod note_expression;
+pub mod mixer;
+pub mod spectrum;
+pub mod synth_panel;
+pub mod key_bindings;
+pub mod keyboard;
+pub mod quantize_panel;
+pub mod transform_panel;
\ No newline at end of file
@@ -0,0 +1,215 @@
+use std::fmt::{self, Display};
+use std::sync::Mutex;
+
+use iced::Element;
+use iced_native::{Background, Clipboard, Event, Hasher, Layout, Length, Point, Rectangle, Size, Vector, Widget, mouse};
+use iced_native::event::Status;
+use iced_native::layout::{Limits, Node};
+use iced_native::mouse::Interaction;
+use iced_wgpu::{Color, Defaults, Primitive, Renderer};
+use iced_graphics::widget::canvas::{Frame, Path, Stroke};
+
+use crate::sequence::{ExpressionEnvelope, Note, NoteId, Sequence, SequenceChange};
+
+const HEIGHT: u16 = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExpressionKind {
+    Pressure,
+    /// CC74, brightness on most synths
+    Timbre,
+}
+
+impl ExpressionKind {
+    pub const ALL: [ExpressionKind; 2] = [ExpressionKind::Pressure, ExpressionKind::Timbre];
+
+    fn envelope(self, note: &Note) -> &ExpressionEnvelope {
+        match self {
+            ExpressionKind::Pressure => &note.expression.pressure,
+            ExpressionKind::Timbre => &note.expression.timbre,
+        }
+    }
+
+    fn envelope_mut(self, note: &mut Note) -> &mut ExpressionEnvelope {
+        match self {
+            ExpressionKind::Pressure => &mut note.expression.pressure,
+            ExpressionKind::Timbre => &mut note.expression.timbre,
+        }
+    }
+
+    fn color(self) -> Color {
+        match self {
+            ExpressionKind::Pressure => Color::from_rgb(1.0, 0.5, 0.4),
+            ExpressionKind::Timbre => Color::from_rgb(0.4, 0.8, 1.0),
+        }
+    }
+}
+
+impl Display for ExpressionKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExpressionKind::Pressure => write!(f, "Pressure"),
+            ExpressionKind::Timbre => write!(f, "Timbre"),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct NoteExpressionState {
+    /// from the last tick drawn at
+    drawing: Option<i32>,
+}
+
+/// One of a note's expression envelopes, stretched across the width of the
+/// widget. Dragging draws, right click clears it.
+pub struct NoteExpressionStrip<'a, Message> {
+    state: &'a mut NoteExpressionState,
+    notes: &'a Mutex<Sequence>,
+    id: NoteId,
+    kind: ExpressionKind,
+    on_change: Box<dyn Fn(SequenceChange) -> Message + 'a>,
+}
+
+impl<'a, Message> NoteExpressionStrip<'a, Message> {
+    pub fn new<F>(
+        state: &'a mut NoteExpressionState,
+        notes: &'a Mutex<Sequence>,
+        id: NoteId,
+        kind: ExpressionKind,
+        on_change: F,
+    ) -> Self
+        where
+            F: 'a + Fn(SequenceChange) -> Message,
+    {
+        Self { state, notes, id, kind, on_change: Box::new(on_change) }
+    }
+
+    fn cursor(note: &Note, cursor_position: Point, bounds: Rectangle) -> (i32, f32) {
+        let position = ((cursor_position.x - bounds.x) / bounds.width).max(0.0).min(1.0);
+        let value = 1.0 - (cursor_position.y - bounds.y) / bounds.height;
+        ((position * note.length as f32).round() as i32, value.max(0.0).min(1.0))
+    }
+}
+
+impl<'a, Message> Widget<Message, Renderer> for NoteExpressionStrip<'a, Message> {
+    fn width(&self) -> Length {
+        Length::Fill
+    }
+
+    fn height(&self) -> Length {
+        Length::Units(HEIGHT)
+    }
+
+    fn layout(&self, _renderer: &Renderer, limits: &Limits) -> Node {
+        Node::new(limits.height(Length::Units(HEIGHT)).max())
+    }
+
+    fn draw(
+        &self,
+        _renderer: &mut Renderer,
+        _defaults: &Defaults,
+        layout: Layout<'_>,
+        cursor_position: Point,
+        _viewport: &Rectangle,
+    ) -> (Primitive, Interaction) {
+        let bounds = layout.bounds();
+        let mut frame = Frame::new(Size::new(bounds.width, bounds.height));
+
+        if let Some(note) = self.notes.lock().unwrap().get(self.id) {
+            let envelope = self.kind.envelope(note);
+
+            if !envelope.is_empty() {
+                let length = note.length.max(1) as f32;
+                let path = Path::new(|path| {
+                    let mut x = 0.0;
+                    while x <= bounds.width {
+                        let tick = x / bounds.width * length;
+                        let y = (1.0 - envelope.value_at(tick).unwrap_or_default()) * bounds.height;
+                        match x == 0.0 {
+                            true => path.move_to(Point::new(x, y)),
+                            false => path.line_to(Point::new(x, y)),
+                        }
+                        x += 2.0;
+                    }
+                });
+                frame.stroke(&path, Stroke::default().with_width(2.0).with_color(self.kind.color()));
+            }
+        }
+
+        (
+            Primitive::Clip {
+                bounds,
+                offset: Vector::default(),
+                content: Box::new(Primitive::Group {
+                    primitives: vec![
+                        Primitive::Quad {
+                            bounds,
+                            background: Background::Color(Color::from_rgb(0.25, 0.25, 0.25)),
+                            border_radius: 0.0,
+                            border_width: 1.0,
+                            border_color: Color::BLACK,
+                        },
+                        Primitive::Translate {
+                            translation: Vector::new(bounds.x, bounds.y),
+                            content: Box::new(frame.into_geometry().into_primitive()),
+                        },
+                    ]
+                }),
+            },
+            match bounds.contains(cursor_position) {
+                true => Interaction::Crosshair,
+                false => Interaction::Idle,
+            },
+        )
+    }
+
+    fn hash_layout(&self, _state: &mut Hasher) {
+
+    }
+
+    fn on_event(&mut self, event: Event, layout: Layout<'_>, cursor_position: Point, messages: &mut Vec<Message>, _renderer: &Renderer, _clipboard: Option<&dyn Clipboard>) -> Status {
+        let bounds = layout.bounds();
+
+        let mut note = match self.notes.lock().unwrap().get(self.id) {
+            Some(note) => note.clone(),
+            None => return Status::Ignored,
+        };
+        let (tick, value) = Self::cursor(&note, cursor_position, bounds);
+        let envelope = self.kind.envelope_mut(&mut note);
+
+        match event {
+            Event::Mouse(mouse::Event::ButtonPressed(mouse::Button::Left)) if bounds.contains(cursor_position) => {
+                envelope.draw(tick, tick, value);
+                self.state.drawing = Some(tick);
+            }
+            Event::Mouse(mouse::Event::ButtonPressed(mouse::Button::Right)) if bounds.contains(cursor_position) => {
+                envelope.points.clear();
+            }
+            Event::Mouse(mouse::Event::CursorMoved { .. }) => match self.state.drawing {
+                Some(last_tick) => {
+                    envelope.draw(last_tick, tick, value);
+                    self.state.drawing = Some(tick);
+                }
+                None => return Status::Ignored,
+            },
+            Event::Mouse(mouse::Event::ButtonReleased(_)) => {
+                self.state.drawing = None;
+                return Status::Ignored;
+            }
+            _ => return Status::Ignored,
+        }
+
+        messages.push((self.on_change)(SequenceChange::Update(self.id, note)));
+        Status::Captured
+    }
+}
+
+impl<'a, Message> Into<Element<'a, Message>>
+for NoteExpressionStrip<'a, Message>
+    where
+        Message: 'a,
+{
+    fn into(self) -> Element<'a, Message> {
+        Element::new(self)
+    }
+}
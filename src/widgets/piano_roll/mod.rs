@@ -1,16 +1,19 @@
-use std::{cmp::max, sync::Mutex};
+use std::{cmp::max, sync::{Arc, Mutex}};
 use std::cmp::min;
 
 use iced::{Element};
-use iced_native::{Background, Clipboard, Color, Event, Hasher, keyboard, Layout, Length, mouse, Point, Rectangle, Vector, Widget};
+use iced_native::{Background, Clipboard, Color, Event, Hasher, keyboard, Layout, Length, mouse, Point, Rectangle, Size, Vector, Widget};
 use iced_native::event::Status;
 use iced_native::layout::{Limits, Node};
 use iced_native::mouse::Interaction;
 use iced_wgpu::{Defaults, Primitive, Renderer};
+use iced_graphics::widget::canvas::{Frame, Path, Stroke};
 
 use crate::audio::{SynthCommand, PlaybackState};
 use crate::helpers::RectangleHelpers;
-use crate::scroll_zoom::ScrollZoomState;
+use crate::keymap::Keymap;
+use crate::overlap::OverlapSettings;
+use crate::scroll_zoom::{ScrollScaleAxisChange, ScrollZoomState, wheel_pixels};
 use crate::sequence::{Note, Pitch, Sequence, SequenceChange};
 use crate::sequence::SequenceChange::{Add};
 use crate::widgets::piano_roll::state::Action::{Dragging, Resizing, Selecting};
@@ -18,7 +21,7 @@ use crate::widgets::piano_roll::state::HoverState::{CanDrag, CanResize, OutOfBou
 use crate::widgets::pitch_grid::{PitchGrid, TetGrid};
 use crate::widgets::pitch_grid;
 use crate::widgets::tick_grid::{LineType, SimpleGrid, TickGrid};
-use crate::widgets::piano_roll::state::{PianoRollState, Action, Cursor, HoverState, PianoRollSelfMessage};
+use crate::widgets::piano_roll::state::{PianoRollState, Action, Cursor, HoverState, Marquee, PianoRollSelfMessage, SelectMode, Tool, NOTE_HEIGHT};
 
 pub mod state;
 
@@ -33,28 +36,33 @@ pub struct PianoRoll<'a> {
 
 
 pub struct PianoRollSettings {
-    pub(crate) tick_grid: Box<dyn TickGrid>,
-    pitch_grid: Box<dyn PitchGrid>,
+    pub(crate) tick_grid: Arc<dyn TickGrid>,
+    pub(crate) pitch_grid: Box<dyn PitchGrid>,
+    pub(crate) keymap: Keymap,
+    pub(crate) overlap: OverlapSettings,
 }
 
 impl Default for PianoRollSettings {
     fn default() -> Self {
         PianoRollSettings {
-            tick_grid: Box::new(SimpleGrid { ticks_per_16th: 32, }),
+            tick_grid: Arc::new(SimpleGrid { ticks_per_16th: 32, }),
+            // up from A
             pitch_grid: Box::new(TetGrid { tones_per_octave: 12, pattern: vec![
                 pitch_grid::LineType::White,
                 pitch_grid::LineType::Black,
                 pitch_grid::LineType::White,
+                pitch_grid::LineType::Tonic,
                 pitch_grid::LineType::Black,
                 pitch_grid::LineType::White,
-                pitch_grid::LineType::White,
                 pitch_grid::LineType::Black,
                 pitch_grid::LineType::White,
+                pitch_grid::LineType::White,
                 pitch_grid::LineType::Black,
-                pitch_grid::LineType::Tonic,
                 pitch_grid::LineType::White,
                 pitch_grid::LineType::Black,
             ]}),
+            keymap: Keymap::default(),
+            overlap: OverlapSettings::default(),
         }
     }
 }
@@ -63,7 +71,14 @@ impl Default for PianoRollSettings {
 pub enum PianoRollMessage {
     SelfMessage(PianoRollSelfMessage),
     SynthCommand(SynthCommand),
+    /// a one-off edit, undone on its own
     SequenceChange(SequenceChange),
+    /// part of an edit made with the mouse, undone with the rest of it
+    Gesture(SequenceChange),
+    /// the mouse was let go
+    GestureEnd,
+    ScrollX(ScrollScaleAxisChange),
+    ScrollY(ScrollScaleAxisChange),
 }
 
 impl<'a> PianoRoll<'a> {
@@ -86,24 +101,46 @@ impl<'a> PianoRoll<'a> {
         }
     }
 
-    fn selection_rect(&self, start_tick: i32, start_note: &Pitch, end_tick: i32, end_note: &Pitch, bounds: &Rectangle) -> Rectangle {
-        let from_tick = min(start_tick, end_tick);
-        let to_tick = max(start_tick, end_tick);
-        let from_note = min(start_note, end_note);
-        let to_note = max(start_note, end_note);
+    fn selection_rect(&self, start: (i32, f32), end: (i32, f32), bounds: &Rectangle) -> Rectangle {
+        let from_tick = min(start.0, end.0);
+        let to_tick = max(start.0, end.0);
+        let to_octave = start.1.max(end.1);
 
         let inner = Rectangle {
             x: from_tick as f32,
-            y: -to_note.to_f32() - 1.0/24.0,
+            y: -to_octave,
             width: (to_tick - from_tick + 1) as f32,
-            height: (to_note.clone() - from_note.clone() + Pitch::new(1, 12)).to_f32(),
+            height: (start.1 - end.1).abs(),
         };
 
         self.scroll_zoom_state.inner_rect_to_screen(inner, &bounds)
     }
 
+    /// the lasso so far, closed back to where it started
+    fn draw_lasso(&self, points: &[(i32, f32)], end: (i32, f32), bounds: Rectangle) -> Primitive {
+        let mut frame = Frame::new(Size::new(bounds.width, bounds.height));
+        let origin = Rectangle { x: 0.0, y: 0.0, ..bounds };
+
+        let path = Path::new(|path| {
+            for (i, &(tick, octave)) in points.iter().chain(Some(&end)).enumerate() {
+                let point = self.scroll_zoom_state.inner_to_screen(Point::new(tick as f32, -octave), &origin);
+                match i {
+                    0 => path.move_to(point),
+                    _ => path.line_to(point),
+                }
+            }
+            path.close();
+        });
+        frame.stroke(&path, Stroke::default().with_width(2.0).with_color(Color::WHITE));
+
+        Primitive::Translate {
+            translation: Vector::new(bounds.x, bounds.y),
+            content: Box::new(frame.into_geometry().into_primitive()),
+        }
+    }
+
     fn note_rect(&self, note: &Note, bounds: Rectangle,) -> Rectangle {
-        let height = self.scroll_zoom_state.y.scale(bounds.height) / 12.0;
+        let height = self.scroll_zoom_state.y.scale(bounds.height) * NOTE_HEIGHT;
 
         Rectangle {
             x: (note.tick as f32 - self.scroll_zoom_state.x.scroll()) * self.scroll_zoom_state.x.scale(bounds.width) + bounds.x,
@@ -117,6 +154,33 @@ impl<'a> PianoRoll<'a> {
         self.note_rect(note, bounds).handle_right()
     }
 
+    /// a line through the middle of a note following its pitch envelope
+    fn draw_pitch_envelope(&self, note: &Note, bounds: Rectangle, frame: &mut Frame) {
+        let x_scale = self.scroll_zoom_state.x.scale(bounds.width);
+        let y_scale = self.scroll_zoom_state.y.scale(bounds.height);
+
+        // about every other pixel
+        let step = (2.0 / x_scale).max(1.0);
+        let point = |tick: f32| {
+            let pitch = note.pitch.to_f32() + note.pitch_envelope.offset_at(tick);
+            Point::new(
+                (note.tick as f32 + tick - self.scroll_zoom_state.x.scroll()) * x_scale,
+                (-pitch - self.scroll_zoom_state.y.scroll()) * y_scale,
+            )
+        };
+
+        let path = Path::new(|path| {
+            path.move_to(point(0.0));
+            let mut tick = step;
+            while tick < note.length as f32 {
+                path.line_to(point(tick));
+                tick += step;
+            }
+            path.line_to(point(note.length as f32));
+        });
+        frame.stroke(&path, Stroke::default().with_width(2.0).with_color(Color::from_rgb(0.9, 0.3, 0.9)));
+    }
+
     fn update_hover(&mut self, layout: Layout, cursor_position: Point, bounds: Rectangle, notes: &Sequence) {
         if layout.bounds().contains(cursor_position) {
             let resize = notes.iter()
@@ -160,8 +224,26 @@ impl<'a> PianoRoll<'a> {
         }
     }
 
+    fn draw_pitch_envelopes(&self, bounds: Rectangle) -> Primitive {
+        let mut frame = Frame::new(Size::new(bounds.width, bounds.height));
+        for (_, note) in self.notes.lock().unwrap().iter() {
+            if !note.pitch_envelope.is_empty() {
+                self.draw_pitch_envelope(note, bounds, &mut frame);
+            }
+        }
+
+        Primitive::Translate {
+            translation: Vector::new(bounds.x, bounds.y),
+            content: Box::new(frame.into_geometry().into_primitive()),
+        }
+    }
+
     fn draw_cursor(&self, bounds: Rectangle) -> Primitive {
-        let x = self.playback_state.playback_cursor as f32 * self.scroll_zoom_state.x.scale(bounds.width);
+        self.draw_tick_line(self.playback_state.playback_cursor, Color::from_rgba(1.0, 1.0, 1.0, 0.5), bounds)
+    }
+
+    fn draw_tick_line(&self, tick: i32, colour: Color, bounds: Rectangle) -> Primitive {
+        let x = tick as f32 * self.scroll_zoom_state.x.scale(bounds.width);
         Primitive::Quad {
             bounds: Rectangle {
                 x: (x - self.scroll_zoom_state.x.view_start * self.scroll_zoom_state.x.scale(bounds.width) + bounds.x).round(),
@@ -169,7 +251,7 @@ impl<'a> PianoRoll<'a> {
                 width: 1.0,
                 height: bounds.height
             },
-            background: Background::Color(Color::from_rgba(1.0, 1.0, 1.0, 0.5)),
+            background: Background::Color(colour),
             border_radius: 0.0,
             border_width: 0.0,
             border_color: Color::BLACK
@@ -216,14 +298,15 @@ impl<'a> PianoRoll<'a> {
 
     fn draw_pitch_grid(&self, bounds: Rectangle) -> Vec<Primitive> {
         let lines = {
+            // higher pitches are further up
             let grid = self.settings.pitch_grid.get_grid_lines(
-                Pitch::from_octave_f32(self.scroll_zoom_state.y.view_start),
-                Pitch::from_octave_f32(self.scroll_zoom_state.y.view_end)
+                Pitch::from_octave_f32(-self.scroll_zoom_state.y.view_end),
+                Pitch::from_octave_f32(-self.scroll_zoom_state.y.view_start)
             );
 
             grid.iter()
                 .map(|line| {
-                    let y = line.pitch.to_f32();
+                    let y = -line.pitch.to_f32();
 
                     let colour = match line.line_type {
                         pitch_grid::LineType::Tonic => Color::from([0.5, 1.0, 0.5, 0.3]),
@@ -280,11 +363,11 @@ impl<'a> Widget<PianoRollMessage, Renderer> for PianoRoll<'a> {
         let bounds = layout.bounds();
 
         let cursor_tick = self.state.cursor.tick;
-        let cursor_note = &self.state.cursor.pitch;
 
         let cursor_lines = self.draw_cursor(bounds);
         let tick_grid_lines = self.draw_tick_grid(bounds);
         let pitch_grid_lines = self.draw_pitch_grid(bounds);
+        let pitch_envelopes = self.draw_pitch_envelopes(bounds);
 
         let mut layers = vec![
             Primitive::Quad {
@@ -318,19 +401,26 @@ impl<'a> Widget<PianoRollMessage, Renderer> for PianoRoll<'a> {
                     })
                     .collect()
             },
+            pitch_envelopes,
             cursor_lines,
         ];
 
-        if let Selecting(start_tick, start_note) = &self.state.action {
-            layers.push(
-                Primitive::Quad {
-                    bounds: self.selection_rect(*start_tick, start_note, cursor_tick, &cursor_note, &bounds),
+        if let Some(tick) = self.state.step_tick {
+            layers.push(self.draw_tick_line(tick, Color::from_rgb(0.4, 1.0, 0.5), bounds));
+        }
+
+        if let Selecting(marquee) = &self.state.action {
+            let end = (cursor_tick, self.state.cursor.octave);
+            layers.push(match marquee.lasso {
+                true => self.draw_lasso(&marquee.points, end, bounds),
+                false => Primitive::Quad {
+                    bounds: self.selection_rect(marquee.points[0], end, &bounds),
                     background: Background::Color(Color::TRANSPARENT),
                     border_radius: 2.0,
                     border_width: 2.0,
                     border_color: Color::WHITE,
-                }
-            )
+                },
+            });
         }
 
         (
@@ -344,6 +434,11 @@ impl<'a> Widget<PianoRollMessage, Renderer> for PianoRoll<'a> {
             match self.state.action {
                 Dragging( .. ) => Interaction::Grabbing,
                 Resizing( .. ) => Interaction::ResizingHorizontally,
+                Action::Bending( .. ) | Action::Sliding( .. ) | Action::Vibrato( .. ) => Interaction::Crosshair,
+                Action::None if self.state.tool == Tool::Bend => match self.state.hover {
+                    HoverState::CanDrag( .. ) | HoverState::CanResize( .. ) => Interaction::Crosshair,
+                    _ => Interaction::Idle,
+                },
                 Action::None => match self.state.hover {
                     HoverState::None => Interaction::Idle,
                     HoverState::OutOfBounds => Interaction::default(),
@@ -367,7 +462,7 @@ impl<'a> Widget<PianoRollMessage, Renderer> for PianoRoll<'a> {
         if self.mouse_enabled {
             let inner_cursor = self.scroll_zoom_state.screen_to_inner(cursor_position, &bounds);
             self.state.update_cursor(
-                Cursor::new( inner_cursor.x as i32, Pitch::new(-(12.0 * inner_cursor.y).round() as i32, 12)),
+                Cursor::new( inner_cursor.x as i32, Pitch::new(-(12.0 * inner_cursor.y).round() as i32, 12), -inner_cursor.y),
                 messages,
                 &notes,
                 self.settings,
@@ -378,65 +473,116 @@ impl<'a> Widget<PianoRollMessage, Renderer> for PianoRoll<'a> {
         let cursor_tick = self.state.cursor.tick;
         let cursor_note = self.state.cursor.pitch.clone();
 
+        let mouse_modifiers = self.settings.keymap.mouse;
 
+        if let Event::Mouse(mouse::Event::ButtonPressed(_)) = event {
+            self.state.focused = bounds.contains(cursor_position);
+        }
 
         match event {
             Event::Mouse(mouse_event) => match mouse_event {
                 mouse::Event::CursorMoved { .. } => {
                 }
+                mouse::Event::ButtonPressed(mouse::Button::Left) if self.state.tool == Tool::Bend => {
+                    let hovered = match self.state.hover {
+                        CanDrag(id) | CanResize(id) => notes.get(id).map(|note| (id, note)),
+                        _ => None,
+                    };
+
+                    if let Some((id, note)) = hovered {
+                        let tick = (cursor_tick - note.tick).max(0).min(note.length);
+                        let action = if mouse_modifiers.slide.held(self.state.modifiers) {
+                            Action::Sliding(id, tick)
+                        } else if mouse_modifiers.vibrato.held(self.state.modifiers) {
+                            Action::Vibrato(id, tick)
+                        } else {
+                            let mut new_note = note.clone();
+                            new_note.pitch_envelope.draw(tick, tick, self.state.bend_offset(&self.state.cursor, note, self.settings));
+                            messages.push(PianoRollMessage::Gesture(SequenceChange::Update(id, new_note)));
+                            Action::Bending(id, tick)
+                        };
+                        messages.push(PianoRollMessage::SelfMessage(PianoRollSelfMessage::Action(action)));
+                    }
+                }
+                mouse::Event::ButtonPressed(mouse::Button::Right) if self.state.tool == Tool::Bend => {
+                    // clears the envelope
+                    if let CanDrag(id) | CanResize(id) = self.state.hover {
+                        if let Some(note) = notes.get(id) {
+                            let new_note = Note { pitch_envelope: Default::default(), ..note.clone() };
+                            messages.push(PianoRollMessage::Gesture(SequenceChange::Update(id, new_note)));
+                        }
+                    }
+                }
                 mouse::Event::ButtonPressed(mouse::Button::Left) => {
-                    if self.state.modifiers.control {
+                    if mouse_modifiers.select.held(self.state.modifiers) {
                         match self.state.hover {
                             HoverState::OutOfBounds => {}
-                            _ => messages.push(PianoRollMessage::SelfMessage(PianoRollSelfMessage::Action(Selecting(cursor_tick, cursor_note))))
+                            _ => {
+                                let marquee = Marquee {
+                                    mode: SelectMode::held(self.state.modifiers, self.settings),
+                                    before: self.state.selection.clone(),
+                                    points: vec![(cursor_tick, self.state.cursor.octave)],
+                                    lasso: self.state.lasso,
+                                };
+                                messages.push(PianoRollMessage::SelfMessage(PianoRollSelfMessage::Action(Selecting(marquee))))
+                            }
                         }
                     } else {
+                        let double_click = match self.state.hover {
+                            CanDrag(id) => self.state.click(id),
+                            _ => false,
+                        };
+
                         match self.state.hover {
                             HoverState::OutOfBounds => {}
                             HoverState::None => {
                                 let mut tick = cursor_tick;
 
-                                if !self.state.modifiers.alt {
+                                if !mouse_modifiers.unquantized.held(self.state.modifiers) {
                                     tick = self.settings.tick_grid.quantize_tick(tick);
                                 }
 
-                                match self.state.modifiers.shift {
+                                match mouse_modifiers.create_resizing.held(self.state.modifiers) {
                                     true => {
-                                        let length = match self.state.modifiers.alt {
+                                        let length = match mouse_modifiers.unquantized.held(self.state.modifiers) {
                                             true => 0,
                                             false => self.settings.tick_grid.grid_size(tick),
                                         };
-                                        let note = Note { tick, pitch: cursor_note.clone(), length };
+                                        let note = Note::new(tick, cursor_note.clone(), length);
                                         messages.push(PianoRollMessage::SynthCommand(SynthCommand::StartPreview(note.pitch.clone())));
-                                        messages.push( PianoRollMessage::SequenceChange(Add(note)));
+                                        messages.push( PianoRollMessage::Gesture(Add(note)));
                                         messages.push(PianoRollMessage::SelfMessage(PianoRollSelfMessage::ResizeLastCreatedNote(cursor_tick)));
                                     }
                                     false => {
-                                        let note = Note { tick, pitch: cursor_note.clone(), length: 32 };
+                                        let note = Note::new(tick, cursor_note.clone(), 32);
                                         messages.push(PianoRollMessage::SynthCommand(SynthCommand::StartPreview(note.pitch.clone())));
-                                        messages.push( PianoRollMessage::SequenceChange(Add(note)));
+                                        messages.push( PianoRollMessage::Gesture(Add(note)));
                                         messages.push(PianoRollMessage::SelfMessage(PianoRollSelfMessage::DragLastCreatedNote(cursor_tick)));
                                     }
                                 };
 
                                 self.state.selection.clear();
                             },
+                            CanDrag(id) if double_click => {
+                                messages.push(PianoRollMessage::SelfMessage(PianoRollSelfMessage::EditExpression(Some(id))));
+                            },
                             CanDrag(id) => {
                                 if let Some(note) = &notes.get(id) {
                                     messages.push(PianoRollMessage::SelfMessage(PianoRollSelfMessage::Action(Dragging(id, cursor_tick - note.tick))));
                                     messages.push(PianoRollMessage::SynthCommand(SynthCommand::StartPreview(note.pitch.clone())));
+                                    // so the keys act on what was last clicked
                                     if !self.state.selection.contains(&id) {
-                                        self.state.selection.clear();
+                                        self.state.selection = vec![id];
                                     }
-                                    if self.state.modifiers.shift {
+                                    if mouse_modifiers.duplicate.held(self.state.modifiers) {
                                         match self.state.selection.is_empty() {
                                             true => {
-                                                messages.push(PianoRollMessage::SequenceChange(Add((*note).clone())))
+                                                messages.push(PianoRollMessage::Gesture(Add((*note).clone())))
                                             },
                                             false => {
                                                 for id in &self.state.selection {
                                                     notes.get(*id).map(|note| {
-                                                        messages.push(PianoRollMessage::SequenceChange(Add(note.clone())))
+                                                        messages.push(PianoRollMessage::Gesture(Add(note.clone())))
                                                     });
                                                 }
                                             }
@@ -448,7 +594,7 @@ impl<'a> Widget<PianoRollMessage, Renderer> for PianoRoll<'a> {
                                 if let Some(note) = &notes.get(id) {
                                     messages.push(PianoRollMessage::SelfMessage(PianoRollSelfMessage::Action(Resizing(id, cursor_tick - note.tick - note.length))));
                                     if !self.state.selection.contains(&id) {
-                                        self.state.selection.clear();
+                                        self.state.selection = vec![id];
                                     }
                                 }
                             },
@@ -457,15 +603,49 @@ impl<'a> Widget<PianoRollMessage, Renderer> for PianoRoll<'a> {
                 mouse::Event::ButtonPressed(mouse::Button::Right) => {
                     self.state.delete_hovered(messages);
                 }
+                mouse::Event::WheelScrolled { delta } if bounds.contains(cursor_position) => {
+                    let pixels = wheel_pixels(delta);
+                    let other_axis = mouse_modifiers.wheel_axis.held(self.state.modifiers);
+                    let (x, y) = (&self.scroll_zoom_state.x, &self.scroll_zoom_state.y);
+
+                    if mouse_modifiers.wheel_zoom.held(self.state.modifiers) {
+                        let amount = pixels.x + pixels.y;
+                        messages.push(match other_axis {
+                            true => PianoRollMessage::ScrollY(y.zoomed(amount, cursor_position.y, bounds.y, bounds.height)),
+                            false => PianoRollMessage::ScrollX(x.zoomed(amount, cursor_position.x, bounds.x, bounds.width)),
+                        });
+                    } else {
+                        // trackpads can scroll both ways at once
+                        let (time, pitch) = match other_axis {
+                            true => (pixels.x + pixels.y, 0.0),
+                            false => (pixels.x, pixels.y),
+                        };
+                        if time != 0.0 {
+                            messages.push(PianoRollMessage::ScrollX(x.scrolled(time, bounds.width)));
+                        }
+                        if pitch != 0.0 {
+                            messages.push(PianoRollMessage::ScrollY(y.scrolled(pitch, bounds.height)));
+                        }
+                    }
+                }
                 mouse::Event::ButtonReleased( .. ) => {
                     messages.push(PianoRollMessage::SelfMessage(PianoRollSelfMessage::Action(Action::None)));
                     messages.push(PianoRollMessage::SynthCommand(SynthCommand::StopPreview));
+                    messages.push(PianoRollMessage::GestureEnd);
                 }
                 _ => {}
             }
             Event::Keyboard(keyboard::Event::ModifiersChanged(modifiers)) => {
                 self.state.modifiers = modifiers;
             }
+            Event::Keyboard(keyboard::Event::KeyPressed { key_code, modifiers }) if self.state.focused => {
+                self.state.key_pressed(key_code, modifiers, &notes, self.settings, self.playback_state.playback_cursor, messages);
+            }
+            Event::Keyboard(keyboard::Event::KeyReleased { key_code, .. }) if self.state.focused => {
+                if self.state.previews_key(key_code, self.settings) {
+                    messages.push(PianoRollMessage::SynthCommand(SynthCommand::StopPreview));
+                }
+            }
             _ => {}
         }
 
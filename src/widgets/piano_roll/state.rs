@@ -1,10 +1,14 @@
-use iced_native::keyboard::Modifiers;
-use crate::sequence::{NoteId, Pitch, Sequence, Note, SequenceChange};
+use std::time::{Duration, Instant};
+
+use iced_native::keyboard::{KeyCode, Modifiers};
+use crate::sequence::{NoteId, Pitch, Sequence, Note, SequenceChange, Vibrato};
 use crate::widgets::piano_roll::state::Action::{Dragging, Resizing};
 use derive_more::{Constructor};
 use std::cmp::{max, min};
 use crate::widgets::piano_roll::{PianoRollMessage, PianoRollSettings};
 use crate::audio::SynthCommand;
+use crate::keymap::{Action as KeyAction, STEP_KEYS};
+use crate::edit;
 
 pub struct PianoRollState {
     pub(crate) action: Action,
@@ -12,6 +16,39 @@ pub struct PianoRollState {
     pub(crate) modifiers: Modifiers,
     pub(crate) selection: Vec<NoteId>,
     pub(crate) cursor: Cursor,
+    pub(crate) tool: Tool,
+    /// the note whose expression is being edited
+    pub(crate) expression_note: Option<NoteId>,
+    pub(crate) last_click: Option<(Instant, NoteId)>,
+    /// keys only edit once the roll has been clicked in
+    pub(crate) focused: bool,
+    /// where the next note typed goes, while step entry is on
+    pub(crate) step_tick: Option<i32>,
+    /// octaves from middle C of the lower row of step keys
+    pub(crate) step_octave: i32,
+    /// each step entered so far, as the tick it started at and the note
+    /// added there, if it wasn't a rest
+    pub(crate) step_history: Vec<(i32, Option<NoteId>)>,
+    /// the note typed last, until the sequence gives it an id
+    pub(crate) step_pending: Option<Note>,
+    /// dragging a selection draws a lasso rather than a rectangle
+    pub(crate) lasso: bool,
+}
+
+/// ticks per vibrato cycle drawn with the bend tool
+const VIBRATO_PERIOD: i32 = 48;
+const DOUBLE_CLICK_TIME: Duration = Duration::from_millis(400);
+/// alt with the arrow keys moves by this fraction of a grid step
+const FINE_STEP_DIVISIONS: i32 = 8;
+/// in octaves
+pub(crate) const NOTE_HEIGHT: f32 = 1.0 / 12.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Tool {
+    /// add, move and resize notes
+    Notes,
+    /// draw pitch envelopes on notes
+    Bend,
 }
 
 pub enum HoverState {
@@ -27,7 +64,111 @@ pub enum Action {
     Deleting,
     Dragging(NoteId, i32),
     Resizing(NoteId, i32),
-    Selecting(i32, Pitch),
+    Selecting(Marquee),
+    /// drawing a note's envelope freehand, from the last tick drawn at
+    Bending(NoteId, i32),
+    /// dragging a slide out from a tick in the note
+    Sliding(NoteId, i32),
+    /// dragging vibrato depth, starting at a tick in the note
+    Vibrato(NoteId, i32),
+}
+
+/// How a selection combines with what was selected before it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SelectMode {
+    Replace,
+    Add,
+    Subtract,
+}
+
+impl SelectMode {
+    pub(crate) fn held(modifiers: Modifiers, settings: &PianoRollSettings) -> Self {
+        let mouse_modifiers = settings.keymap.mouse;
+        if mouse_modifiers.select_subtract.held(modifiers) {
+            SelectMode::Subtract
+        } else if mouse_modifiers.select_add.held(modifiers) {
+            SelectMode::Add
+        } else {
+            SelectMode::Replace
+        }
+    }
+
+    fn combine(self, before: &[NoteId], hits: impl Iterator<Item=NoteId>) -> Vec<NoteId> {
+        match self {
+            SelectMode::Replace => hits.collect(),
+            SelectMode::Add => {
+                let mut selection = before.to_vec();
+                selection.extend(hits.filter(|id| !before.contains(id)));
+                selection
+            }
+            SelectMode::Subtract => {
+                let hits: Vec<NoteId> = hits.collect();
+                before.iter().filter(|id| !hits.contains(id)).cloned().collect()
+            }
+        }
+    }
+}
+
+/// A selection being dragged out, in (tick, octave) points: the corner a
+/// rectangle started from, or every point along a lasso.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Marquee {
+    pub mode: SelectMode,
+    /// what was selected when the drag started
+    pub before: Vec<NoteId>,
+    pub points: Vec<(i32, f32)>,
+    pub lasso: bool,
+}
+
+impl Marquee {
+    /// whether the note is in the rectangle to the cursor, or the lasso
+    /// closed back to its start
+    fn hits(&self, cursor: &Cursor, note: &Note) -> bool {
+        let pitch = note.pitch.to_f32();
+
+        match self.lasso {
+            false => {
+                let (start_tick, start_octave) = self.points[0];
+                let (from_tick, to_tick) = (min(start_tick, cursor.tick), max(start_tick, cursor.tick));
+                let (from_octave, to_octave) = (start_octave.min(cursor.octave), start_octave.max(cursor.octave));
+
+                note.tick <= to_tick && note.end_tick() >= from_tick
+                    && pitch - NOTE_HEIGHT / 2.0 <= to_octave && pitch + NOTE_HEIGHT / 2.0 >= from_octave
+            }
+            true => {
+                let mut points: Vec<(f32, f32)> = self.points.iter().map(|&(tick, octave)| (tick as f32, octave)).collect();
+                points.push((cursor.tick as f32, cursor.octave));
+
+                [note.tick, note.tick + note.length / 2, note.end_tick()].iter()
+                    .any(|&tick| polygon_contains(&points, tick as f32, pitch))
+            }
+        }
+    }
+}
+
+/// Picks out notes to select by what they are rather than where.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SelectCriterion {
+    /// in any octave of a selected note's pitch
+    PitchClass,
+    /// at a selected note's pitch
+    Pitch,
+    /// starting from one tick up to another
+    TimeRange(i32, i32),
+    /// notes have no velocity, so this is the pressure they start with
+    PressureAbove(f32),
+}
+
+impl SelectCriterion {
+    fn matches(self, note: &Note, selected_pitches: &[Pitch]) -> bool {
+        match self {
+            SelectCriterion::PitchClass => selected_pitches.iter().any(|pitch| (note.pitch.0 - pitch.0).is_integer()),
+            SelectCriterion::Pitch => selected_pitches.contains(&note.pitch),
+            SelectCriterion::TimeRange(start, end) => note.tick >= start && note.tick < end,
+            // without an envelope a note plays at full pressure
+            SelectCriterion::PressureAbove(threshold) => note.expression.pressure.value_at(0.0).unwrap_or(1.0) > threshold,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -35,6 +176,14 @@ pub enum PianoRollSelfMessage {
     Action(Action),
     DragLastCreatedNote(i32),
     ResizeLastCreatedNote(i32),
+    SetTool(Tool),
+    EditExpression(Option<NoteId>),
+    /// selects the notes at these positions, e.g. ones just added
+    SelectAt(Vec<(i32, Pitch)>),
+    /// turns step entry on at a tick, or off
+    SetStepEntry(Option<i32>),
+    SetLasso(bool),
+    SelectWhere(SelectCriterion),
 }
 
 impl Default for PianoRollState {
@@ -45,6 +194,15 @@ impl Default for PianoRollState {
             modifiers: Modifiers::default(),
             selection: vec![],
             cursor: Cursor::default(),
+            tool: Tool::Notes,
+            expression_note: None,
+            last_click: None,
+            focused: false,
+            step_tick: None,
+            step_octave: 0,
+            step_history: vec![],
+            step_pending: None,
+            lasso: false,
         }
     }
 }
@@ -53,6 +211,8 @@ impl Default for PianoRollState {
 pub struct Cursor {
     pub tick: i32,
     pub pitch: Pitch,
+    /// the unquantized pitch, in octaves
+    pub octave: f32,
 }
 
 impl PianoRollState {
@@ -69,16 +229,218 @@ impl PianoRollState {
                     self.action = Resizing(id, cursor_tick - note.tick);
                 }
             }
+            PianoRollSelfMessage::SetTool(tool) => {
+                self.tool = tool;
+                self.action = Action::None;
+            }
+            PianoRollSelfMessage::EditExpression(id) => {
+                self.expression_note = id;
+                self.action = Action::None;
+            }
+            PianoRollSelfMessage::SelectAt(positions) => {
+                self.selection = notes.iter()
+                    .filter(|(_id, note)| positions.iter().any(|(tick, pitch)| note.tick == *tick && &note.pitch == pitch))
+                    .map(|(id, _note)| id)
+                    .collect();
+            }
+            PianoRollSelfMessage::SetStepEntry(tick) => {
+                self.step_tick = tick;
+                self.step_history.clear();
+                self.step_pending = None;
+                self.focused |= tick.is_some();
+            }
+            PianoRollSelfMessage::SetLasso(lasso) => {
+                self.lasso = lasso;
+            }
+            PianoRollSelfMessage::SelectWhere(criterion) => {
+                let selected_pitches: Vec<Pitch> = self.selection.iter()
+                    .filter_map(|id| notes.get(*id))
+                    .map(|note| note.pitch.clone())
+                    .collect();
+
+                self.selection = notes.iter()
+                    .filter(|(_id, note)| criterion.matches(note, &selected_pitches))
+                    .map(|(id, _note)| id)
+                    .collect();
+            }
+        }
+    }
+
+    pub(crate) fn key_pressed(&mut self, key_code: KeyCode, modifiers: Modifiers, notes: &Sequence, settings: &PianoRollSettings, playhead: i32, messages: &mut Vec<PianoRollMessage>) {
+        self.resolve_step(notes);
+
+        if let Some(tick) = self.step_tick {
+            if !modifiers.control {
+                if let Some((_, semitone)) = STEP_KEYS.iter().find(|(key, _)| *key == key_code) {
+                    let length = settings.tick_grid.grid_size(tick);
+                    // middle C is 9 semitones below A 440
+                    let note = Note::new(tick, Pitch::new(semitone - 9 + 12 * self.step_octave, 12), length);
+                    messages.push(PianoRollMessage::SynthCommand(SynthCommand::StartPreview(note.pitch.clone())));
+                    messages.push(PianoRollMessage::SequenceChange(SequenceChange::Add(note.clone())));
+                    self.step_pending = Some(note);
+                    self.step_tick = Some(tick + length);
+                    return;
+                }
+            }
+
+            if let Some(action) = settings.keymap.step_action(key_code, modifiers) {
+                self.step(action, tick, settings, messages);
+                return;
+            }
+        }
+
+        if let Some(action) = settings.keymap.action(key_code, modifiers) {
+            self.perform(action, notes, settings, playhead, messages);
+        }
+    }
+
+    fn perform(&mut self, action: KeyAction, notes: &Sequence, settings: &PianoRollSettings, playhead: i32, messages: &mut Vec<PianoRollMessage>) {
+        let selected: Vec<(NoteId, &Note)> = self.selection.iter()
+            .filter_map(|id| notes.get(*id).map(|note| (*id, note)))
+            .collect();
+
+        match action {
+            KeyAction::SelectAll => {
+                self.selection = notes.iter().map(|(id, _note)| id).collect();
+            }
+            KeyAction::DuplicateAfter => {
+                duplicate_after(&selected, messages);
+            }
+            KeyAction::Delete => {
+                let removed = selected.iter().map(|(id, _note)| SequenceChange::Remove(*id)).collect();
+                messages.push(PianoRollMessage::SequenceChange(SequenceChange::Batch(removed)));
+                self.selection.clear();
+            }
+            KeyAction::NudgeLeft | KeyAction::NudgeRight | KeyAction::NudgeLeftFine | KeyAction::NudgeRightFine
+            | KeyAction::Shorten | KeyAction::Lengthen | KeyAction::ShortenFine | KeyAction::LengthenFine
+            if !selected.is_empty() => {
+                let min_tick = selected.iter().map(|(_, note)| note.tick).min().unwrap();
+                let mut step = settings.tick_grid.grid_size(min_tick);
+                if matches!(action, KeyAction::NudgeLeftFine | KeyAction::NudgeRightFine | KeyAction::ShortenFine | KeyAction::LengthenFine) {
+                    step = max(1, step / FINE_STEP_DIVISIONS);
+                }
+                if matches!(action, KeyAction::NudgeLeft | KeyAction::NudgeLeftFine | KeyAction::Shorten | KeyAction::ShortenFine) {
+                    step = -step;
+                }
+                let resize = matches!(action, KeyAction::Shorten | KeyAction::Lengthen | KeyAction::ShortenFine | KeyAction::LengthenFine);
+
+                let mut moved = vec![];
+                for (id, note) in selected {
+                    let new_note = match resize {
+                        // shortening stops at a step, or the note's length if shorter
+                        true => Note { length: max(note.length + step, min(note.length, step.abs())), ..note.clone() },
+                        false => Note { tick: note.tick + max(step, -min_tick), ..note.clone() },
+                    };
+
+                    if note != &new_note {
+                        moved.push(SequenceChange::Update(id, new_note));
+                    }
+                }
+                messages.push(PianoRollMessage::SequenceChange(SequenceChange::Batch(moved)));
+            }
+            KeyAction::ScaleStepUp | KeyAction::ScaleStepDown | KeyAction::OctaveUp | KeyAction::OctaveDown
+            if !selected.is_empty() => {
+                let transposed: Vec<(NoteId, Note)> = selected.iter()
+                    .map(|(id, note)| {
+                        let pitch = match action {
+                            KeyAction::OctaveUp => note.pitch.clone() + Pitch::new(1, 1),
+                            KeyAction::OctaveDown => note.pitch.clone() - Pitch::new(1, 1),
+                            _ => settings.pitch_grid.scale_step(note.pitch.clone(), action == KeyAction::ScaleStepUp),
+                        };
+                        (*id, Note { pitch, ..(*note).clone() })
+                    })
+                    .collect();
+
+                // same arbitrary range as dragging
+                if transposed.iter().all(|(_, note)| note.pitch >= Pitch::new(-4, 1) && note.pitch <= Pitch::new(4, 1)) {
+                    messages.push(PianoRollMessage::SynthCommand(SynthCommand::StartPreview(transposed[0].1.pitch.clone())));
+                    let changes = transposed.into_iter().map(|(id, note)| SequenceChange::Update(id, note)).collect();
+                    messages.push(PianoRollMessage::SequenceChange(SequenceChange::Batch(changes)));
+                }
+            }
+            KeyAction::StepRest | KeyAction::StepBack | KeyAction::StepOctaveDown | KeyAction::StepOctaveUp => {
+                if let Some(tick) = self.step_tick {
+                    self.step(action, tick, settings, messages);
+                }
+            }
+            KeyAction::Legato if !selected.is_empty() => {
+                messages.push(PianoRollMessage::SequenceChange(edit::legato(notes, &selected)));
+            }
+            KeyAction::Glue if !selected.is_empty() => {
+                messages.push(PianoRollMessage::SequenceChange(edit::glue(&selected)));
+            }
+            KeyAction::SplitAtPlayhead if !selected.is_empty() => {
+                messages.push(PianoRollMessage::SequenceChange(edit::split(&selected, playhead)));
+            }
+            KeyAction::SplitAtMouse if !selected.is_empty() => {
+                messages.push(PianoRollMessage::SequenceChange(edit::split(&selected, self.cursor.tick)));
+            }
+            KeyAction::Chop if !selected.is_empty() => {
+                messages.push(PianoRollMessage::SequenceChange(edit::chop(&selected, &*settings.tick_grid)));
+            }
+            _ => {}
         }
     }
 
+    /// Notes the id of the last note typed, which the sequence has added
+    /// since. It may not be there, if resolving overlaps removed it.
+    fn resolve_step(&mut self, notes: &Sequence) {
+        if let Some(pending) = self.step_pending.take() {
+            let id = notes.last_added()
+                .filter(|(_id, note)| note.tick == pending.tick && note.pitch == pending.pitch)
+                .map(|(id, _note)| id);
+            self.step_history.push((pending.tick, id));
+        }
+    }
+
+    fn step(&mut self, action: KeyAction, tick: i32, settings: &PianoRollSettings, messages: &mut Vec<PianoRollMessage>) {
+        let length = settings.tick_grid.grid_size(tick);
+
+        match action {
+            KeyAction::StepRest => {
+                self.step_history.push((tick, None));
+                self.step_tick = Some(tick + length);
+            }
+            // steps back over the last step, removing the note entered there
+            KeyAction::StepBack => match self.step_history.pop() {
+                Some((previous, id)) => {
+                    if let Some(id) = id {
+                        messages.push(PianoRollMessage::SequenceChange(SequenceChange::Remove(id)));
+                    }
+                    self.step_tick = Some(previous);
+                }
+                // nothing entered yet, so there's nothing to remove
+                None => self.step_tick = Some(max(0, tick - length)),
+            },
+            KeyAction::StepOctaveDown => self.step_octave = max(-4, self.step_octave - 1),
+            KeyAction::StepOctaveUp => self.step_octave = min(3, self.step_octave + 1),
+            _ => {}
+        }
+    }
+
+    /// whether releasing a key should stop the note it previewed
+    pub(crate) fn previews_key(&self, key_code: KeyCode, settings: &PianoRollSettings) -> bool {
+        settings.keymap.previews_key(key_code)
+            || (self.step_tick.is_some() && STEP_KEYS.iter().any(|(key, _)| *key == key_code))
+    }
+
+    /// Notes a click on a note, returning whether it was the second of a
+    /// double click.
+    pub(crate) fn click(&mut self, id: NoteId) -> bool {
+        let now = Instant::now();
+        let double = matches!(self.last_click, Some((time, last_id)) if last_id == id && now - time < DOUBLE_CLICK_TIME);
+
+        self.last_click = if double { None } else { Some((now, id)) };
+        double
+    }
+
     pub(crate) fn delete_hovered(&mut self, messages: &mut Vec<PianoRollMessage>) {
         match self.hover {
             HoverState::None => {
                 messages.push(PianoRollMessage::SelfMessage(PianoRollSelfMessage::Action(Action::Deleting)));
             },
             HoverState::CanDrag(id) => {
-                messages.push(PianoRollMessage::SequenceChange(SequenceChange::Remove(id)));
+                messages.push(PianoRollMessage::Gesture(SequenceChange::Remove(id)));
                 messages.push(PianoRollMessage::SelfMessage(PianoRollSelfMessage::Action(Action::Deleting)));
             },
             HoverState::CanResize(_) => {
@@ -88,6 +450,15 @@ impl PianoRollState {
         }
     }
 
+    /// the interval from a note to the cursor, to the nearest semitone unless
+    /// the unquantized modifier is held
+    pub(crate) fn bend_offset(&self, cursor: &Cursor, note: &Note, settings: &PianoRollSettings) -> Pitch {
+        match settings.keymap.mouse.unquantized.held(self.modifiers) {
+            true => Pitch::from_octave_f32(cursor.octave - note.pitch.to_f32()),
+            false => cursor.pitch.clone() - note.pitch.clone(),
+        }
+    }
+
     pub fn update_cursor(&mut self, cursor: Cursor, messages: &mut Vec<PianoRollMessage>, notes: &Sequence, settings: &PianoRollSettings) {
         if self.cursor != cursor {
             match &self.action {
@@ -95,7 +466,7 @@ impl PianoRollState {
                     if let Some(note) = notes.get(*note_id) {
                         let quantize_offset = note.tick - settings.tick_grid.quantize_tick(note.tick);
                         let mut tick = max(0, cursor.tick - drag_offset);
-                        if !self.modifiers.alt {
+                        if !settings.keymap.mouse.unquantized.held(self.modifiers) {
                             tick = settings.tick_grid.quantize_tick(tick - quantize_offset) + quantize_offset;
                         }
 
@@ -121,11 +492,11 @@ impl PianoRollState {
                             let new_note = Note {
                                 tick: note.tick + tick_offset,
                                 pitch: note.pitch.clone() + note_offset.clone(),
-                                ..*note
+                                ..note.clone()
                             };
 
                             if note != &new_note {
-                                messages.push(PianoRollMessage::SequenceChange(SequenceChange::Update(note_id, new_note.clone())));
+                                messages.push(PianoRollMessage::Gesture(SequenceChange::Update(note_id, new_note.clone())));
                             }
                             if &note.pitch != &new_note.pitch {
                                 messages.push(PianoRollMessage::SynthCommand(SynthCommand::StartPreview(new_note.pitch.clone())));
@@ -137,7 +508,7 @@ impl PianoRollState {
                     if let Some(note) = notes.get(*note_id) {
                         let quantize_offset = note.tick + note.length - settings.tick_grid.quantize_tick(note.tick + note.length);
                         let mut tick = cursor.tick - drag_offset;
-                        if !self.modifiers.alt {
+                        if !settings.keymap.mouse.unquantized.held(self.modifiers) {
                             tick = settings.tick_grid.quantize_tick(tick - quantize_offset) + quantize_offset;
                         }
                         let length = tick - note.tick;
@@ -151,7 +522,7 @@ impl PianoRollState {
                         }
 
                         let mut min_length = selected_notes.iter().map(|(_, note)| note.length).min().unwrap();
-                        if !self.modifiers.alt {
+                        if !settings.keymap.mouse.unquantized.held(self.modifiers) {
                             min_length -= settings.tick_grid.grid_size(tick);
                         }
 
@@ -164,7 +535,7 @@ impl PianoRollState {
                             };
 
                             if note != &new_note {
-                                messages.push( PianoRollMessage::SequenceChange(SequenceChange::Update(note_id, new_note)));
+                                messages.push( PianoRollMessage::Gesture(SequenceChange::Update(note_id, new_note)));
                             }
                         }
                     }
@@ -172,16 +543,45 @@ impl PianoRollState {
                 Action::Deleting => {
                     self.delete_hovered(messages);
                 },
-                Action::Selecting(start_tick, start_note) => {
-                    let from_tick = min(start_tick, &cursor.tick).clone();
-                    let to_tick = max(start_tick, &cursor.tick).clone();
-                    let from_note = min(start_note, &cursor.pitch).clone();
-                    let to_note = max(start_note, &cursor.pitch).clone();
-
-                    self.selection = notes.iter()
-                        .filter(|(_id, note)| note.tick <= to_tick && note.end_tick() >= from_tick && note.pitch <= to_note && note.pitch >= from_note)
-                        .map(|(id, _note)| id)
-                        .collect();
+                Action::Selecting(marquee) => {
+                    let hits = notes.iter()
+                        .filter(|(_id, note)| marquee.hits(&cursor, note))
+                        .map(|(id, _note)| id);
+                    self.selection = marquee.mode.combine(&marquee.before, hits);
+
+                    if marquee.lasso {
+                        let mut marquee = marquee.clone();
+                        marquee.points.push((cursor.tick, cursor.octave));
+                        self.action = Action::Selecting(marquee);
+                    }
+                }
+                Action::Bending(note_id, last_tick) => {
+                    if let Some(note) = notes.get(*note_id) {
+                        let tick = (cursor.tick - note.tick).max(0).min(note.length);
+                        let mut new_note = note.clone();
+                        new_note.pitch_envelope.draw(*last_tick, tick, self.bend_offset(&cursor, note, settings));
+
+                        messages.push(PianoRollMessage::Gesture(SequenceChange::Update(*note_id, new_note)));
+                        self.action = Action::Bending(*note_id, tick);
+                    }
+                }
+                Action::Sliding(note_id, start_tick) => {
+                    if let Some(note) = notes.get(*note_id) {
+                        let tick = (cursor.tick - note.tick).max(0).min(note.length);
+                        let mut new_note = note.clone();
+                        new_note.pitch_envelope.slide(*start_tick, tick, self.bend_offset(&cursor, note, settings));
+
+                        messages.push(PianoRollMessage::Gesture(SequenceChange::Update(*note_id, new_note)));
+                    }
+                }
+                Action::Vibrato(note_id, start_tick) => {
+                    if let Some(note) = notes.get(*note_id) {
+                        let depth = (cursor.octave - note.pitch.to_f32()).abs();
+                        let mut new_note = note.clone();
+                        new_note.pitch_envelope.vibrato = Some(Vibrato { delay: *start_tick, period: VIBRATO_PERIOD, depth });
+
+                        messages.push(PianoRollMessage::Gesture(SequenceChange::Update(*note_id, new_note)));
+                    }
                 }
                 Action::None => { },
             }
@@ -191,3 +591,39 @@ impl PianoRollState {
 
     }
 }
+
+/// Even-odd test of a point against a closed polygon.
+fn polygon_contains(points: &[(f32, f32)], x: f32, y: f32) -> bool {
+    if points.len() < 3 {
+        return false;
+    }
+
+    let mut inside = false;
+    let mut previous = points[points.len() - 1];
+    for &point in points {
+        let ((x0, y0), (x1, y1)) = (previous, point);
+        if (y0 > y) != (y1 > y) && x < x0 + (y - y0) / (y1 - y0) * (x1 - x0) {
+            inside = !inside;
+        }
+        previous = point;
+    }
+    inside
+}
+
+/// Copies notes to just after the last of them ends, selecting the copies.
+fn duplicate_after(selected: &[(NoteId, &Note)], messages: &mut Vec<PianoRollMessage>) {
+    let start = match selected.iter().map(|(_, note)| note.tick).min() {
+        Some(start) => start,
+        None => return,
+    };
+    let end = selected.iter().map(|(_, note)| note.end_tick()).max().unwrap();
+
+    let copies: Vec<Note> = selected.iter()
+        .map(|(_, note)| Note { tick: note.tick + end - start, ..(*note).clone() })
+        .collect();
+
+    let positions = copies.iter().map(|note| (note.tick, note.pitch.clone())).collect();
+    let added = copies.into_iter().map(SequenceChange::Add).collect();
+    messages.push(PianoRollMessage::SequenceChange(SequenceChange::Batch(added)));
+    messages.push(PianoRollMessage::SelfMessage(PianoRollSelfMessage::SelectAt(positions)));
+}
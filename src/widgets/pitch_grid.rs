@@ -17,8 +17,37 @@ pub struct GridLine {
 pub trait PitchGrid {
     fn get_grid_lines(&self, start: Pitch, end: Pitch) -> Vec<GridLine>;
     fn quantize_pitch(&self, pitch: Pitch) -> Pitch;
+
+    /// what to call a line on the keyboard, by default its distance from
+    /// A 440 as a fraction of an octave
+    fn label(&self, pitch: &Pitch) -> String {
+        pitch.0.to_string()
+    }
+
+    /// The next line up or down that isn't a black one, keeping any offset
+    /// the pitch has from the grid.
+    fn scale_step(&self, pitch: Pitch, up: bool) -> Pitch {
+        let on_grid = self.quantize_pitch(pitch.clone());
+        let offset = pitch.clone() - on_grid.clone();
+        let octave = Pitch::new(1, 1);
+
+        let lines = match up {
+            true => self.get_grid_lines(on_grid.clone(), on_grid.clone() + octave),
+            false => self.get_grid_lines(on_grid.clone() - octave, on_grid.clone()),
+        };
+        let mut scale = lines.into_iter()
+            .filter(|line| line.line_type != LineType::Black && line.pitch != on_grid);
+
+        let next = match up {
+            true => scale.next(),
+            false => scale.last(),
+        };
+        next.map_or(pitch, |line| line.pitch + offset)
+    }
 }
 
+const NOTE_NAMES: [&str; 12] = ["C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B"];
+
 pub struct TetGrid {
     pub tones_per_octave: i32,
     pub pattern: Vec<LineType>,
@@ -55,4 +84,18 @@ impl PitchGrid for TetGrid {
         let ratio = pitch.0.mul(self.tones_per_octave).round().div(self.tones_per_octave);
         Pitch::from_octave(ratio)
     }
+
+    /// note names for 12 tones, otherwise steps up from A and the octave
+    fn label(&self, pitch: &Pitch) -> String {
+        let steps = pitch.0.mul(self.tones_per_octave).round().to_integer();
+
+        match self.tones_per_octave {
+            12 => {
+                // MIDI numbering, octaves starting at C
+                let key = steps + 69;
+                format!("{}{}", NOTE_NAMES[key.rem_euclid(12) as usize], key.div_euclid(12) - 1)
+            }
+            tones => format!("{}\\{} {}", steps.rem_euclid(tones), tones, steps.div_euclid(tones) + 4),
+        }
+    }
 }
\ No newline at end of file
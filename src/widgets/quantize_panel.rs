@@ -0,0 +1,122 @@
+use iced::{Button, Checkbox, Column, Element, Length, Row, Slider, Text, button, slider};
+
+use crate::quantize::{Groove, HumanizeSettings, QuantizeSettings};
+
+#[derive(Debug, Clone)]
+pub enum QuantizeMessage {
+    SetQuantize(QuantizeSettings),
+    SetHumanize(HumanizeSettings),
+    Quantize,
+    Humanize,
+    /// takes a groove from the selected notes
+    ExtractGroove,
+    ApplyGroove,
+}
+
+/// Quantize, humanize and groove settings, acting on the selected notes.
+#[derive(Default)]
+pub struct QuantizePanel {
+    quantize_sliders: [slider::State; 3],
+    humanize_sliders: [slider::State; 4],
+    quantize_button: button::State,
+    humanize_button: button::State,
+    extract_groove_button: button::State,
+    apply_groove_button: button::State,
+}
+
+impl QuantizePanel {
+    pub fn view(
+        &mut self,
+        quantize: QuantizeSettings,
+        humanize: HumanizeSettings,
+        groove: Option<&Groove>,
+        has_selection: bool,
+    ) -> Element<QuantizeMessage> {
+        let set_quantize = move |quantize: QuantizeSettings| QuantizeMessage::SetQuantize(quantize);
+        let set_humanize = move |humanize: HumanizeSettings| QuantizeMessage::SetHumanize(humanize);
+
+        let mut quantize_button = Button::new(&mut self.quantize_button, Text::new("Quantize"));
+        let mut humanize_button = Button::new(&mut self.humanize_button, Text::new("Humanize"));
+        let mut extract_groove_button = Button::new(&mut self.extract_groove_button, Text::new("Take groove"));
+        let mut apply_groove_button = Button::new(&mut self.apply_groove_button, Text::new("Apply groove"));
+        if has_selection {
+            quantize_button = quantize_button.on_press(QuantizeMessage::Quantize);
+            humanize_button = humanize_button.on_press(QuantizeMessage::Humanize);
+            extract_groove_button = extract_groove_button.on_press(QuantizeMessage::ExtractGroove);
+            if groove.is_some() {
+                apply_groove_button = apply_groove_button.on_press(QuantizeMessage::ApplyGroove);
+            }
+        }
+
+        let [strength, swing, window] = &mut self.quantize_sliders;
+        let quantize_row = Row::new()
+            .spacing(8)
+            .push(quantize_button)
+            .push(Checkbox::new(quantize.starts, "Starts", move |starts| {
+                set_quantize(QuantizeSettings { starts, ..quantize })
+            }))
+            .push(Checkbox::new(quantize.ends, "Ends", move |ends| {
+                set_quantize(QuantizeSettings { ends, ..quantize })
+            }))
+            .push(Checkbox::new(quantize.lengths, "Lengths", move |lengths| {
+                set_quantize(QuantizeSettings { lengths, ..quantize })
+            }))
+            .push(control(strength, format!("Strength {:.0}%", quantize.strength * 100.0), 0.0..=1.0, 0.01, quantize.strength, move |strength| {
+                set_quantize(QuantizeSettings { strength, ..quantize })
+            }))
+            .push(control(swing, format!("Swing {:.0}%", quantize.swing * 100.0), 0.0..=1.0, 0.01, quantize.swing, move |swing| {
+                set_quantize(QuantizeSettings { swing, ..quantize })
+            }))
+            .push(control(window, format!("Window {:.0}%", quantize.window * 100.0), 0.0..=1.0, 0.01, quantize.window, move |window| {
+                set_quantize(QuantizeSettings { window, ..quantize })
+            }));
+
+        let [timing, length, pressure, seed] = &mut self.humanize_sliders;
+        let humanize_row = Row::new()
+            .spacing(8)
+            .push(humanize_button)
+            .push(control(timing, format!("Timing ±{} ticks", humanize.timing), 0.0..=32.0, 1.0, humanize.timing as f32, move |timing| {
+                set_humanize(HumanizeSettings { timing: timing as i32, ..humanize })
+            }))
+            .push(control(length, format!("Length ±{} ticks", humanize.length), 0.0..=32.0, 1.0, humanize.length as f32, move |length| {
+                set_humanize(HumanizeSettings { length: length as i32, ..humanize })
+            }))
+            .push(control(pressure, format!("Pressure ±{:.2}", humanize.pressure), 0.0..=0.5, 0.01, humanize.pressure, move |pressure| {
+                set_humanize(HumanizeSettings { pressure, ..humanize })
+            }))
+            .push(control(seed, format!("Seed {}", humanize.seed), 0.0..=999.0, 1.0, humanize.seed as f32, move |seed| {
+                set_humanize(HumanizeSettings { seed: seed as u32, ..humanize })
+            }));
+
+        let groove_row = Row::new()
+            .spacing(8)
+            .push(extract_groove_button)
+            .push(apply_groove_button)
+            .push(Text::new(match groove {
+                Some(groove) => format!("{} lines over {} ticks, at the quantize strength", groove.offsets.len(), groove.length),
+                None => "No groove taken".to_string(),
+            }).size(14));
+
+        Column::new()
+            .spacing(4)
+            .push(quantize_row)
+            .push(humanize_row)
+            .push(groove_row)
+            .into()
+    }
+}
+
+fn control<'a>(
+    state: &'a mut slider::State,
+    label: String,
+    range: std::ops::RangeInclusive<f32>,
+    step: f32,
+    value: f32,
+    on_change: impl Fn(f32) -> QuantizeMessage + 'static,
+) -> Element<'a, QuantizeMessage> {
+    Column::new()
+        .width(Length::Units(120))
+        .push(Text::new(label).size(14))
+        .push(Slider::new(state, range, value, on_change).step(step))
+        .into()
+}
@@ -1,27 +1,73 @@
 use crate::widgets::piano_roll::{PianoRoll, PianoRollSettings, PianoRollMessage};
 use crate::widgets::timeline::{Timeline, TimelineState};
-use iced::{Element, Column, Row, Space, Length};
+use iced::{Button, Checkbox, Element, Column, PickList, Row, Slider, Space, Length, Text, button, pick_list, slider};
 use crate::widgets::scroll_bar::{Orientation, ScrollZoomBar, ScrollZoomBarState};
-use crate::scroll_zoom::{ScrollZoomState, ScrollScaleAxisChange, ScrollScaleAxis};
+use crate::scroll_zoom::{FollowMode, ScrollZoomState, ScrollScaleAxisChange, ScrollScaleAxis};
 use std::sync::{Arc, Mutex};
-use crate::sequence::{Sequence, SequenceChange};
+use crate::automation::{Automation, AutomationChange, AutomationTarget};
+use crate::sequence::{Note, NoteId, Pitch, Sequence, SequenceChange};
 use crate::audio::{PlaybackState, SynthCommand};
 
 use SequenceEditorMessage::SelfMessage;
 use SequenceEditorSelfMessage::{ScrollUpdateX, ScrollUpdateY};
-use crate::widgets::piano_roll::state::{PianoRollState, PianoRollSelfMessage};
+use crate::widgets::keyboard::{self, KeyboardState, PianoKeyboard};
+use crate::widgets::quantize_panel::{QuantizeMessage, QuantizePanel};
+use crate::widgets::transform_panel::{TransformMessage, TransformPanel};
+use crate::quantize::{Groove, HumanizeSettings, QuantizeSettings};
+use crate::widgets::automation_lane::{AutomationLaneState, AutomationLaneView, AutomationTool};
+use crate::widgets::note_expression::{ExpressionKind, NoteExpressionState, NoteExpressionStrip};
+use crate::widgets::piano_roll::state::{Action, PianoRollState, PianoRollSelfMessage, SelectCriterion, Tool};
 
 pub struct SequenceEditor {
     timeline: TimelineState,
     piano_roll: PianoRollState,
+    keyboard: KeyboardState,
     scroll_zoom: ScrollZoomState,
     scroll_bar_x: ScrollZoomBarState,
     scroll_bar_y: ScrollZoomBarState,
+    automation_tool: AutomationTool,
+    automation_tool_pick_list: pick_list::State<AutomationTool>,
+    add_lane_button: button::State,
+    automation_targets: Vec<AutomationTarget>,
+    lanes: Vec<LaneControls>,
+    expression_strips: [NoteExpressionState; 2],
+    close_expression_button: button::State,
+    follow: FollowMode,
+    follow_pick_list: pick_list::State<FollowMode>,
+    /// set when the view is moved by hand during playback, until it next
+    /// starts
+    follow_suspended: bool,
+    playing: bool,
+    select_pitch_button: button::State,
+    select_pitch_class_button: button::State,
+    select_in_loop_button: button::State,
+    select_pressure_button: button::State,
+    pressure_threshold: f32,
+    pressure_threshold_slider: slider::State,
+    show_quantize: bool,
+    quantize_button: button::State,
+    quantize_panel: QuantizePanel,
+    quantize: QuantizeSettings,
+    humanize: HumanizeSettings,
+    groove: Option<Groove>,
+    show_transform: bool,
+    transform_button: button::State,
+    transform_panel: TransformPanel,
+}
+
+#[derive(Default)]
+struct LaneControls {
+    lane: AutomationLaneState,
+    target_pick_list: pick_list::State<AutomationTarget>,
+    remove_button: button::State,
 }
 
 #[derive(Debug, Clone)]
 pub enum SequenceEditorMessage {
     SequenceChange(SequenceChange),
+    Gesture(SequenceChange),
+    GestureEnd,
+    AutomationChange(AutomationChange),
     SynthCommand(SynthCommand),
     SelfMessage(SequenceEditorSelfMessage),
 }
@@ -31,6 +77,14 @@ pub enum SequenceEditorSelfMessage {
     PianoRoll(PianoRollSelfMessage),
     ScrollUpdateX(ScrollScaleAxisChange),
     ScrollUpdateY(ScrollScaleAxisChange),
+    SetAutomationTool(AutomationTool),
+    SetFollow(FollowMode),
+    SetFollowSuspended(bool),
+    SetPressureThreshold(f32),
+    ToggleQuantize,
+    Quantize(QuantizeMessage),
+    ToggleTransform,
+    Transform(TransformMessage),
 }
 
 impl Default for SequenceEditor {
@@ -38,12 +92,39 @@ impl Default for SequenceEditor {
         Self {
             timeline: TimelineState::new(),
             piano_roll: Default::default(),
+            keyboard: Default::default(),
             scroll_zoom: ScrollZoomState {
                 x: ScrollScaleAxis::new(0.0,32.0*32.0, 0.0, 32.0*32.0*4.0),
                 y: ScrollScaleAxis::new(-1.5, 3.0, -4.0, 8.0),
             },
             scroll_bar_x: Default::default(),
-            scroll_bar_y: Default::default()
+            scroll_bar_y: Default::default(),
+            automation_tool: AutomationTool::Pencil,
+            automation_tool_pick_list: Default::default(),
+            add_lane_button: button::State::new(),
+            automation_targets: AutomationTarget::all(),
+            lanes: vec![],
+            expression_strips: Default::default(),
+            close_expression_button: button::State::new(),
+            follow: FollowMode::Page,
+            follow_pick_list: Default::default(),
+            follow_suspended: false,
+            playing: false,
+            select_pitch_button: button::State::new(),
+            select_pitch_class_button: button::State::new(),
+            select_in_loop_button: button::State::new(),
+            select_pressure_button: button::State::new(),
+            pressure_threshold: 0.5,
+            pressure_threshold_slider: Default::default(),
+            show_quantize: false,
+            quantize_button: button::State::new(),
+            quantize_panel: Default::default(),
+            quantize: Default::default(),
+            humanize: Default::default(),
+            groove: None,
+            show_transform: false,
+            transform_button: button::State::new(),
+            transform_panel: Default::default(),
         }
     }
 }
@@ -51,15 +132,152 @@ impl Default for SequenceEditor {
 impl SequenceEditor {
     pub fn view<'a>(&'a mut self,
             notes: &'a Arc<Mutex<Sequence>>,
+            automation: &'a Arc<Mutex<Automation>>,
+            lane_names: Vec<String>,
             settings: &'a PianoRollSettings,
             playback_state: &'a PlaybackState,
+            held_pitches: Vec<Pitch>,
     ) -> Element<'a, SequenceEditorMessage> {
+        let targets: Vec<AutomationTarget> = automation.lock().unwrap().lanes().iter().map(|lane| lane.target).collect();
+        self.lanes.resize_with(targets.len(), Default::default);
+
+        let mut lanes = Column::new()
+            .spacing(2)
+            .push(Row::new()
+                .spacing(4)
+                .push(Text::new("Automation"))
+                .push(PickList::new(
+                    &mut self.automation_tool_pick_list,
+                    &AutomationTool::ALL[..],
+                    Some(self.automation_tool),
+                    |tool| SelfMessage(SequenceEditorSelfMessage::SetAutomationTool(tool)),
+                ))
+                .push(Button::new(&mut self.add_lane_button, Text::new("Add lane"))
+                    .on_press(SequenceEditorMessage::AutomationChange(AutomationChange::AddLane(AutomationTarget::ControlChange(1)))))
+            );
+
+        for (index, ((controls, target), name)) in self.lanes.iter_mut().zip(targets).zip(lane_names).enumerate() {
+            // parameter lanes come from recording, so can't be retargeted
+            let header: Element<_> = match target {
+                AutomationTarget::Parameter(_) => Text::new(name).into(),
+                _ => PickList::new(
+                    &mut controls.target_pick_list,
+                    &self.automation_targets,
+                    Some(target),
+                    move |target| SequenceEditorMessage::AutomationChange(AutomationChange::SetTarget(index, target)),
+                ).into(),
+            };
+
+            lanes = lanes
+                .push(Row::new()
+                    .spacing(4)
+                    .push(header)
+                    .push(Button::new(&mut controls.remove_button, Text::new("Remove"))
+                        .on_press(SequenceEditorMessage::AutomationChange(AutomationChange::RemoveLane(index))))
+                )
+                .push(Row::new()
+                    .push(Space::new(Length::Units(keyboard::WIDTH), Length::Shrink))
+                    .push(AutomationLaneView::new(
+                        &mut controls.lane,
+                        automation,
+                        index,
+                        &self.scroll_zoom.x,
+                        self.automation_tool,
+                        SequenceEditorMessage::AutomationChange,
+                    ))
+                    .push(Space::new(Length::Units(20), Length::Shrink))
+                );
+        }
+
+        let mut expression = Column::new().spacing(2);
+        let expression_note = self.piano_roll.expression_note
+            .filter(|id| notes.lock().unwrap().get(*id).is_some());
+
+        if let Some(id) = expression_note {
+            expression = expression.push(Row::new()
+                .spacing(4)
+                .push(Text::new("Note expression"))
+                .push(Button::new(&mut self.close_expression_button, Text::new("Close"))
+                    .on_press(SelfMessage(SequenceEditorSelfMessage::PianoRoll(PianoRollSelfMessage::EditExpression(None)))))
+            );
+
+            for (state, &kind) in self.expression_strips.iter_mut().zip(ExpressionKind::ALL.iter()) {
+                expression = expression.push(Row::new()
+                    .spacing(4)
+                    .push(Text::new(kind.to_string()).width(Length::Units(80)))
+                    .push(NoteExpressionStrip::new(state, notes, id, kind, SequenceEditorMessage::Gesture))
+                    .push(Space::new(Length::Units(20), Length::Shrink))
+                );
+            }
+        }
+
+        let select_where = |criterion| SelfMessage(SequenceEditorSelfMessage::PianoRoll(PianoRollSelfMessage::SelectWhere(criterion)));
+        let has_selection = !self.piano_roll.selection.is_empty();
+
+        let mut select_pitch = Button::new(&mut self.select_pitch_button, Text::new("Same pitch"));
+        let mut select_pitch_class = Button::new(&mut self.select_pitch_class_button, Text::new("Same pitch class"));
+        if has_selection {
+            select_pitch = select_pitch.on_press(select_where(SelectCriterion::Pitch));
+            select_pitch_class = select_pitch_class.on_press(select_where(SelectCriterion::PitchClass));
+        }
+        let mut select_in_loop = Button::new(&mut self.select_in_loop_button, Text::new("In loop"));
+        if let Some((start, end)) = playback_state.looping {
+            select_in_loop = select_in_loop.on_press(select_where(SelectCriterion::TimeRange(start, end)));
+        }
+
+        let select = Row::new()
+            .spacing(4)
+            .push(Checkbox::new(self.piano_roll.lasso, "Lasso", |lasso| {
+                SelfMessage(SequenceEditorSelfMessage::PianoRoll(PianoRollSelfMessage::SetLasso(lasso)))
+            }))
+            .push(Text::new("Select"))
+            .push(select_pitch)
+            .push(select_pitch_class)
+            .push(select_in_loop)
+            .push(Button::new(&mut self.select_pressure_button, Text::new(format!("Pressure above {:.2}", self.pressure_threshold)))
+                .on_press(select_where(SelectCriterion::PressureAbove(self.pressure_threshold))))
+            .push(Slider::new(&mut self.pressure_threshold_slider, 0.0..=1.0, self.pressure_threshold, |threshold| {
+                SelfMessage(SequenceEditorSelfMessage::SetPressureThreshold(threshold))
+            }).step(0.01).width(Length::Units(100)))
+            .push(Button::new(&mut self.quantize_button, Text::new("Quantize..."))
+                .on_press(SelfMessage(SequenceEditorSelfMessage::ToggleQuantize)))
+            .push(Button::new(&mut self.transform_button, Text::new("Transform..."))
+                .on_press(SelfMessage(SequenceEditorSelfMessage::ToggleTransform)));
+
+        let quantize: Element<_> = match self.show_quantize {
+            true => self.quantize_panel.view(self.quantize, self.humanize, self.groove.as_ref(), has_selection)
+                .map(|message| SelfMessage(SequenceEditorSelfMessage::Quantize(message))),
+            false => Column::new().into(),
+        };
+        let transform: Element<_> = match self.show_transform {
+            true => self.transform_panel.view(&*settings.pitch_grid, playback_state.playback_start_cursor, has_selection)
+                .map(|message| SelfMessage(SequenceEditorSelfMessage::Transform(message))),
+            false => Column::new().into(),
+        };
+
         Column::new()
             .push(Row::new()
+                .spacing(4)
+                .push(PickList::new(
+                    &mut self.follow_pick_list,
+                    &FollowMode::ALL[..],
+                    Some(self.follow),
+                    |mode| SelfMessage(SequenceEditorSelfMessage::SetFollow(mode)),
+                ))
+                .push(Checkbox::new(self.follow_suspended, "Suspend following", |suspended| {
+                    SelfMessage(SequenceEditorSelfMessage::SetFollowSuspended(suspended))
+                }))
+            )
+            .push(select)
+            .push(quantize)
+            .push(transform)
+            .push(Row::new()
+                .push(Space::new(Length::Units(keyboard::WIDTH), Length::Shrink))
                 .push(Timeline::new(
                     &self.scroll_zoom.x,
                     &settings,
                     SequenceEditorMessage::SynthCommand,
+                    |scroll| SelfMessage(ScrollUpdateX(scroll)),
                     &mut self.timeline,
                     &playback_state,
                 ))
@@ -67,6 +285,13 @@ impl SequenceEditor {
                 .height(Length::Shrink)
             )
             .push(Row::new()
+                .push(PianoKeyboard::new(
+                    &mut self.keyboard,
+                    &self.scroll_zoom.y,
+                    &settings,
+                    held_pitches,
+                    SequenceEditorMessage::SynthCommand,
+                ))
                 .push(Into::<Element<'a, PianoRollMessage>>::into(
                     PianoRoll::new(
                         &mut self.piano_roll,
@@ -81,6 +306,10 @@ impl SequenceEditor {
                             PianoRollMessage::SelfMessage(content) => SequenceEditorMessage::SelfMessage(SequenceEditorSelfMessage::PianoRoll(content)),
                             PianoRollMessage::SynthCommand(content) => SequenceEditorMessage::SynthCommand(content),
                             PianoRollMessage::SequenceChange(content) => SequenceEditorMessage::SequenceChange(content),
+                            PianoRollMessage::Gesture(content) => SequenceEditorMessage::Gesture(content),
+                            PianoRollMessage::GestureEnd => SequenceEditorMessage::GestureEnd,
+                            PianoRollMessage::ScrollX(scroll) => SelfMessage(ScrollUpdateX(scroll)),
+                            PianoRollMessage::ScrollY(scroll) => SelfMessage(ScrollUpdateY(scroll)),
                         }
                     })
                 )
@@ -93,7 +322,10 @@ impl SequenceEditor {
                 ))
                 .height(Length::Fill)
             )
+            .push(expression)
+            .push(lanes)
             .push(Row::new()
+                .push(Space::new(Length::Units(keyboard::WIDTH), Length::Shrink))
                 .push(ScrollZoomBar::new(
                     &mut self.scroll_bar_x,
                     &self.scroll_zoom.x,
@@ -108,16 +340,50 @@ impl SequenceEditor {
             .into()
     }
 
-    pub fn update(&mut self, message: SequenceEditorSelfMessage, notes: &Arc<Mutex<Sequence>>,) {
+    pub fn tool(&self) -> Tool {
+        self.piano_roll.tool
+    }
+
+    pub fn step_entry(&self) -> bool {
+        self.piano_roll.step_tick.is_some()
+    }
+
+    /// Keeps the playhead in view while playing, unless following has been
+    /// suspended.
+    pub fn follow_playhead(&mut self, state: &PlaybackState) {
+        if state.playing && !self.playing {
+            self.follow_suspended = false;
+        }
+        self.playing = state.playing;
+
+        if state.playing && !self.follow_suspended {
+            if let Some(ScrollScaleAxisChange::View(start, end)) = self.scroll_zoom.x.follow(state.playback_cursor as f32, self.follow) {
+                self.scroll_zoom.x.view_start = start;
+                self.scroll_zoom.x.view_end = end;
+            }
+        }
+    }
+
+    /// Changes to the notes come back to be applied together.
+    pub fn update(&mut self, message: SequenceEditorSelfMessage, notes: &Arc<Mutex<Sequence>>, settings: &PianoRollSettings) -> Vec<SequenceChange> {
         match message {
-            ScrollUpdateX(scroll) => match scroll {
-                ScrollScaleAxisChange::Left(new_pos) => {
-                    self.scroll_zoom.x.view_start = new_pos
-                },
-                ScrollScaleAxisChange::Right(new_pos) => {
-                    self.scroll_zoom.x.view_end = new_pos
-                },
-                _ => {}
+            ScrollUpdateX(scroll) => {
+                // moving the view by hand stops it following
+                self.follow_suspended |= self.playing;
+
+                match scroll {
+                    ScrollScaleAxisChange::Left(new_pos) => {
+                        self.scroll_zoom.x.view_start = new_pos
+                    },
+                    ScrollScaleAxisChange::Right(new_pos) => {
+                        self.scroll_zoom.x.view_end = new_pos
+                    },
+                    ScrollScaleAxisChange::View(start, end) => {
+                        self.scroll_zoom.x.view_start = start;
+                        self.scroll_zoom.x.view_end = end;
+                    },
+                    _ => {}
+                }
             },
             ScrollUpdateY(scroll) => match scroll {
                 ScrollScaleAxisChange::Left(new_pos) => {
@@ -126,11 +392,93 @@ impl SequenceEditor {
                 ScrollScaleAxisChange::Right(new_pos) => {
                     self.scroll_zoom.y.view_end = new_pos
                 },
+                ScrollScaleAxisChange::View(start, end) => {
+                    self.scroll_zoom.y.view_start = start;
+                    self.scroll_zoom.y.view_end = end;
+                },
                 _ => {}
             },
-            SequenceEditorSelfMessage::PianoRoll(action) => {
-                self.piano_roll.on_event(action, &*notes.lock().unwrap());
+            SequenceEditorSelfMessage::SetAutomationTool(tool) => {
+                self.automation_tool = tool;
+            }
+            SequenceEditorSelfMessage::SetFollow(mode) => {
+                self.follow = mode;
+            }
+            SequenceEditorSelfMessage::SetFollowSuspended(suspended) => {
+                self.follow_suspended = suspended;
+            }
+            SequenceEditorSelfMessage::SetPressureThreshold(threshold) => {
+                self.pressure_threshold = threshold;
+            }
+            SequenceEditorSelfMessage::PianoRoll(message) => {
+                let notes = notes.lock().unwrap();
+                let moved = match self.piano_roll.action {
+                    Action::Dragging(id, _) | Action::Resizing(id, _) => Some(id),
+                    _ => None,
+                };
+                self.piano_roll.on_event(message, &notes);
+
+                // what the mouse moved comes back once it's let go, as the
+                // edit that overlaps are resolved for
+                if let (Some(id), Action::None) = (moved, &self.piano_roll.action) {
+                    let mut ids = self.piano_roll.selection.clone();
+                    if !ids.contains(&id) {
+                        ids.push(id);
+                    }
+                    let moved_notes = ids.into_iter()
+                        .filter_map(|id| notes.get(id).map(|note| SequenceChange::Update(id, note.clone())))
+                        .collect();
+                    return vec![SequenceChange::Batch(moved_notes)];
+                }
+            }
+            SequenceEditorSelfMessage::ToggleQuantize => {
+                self.show_quantize = !self.show_quantize;
+            }
+            SequenceEditorSelfMessage::Quantize(message) => {
+                return self.quantize_selection(message, &*notes.lock().unwrap(), settings);
+            }
+            SequenceEditorSelfMessage::ToggleTransform => {
+                self.show_transform = !self.show_transform;
+            }
+            SequenceEditorSelfMessage::Transform(message) => {
+                if let Some(transform) = self.transform_panel.update(message) {
+                    let notes = notes.lock().unwrap();
+                    return transform.apply(&self.selected(&notes), &*settings.pitch_grid);
+                }
+            }
+        }
+        vec![]
+    }
+
+    fn selected<'n>(&self, notes: &'n Sequence) -> Vec<(NoteId, &'n Note)> {
+        self.piano_roll.selection.iter()
+            .filter_map(|id| notes.get(*id).map(|note| (*id, note)))
+            .collect()
+    }
+
+    fn quantize_selection(&mut self, message: QuantizeMessage, notes: &Sequence, settings: &PianoRollSettings) -> Vec<SequenceChange> {
+        let selected = self.selected(notes);
+        let grid = &*settings.tick_grid;
+
+        match message {
+            QuantizeMessage::SetQuantize(quantize) => self.quantize = quantize,
+            QuantizeMessage::SetHumanize(humanize) => self.humanize = humanize,
+            QuantizeMessage::Quantize => {
+                return selected.iter()
+                    .map(|(id, note)| SequenceChange::Update(*id, self.quantize.quantize(note, grid)))
+                    .collect();
+            }
+            QuantizeMessage::Humanize => return self.humanize.humanize(&selected),
+            QuantizeMessage::ExtractGroove => {
+                let notes: Vec<&Note> = selected.iter().map(|(_, note)| *note).collect();
+                self.groove = Groove::extract(&notes, grid);
+            }
+            QuantizeMessage::ApplyGroove => {
+                if let Some(groove) = &self.groove {
+                    return groove.apply(&selected, grid, self.quantize.strength);
+                }
             }
         }
+        vec![]
     }
 }
\ No newline at end of file
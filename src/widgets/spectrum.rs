@@ -0,0 +1,98 @@
+use iced::Element;
+use iced_native::{Background, Hasher, Layout, Length, Point, Rectangle, Widget};
+use iced_native::layout::{Limits, Node};
+use iced_native::mouse::Interaction;
+use iced_wgpu::{Color, Defaults, Primitive, Renderer};
+
+/// the top of the scale, in dB
+const CEILING_DB: f32 = 0.0;
+/// the bottom of the scale, in dB
+const FLOOR_DB: f32 = -90.0;
+
+/// Bars for the bands of a `MeterReport`'s spectrum, low to high.
+pub struct Spectrum<'a> {
+    bands: &'a [f32],
+}
+
+impl<'a> Spectrum<'a> {
+    pub fn new(bands: &'a [f32]) -> Self {
+        Self { bands }
+    }
+}
+
+impl<'a, Message> Widget<Message, Renderer> for Spectrum<'a> {
+    fn width(&self) -> Length {
+        Length::Units(320)
+    }
+
+    fn height(&self) -> Length {
+        Length::Units(120)
+    }
+
+    fn layout(&self, _renderer: &Renderer, limits: &Limits) -> Node {
+        Node::new(limits.width(self.width()).height(self.height()).max())
+    }
+
+    fn draw(
+        &self,
+        _renderer: &mut Renderer,
+        _defaults: &Defaults,
+        layout: Layout<'_>,
+        _cursor_position: Point,
+        _viewport: &Rectangle,
+    ) -> (Primitive, Interaction) {
+        let bounds = layout.bounds();
+        let band_width = bounds.width / self.bands.len().max(1) as f32;
+
+        let bars = self.bands.iter()
+            .enumerate()
+            .map(|(band, &db)| {
+                let level = ((db - FLOOR_DB) / (CEILING_DB - FLOOR_DB)).max(0.0).min(1.0);
+                let height = level * bounds.height;
+
+                Primitive::Quad {
+                    bounds: Rectangle {
+                        x: bounds.x + band as f32 * band_width,
+                        y: bounds.y + bounds.height - height,
+                        width: (band_width - 1.0).max(1.0),
+                        height,
+                    },
+                    background: Background::Color(Color::from_rgb(0.3, 0.6, 0.9)),
+                    border_radius: 0.0,
+                    border_width: 0.0,
+                    border_color: Color::BLACK,
+                }
+            })
+            .collect();
+
+        (
+            Primitive::Group {
+                primitives: vec![
+                    Primitive::Quad {
+                        bounds,
+                        background: Background::Color(Color::from_rgb(0.15, 0.15, 0.15)),
+                        border_radius: 0.0,
+                        border_width: 0.0,
+                        border_color: Color::BLACK,
+                    },
+                    Primitive::Group { primitives: bars },
+                ]
+            },
+            Interaction::Idle
+        )
+    }
+
+    fn hash_layout(&self, _state: &mut Hasher) {
+
+    }
+}
+
+impl<'a, Message> Into<Element<'a, Message>>
+for Spectrum<'a>
+    where
+        Message: 'a,
+{
+    fn into(self) -> Element<'a, Message> {
+        Element::new(self)
+    }
+}
@@ -0,0 +1,114 @@
+use std::fmt;
+
+use iced::{Checkbox, Column, Element, Length, PickList, Row, Slider, Text, pick_list, slider};
+
+use crate::audio::{ChorusSettings, ChorusWaveform, REVERB_PRESETS, ReverbSettings, SynthEffects};
+
+const CHORUS_WAVEFORMS: [ChorusWaveform; 2] = [ChorusWaveform::Sine, ChorusWaveform::Triangle];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReverbPreset(usize);
+
+impl fmt::Display for ReverbPreset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", REVERB_PRESETS[self.0].0)
+    }
+}
+
+/// Controls for the internal synth's reverb and chorus.
+pub struct SynthPanel {
+    presets: Vec<ReverbPreset>,
+    preset_pick_list: pick_list::State<ReverbPreset>,
+    reverb_sliders: [slider::State; 4],
+    chorus_sliders: [slider::State; 4],
+    waveform_pick_list: pick_list::State<ChorusWaveform>,
+}
+
+impl Default for SynthPanel {
+    fn default() -> Self {
+        Self {
+            presets: (0..REVERB_PRESETS.len()).map(ReverbPreset).collect(),
+            preset_pick_list: Default::default(),
+            reverb_sliders: Default::default(),
+            chorus_sliders: Default::default(),
+            waveform_pick_list: Default::default(),
+        }
+    }
+}
+
+impl SynthPanel {
+    pub fn view(&mut self, effects: SynthEffects) -> Element<SynthEffects> {
+        let SynthEffects { reverb, chorus } = effects;
+        let set_reverb = move |reverb: ReverbSettings| SynthEffects { reverb, ..effects };
+        let set_chorus = move |chorus: ChorusSettings| SynthEffects { chorus, ..effects };
+
+        let preset = REVERB_PRESETS.iter()
+            .position(|&(_, preset)| preset == ReverbSettings { enabled: true, ..reverb })
+            .map(ReverbPreset);
+
+        let [room_size, damping, width, level] = &mut self.reverb_sliders;
+        let reverb_row = Row::new()
+            .spacing(8)
+            .push(Checkbox::new(reverb.enabled, "Reverb", move |enabled| {
+                set_reverb(ReverbSettings { enabled, ..reverb })
+            }))
+            .push(PickList::new(&mut self.preset_pick_list, &self.presets[..], preset, move |ReverbPreset(index)| {
+                set_reverb(ReverbSettings { enabled: reverb.enabled, ..REVERB_PRESETS[index].1 })
+            }))
+            .push(control(room_size, "Room size", 0.0..=1.0, 0.01, reverb.room_size, move |room_size| {
+                set_reverb(ReverbSettings { room_size, ..reverb })
+            }))
+            .push(control(damping, "Damping", 0.0..=1.0, 0.01, reverb.damping, move |damping| {
+                set_reverb(ReverbSettings { damping, ..reverb })
+            }))
+            .push(control(width, "Width", 0.0..=100.0, 0.5, reverb.width, move |width| {
+                set_reverb(ReverbSettings { width, ..reverb })
+            }))
+            .push(control(level, "Level", 0.0..=1.0, 0.01, reverb.level, move |level| {
+                set_reverb(ReverbSettings { level, ..reverb })
+            }));
+
+        let [voices, level, speed, depth] = &mut self.chorus_sliders;
+        let chorus_row = Row::new()
+            .spacing(8)
+            .push(Checkbox::new(chorus.enabled, "Chorus", move |enabled| {
+                set_chorus(ChorusSettings { enabled, ..chorus })
+            }))
+            .push(PickList::new(&mut self.waveform_pick_list, &CHORUS_WAVEFORMS[..], Some(chorus.waveform), move |waveform| {
+                set_chorus(ChorusSettings { waveform, ..chorus })
+            }))
+            .push(control(voices, "Voices", 1.0..=99.0, 1.0, chorus.voices as f64, move |voices| {
+                set_chorus(ChorusSettings { voices: voices as u32, ..chorus })
+            }))
+            .push(control(level, "Level", 0.0..=10.0, 0.1, chorus.level, move |level| {
+                set_chorus(ChorusSettings { level, ..chorus })
+            }))
+            .push(control(speed, "Speed (Hz)", 0.29..=5.0, 0.01, chorus.speed, move |speed| {
+                set_chorus(ChorusSettings { speed, ..chorus })
+            }))
+            .push(control(depth, "Depth (ms)", 0.0..=256.0, 0.5, chorus.depth, move |depth| {
+                set_chorus(ChorusSettings { depth, ..chorus })
+            }));
+
+        Column::new()
+            .spacing(4)
+            .push(reverb_row)
+            .push(chorus_row)
+            .into()
+    }
+}
+
+fn control<'a>(
+    state: &'a mut slider::State,
+    name: &str,
+    range: std::ops::RangeInclusive<f64>,
+    step: f64,
+    value: f64,
+    on_change: impl Fn(f64) -> SynthEffects + 'static,
+) -> Element<'a, SynthEffects> {
+    Column::new()
+        .width(Length::Units(120))
+        .push(Text::new(format!("{} {:.2}", name, value)).size(14))
+        .push(Slider::new(state, range, value, on_change).step(step))
+        .into()
+}
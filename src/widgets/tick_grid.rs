@@ -1,3 +1,5 @@
+use std::fmt::Debug;
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum LineType {
     Bar(i32),
@@ -10,12 +12,17 @@ pub struct GridLine {
     pub line_type: LineType,
 }
 
-pub trait TickGrid {
+/// Shared with the audio engine, which follows the bar and beat lines for
+/// the metronome.
+pub trait TickGrid: Debug + Send + Sync {
     fn get_grid_lines(&self, start: i32, end: i32) -> Vec<GridLine>;
     fn quantize_tick(&self, tick: i32) -> i32;
     fn grid_size(&self, tick: i32) -> i32;
+    fn bar_length(&self) -> i32;
+    fn beat_length(&self) -> i32;
 }
 
+#[derive(Debug)]
 pub struct SimpleGrid {
     pub(crate) ticks_per_16th: i32,
 }
@@ -41,4 +48,12 @@ impl TickGrid for SimpleGrid {
     fn grid_size(&self, _tick: i32) -> i32 {
         return self.ticks_per_16th;
     }
+
+    fn bar_length(&self) -> i32 {
+        return self.ticks_per_16th * 16;
+    }
+
+    fn beat_length(&self) -> i32 {
+        return self.ticks_per_16th * 4;
+    }
 }
\ No newline at end of file
@@ -4,7 +4,7 @@ use iced_native::layout::{Limits, Node};
 use iced_native::mouse::Interaction;
 use iced_wgpu::{Color, Defaults, Primitive, Renderer};
 
-use crate::scroll_zoom::ScrollScaleAxis;
+use crate::scroll_zoom::{ScrollScaleAxis, ScrollScaleAxisChange, wheel_pixels};
 use crate::widgets::piano_roll::PianoRollSettings;
 use crate::widgets::tick_grid::LineType;
 use crate::audio::{SynthCommand, PlaybackState};
@@ -17,6 +17,7 @@ pub struct Timeline<'a, Message> {
     scroll: &'a ScrollScaleAxis,
     settings: &'a PianoRollSettings,
     on_synth_command: Box<dyn Fn(SynthCommand) -> Message + 'a>,
+    on_scroll: Box<dyn Fn(ScrollScaleAxisChange) -> Message + 'a>,
     state: &'a mut TimelineState,
     playback_state: &'a PlaybackState,
 }
@@ -43,17 +44,26 @@ pub enum Action {
 }
 
 impl<'a, Message> Timeline<'a, Message> {
-    pub fn new<FS>(
+    pub fn new<FS, FC>(
         scroll: &'a ScrollScaleAxis,
         settings: &'a PianoRollSettings,
         on_synth_command: FS,
+        on_scroll: FC,
         state: &'a mut TimelineState,
         playback_state: &'a PlaybackState,
     ) -> Self
         where
             FS: 'a + Fn(SynthCommand) -> Message,
+            FC: 'a + Fn(ScrollScaleAxisChange) -> Message,
     {
-        Self { scroll, settings, on_synth_command: Box::new(on_synth_command), state, playback_state }
+        Self {
+            scroll,
+            settings,
+            on_synth_command: Box::new(on_synth_command),
+            on_scroll: Box::new(on_scroll),
+            state,
+            playback_state,
+        }
     }
 
     fn seek(&mut self, cursor_position: Point, messages: &mut Vec<Message>, bounds: Rectangle) {
@@ -76,7 +86,7 @@ impl<'a, Message> Timeline<'a, Message> {
     fn cursor_tick(&self, cursor_position: Point, bounds: Rectangle) -> i32 {
         let mut cursor_tick = self.scroll.screen_to_inner(cursor_position.x, bounds.x, bounds.width) as i32;
 
-        if !self.state.modifiers.alt {
+        if !self.settings.keymap.mouse.unquantized.held(self.state.modifiers) {
             cursor_tick = self.settings.tick_grid.quantize_tick(cursor_tick);
         }
         max(0, cursor_tick)
@@ -261,7 +271,7 @@ impl<'a, Message> Widget<Message, Renderer> for Timeline<'a, Message> {
                 }
                 mouse::Event::ButtonPressed(mouse::Button::Left) => {
                     if bounds.contains(cursor_position) {
-                        match self.state.modifiers.control {
+                        match self.settings.keymap.mouse.select.held(self.state.modifiers) {
                             true => {
                                 let cursor_tick = self.cursor_tick(cursor_position, bounds);
                                 self.state.action = Action::Selecting(cursor_tick);
@@ -282,6 +292,16 @@ impl<'a, Message> Widget<Message, Renderer> for Timeline<'a, Message> {
                     self.state.action = Action::None;
                     Status::Captured
                 },
+                mouse::Event::WheelScrolled { delta } if bounds.contains(cursor_position) => {
+                    let pixels = wheel_pixels(delta);
+                    let amount = pixels.x + pixels.y;
+
+                    messages.push((self.on_scroll)(match self.settings.keymap.mouse.wheel_zoom.held(self.state.modifiers) {
+                        true => self.scroll.zoomed(amount, cursor_position.x, bounds.x, bounds.width),
+                        false => self.scroll.scrolled(amount, bounds.width),
+                    }));
+                    Status::Captured
+                },
                 _ => Status::Ignored,
             }
             Event::Keyboard(keyboard::Event::ModifiersChanged(modifiers)) => {
@@ -0,0 +1,126 @@
+use iced::{Button, Checkbox, Column, Element, Length, Row, Text, TextInput, button, text_input};
+use num_rational::Rational32;
+use num_traits::{ToPrimitive, Zero};
+
+use crate::sequence::Pitch;
+use crate::transform::Transform;
+use crate::widgets::pitch_grid::PitchGrid;
+
+#[derive(Debug, Clone)]
+pub enum TransformMessage {
+    SetInterval(String),
+    SetAxis(String),
+    SetFactor(String),
+    SetAnchorAtPlayhead(bool),
+    Apply(Transform),
+}
+
+/// Transforms for the selected notes. Intervals and pitches are typed as
+/// fractions of an octave, A 440 being 0.
+pub struct TransformPanel {
+    interval: String,
+    axis: String,
+    factor: String,
+    anchor_at_playhead: bool,
+    interval_input: text_input::State,
+    axis_input: text_input::State,
+    factor_input: text_input::State,
+    transpose_button: button::State,
+    step_down_button: button::State,
+    step_up_button: button::State,
+    invert_button: button::State,
+    retrograde_button: button::State,
+    stretch_button: button::State,
+}
+
+impl Default for TransformPanel {
+    fn default() -> Self {
+        Self {
+            interval: "7/12".to_string(),
+            // middle C
+            axis: "-3/4".to_string(),
+            factor: "2".to_string(),
+            anchor_at_playhead: false,
+            interval_input: Default::default(),
+            axis_input: Default::default(),
+            factor_input: Default::default(),
+            transpose_button: button::State::new(),
+            step_down_button: button::State::new(),
+            step_up_button: button::State::new(),
+            invert_button: button::State::new(),
+            retrograde_button: button::State::new(),
+            stretch_button: button::State::new(),
+        }
+    }
+}
+
+impl TransformPanel {
+    pub fn view(&mut self, pitch_grid: &dyn PitchGrid, playhead: i32, has_selection: bool) -> Element<TransformMessage> {
+        let interval = parse(&self.interval).map(Pitch::from_octave);
+        let axis = parse(&self.axis).map(Pitch::from_octave);
+        let factor = parse(&self.factor).filter(|factor| *factor > Rational32::zero());
+        let anchor = if self.anchor_at_playhead { Some(playhead) } else { None };
+
+        let pitch_row = Row::new()
+            .spacing(8)
+            .push(TextInput::new(&mut self.interval_input, "interval", &self.interval, TransformMessage::SetInterval)
+                .width(Length::Units(80)))
+            .push(Text::new(match &interval {
+                Some(interval) => format!("{:.2} semitones", interval.to_f32() * 12.0),
+                None => "not a fraction".to_string(),
+            }).size(14).width(Length::Units(100)))
+            .push(transform_button(&mut self.transpose_button, "Transpose", interval.map(Transform::Transpose), has_selection))
+            .push(transform_button(&mut self.step_down_button, "Scale step down", Some(Transform::DiatonicTranspose(-1)), has_selection))
+            .push(transform_button(&mut self.step_up_button, "Scale step up", Some(Transform::DiatonicTranspose(1)), has_selection))
+            .push(TextInput::new(&mut self.axis_input, "pitch", &self.axis, TransformMessage::SetAxis)
+                .width(Length::Units(80)))
+            .push(Text::new(match &axis {
+                Some(axis) => pitch_grid.label(axis),
+                None => "not a fraction".to_string(),
+            }).size(14).width(Length::Units(60)))
+            .push(transform_button(&mut self.invert_button, "Invert", axis.map(Transform::Invert), has_selection));
+
+        let time_row = Row::new()
+            .spacing(8)
+            .push(transform_button(&mut self.retrograde_button, "Retrograde", Some(Transform::Retrograde), has_selection))
+            .push(TextInput::new(&mut self.factor_input, "factor", &self.factor, TransformMessage::SetFactor)
+                .width(Length::Units(80)))
+            .push(Text::new(match factor {
+                Some(factor) => format!("×{:.3}", factor.to_f32().unwrap_or_default()),
+                None => "not a positive fraction".to_string(),
+            }).size(14).width(Length::Units(100)))
+            .push(transform_button(&mut self.stretch_button, "Stretch", factor.map(|factor| Transform::Stretch { factor, anchor }), has_selection))
+            .push(Checkbox::new(self.anchor_at_playhead, "From the playhead", TransformMessage::SetAnchorAtPlayhead));
+
+        Column::new()
+            .spacing(4)
+            .push(pitch_row)
+            .push(time_row)
+            .into()
+    }
+
+    /// The transform to apply, if the message asks for one.
+    pub fn update(&mut self, message: TransformMessage) -> Option<Transform> {
+        match message {
+            TransformMessage::SetInterval(interval) => self.interval = interval,
+            TransformMessage::SetAxis(axis) => self.axis = axis,
+            TransformMessage::SetFactor(factor) => self.factor = factor,
+            TransformMessage::SetAnchorAtPlayhead(at_playhead) => self.anchor_at_playhead = at_playhead,
+            TransformMessage::Apply(transform) => return Some(transform),
+        }
+        None
+    }
+}
+
+/// Only pressable with a selection and a transform to apply.
+fn transform_button<'a>(state: &'a mut button::State, label: &str, transform: Option<Transform>, has_selection: bool) -> Button<'a, TransformMessage> {
+    let button = Button::new(state, Text::new(label));
+    match transform.filter(|_| has_selection) {
+        Some(transform) => button.on_press(TransformMessage::Apply(transform)),
+        None => button,
+    }
+}
+
+fn parse(text: &str) -> Option<Rational32> {
+    text.trim().parse().ok()
+}